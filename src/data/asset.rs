@@ -1,25 +1,106 @@
 use crate::*;
 
-use std::fs::read_dir;
-use bevy::utils::HashMap;
+use std::{fs::read_dir, time::SystemTime};
+use bevy::utils::{HashMap, HashSet};
 use serde::{Deserialize, Serialize};
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 pub struct DataAssetPlugin<T: Default + Clone + Serialize + for<'a> Deserialize<'a> + std::fmt::Debug + TypePath + FromReflect + Sync + Send + 'static> {
     type_name: String,
+    source: DataAssetSource,
+    hot_reload_interval: Option<f32>,
     phantom_data: PhantomData<T>,
 }
 
 impl<T: Default + Clone + Serialize + for<'a> Deserialize<'a> + std::fmt::Debug + TypePath + FromReflect + Sync + Send + 'static> DataAssetPlugin<T> {
     pub fn new<S: AsRef<str>>(type_name: S) -> Self {
-        Self { type_name: type_name.as_ref().to_owned(), phantom_data: PhantomData }
+        Self { type_name: type_name.as_ref().to_owned(), source: DataAssetSource::Ron, hot_reload_interval: None, phantom_data: PhantomData }
+    }
+
+    pub fn with_source(mut self, source: DataAssetSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Polls `data/{name}/*` of every content root every `interval` seconds, reloading edited, added & deleted .ron files and sending `DataAssetChanged<T>` for each.
+    pub fn with_hot_reload(mut self, interval: f32) -> Self {
+        self.hot_reload_interval = Some(interval);
+        self
     }
 }
 
 impl<T: Default + Clone + Serialize + for<'a> Deserialize<'a> + std::fmt::Debug + TypePath + FromReflect + Sync + Send + 'static> Plugin for DataAssetPlugin<T> {
     fn build(&self, app: &mut App) {
+        let content_roots = app.world.get_resource_or_insert_with(ContentRoots::load).clone();
         app.register_type::<DataAssets<T>>()
-            .insert_resource(DataAssets::<T>::new(&self.type_name));
+            .add_event::<DataAssetChanged<T>>()
+            .init_resource::<DataAssetLoadReport>()
+            .insert_resource(DataAssets::<T>::new_unloaded(&self.type_name, self.source, content_roots))
+            .add_systems(PreStartup, stsys_init_data_assets::<T>)
+            .add_systems(PostUpdate, sys_update_data_asset_load_report::<T>.run_if(resource_changed::<DataAssets<T>>()))
+            .add_systems(OnEnter(AppState::Main), stsys_report_missing_data_asset_names::<T>);
+
+        if let Some(interval) = self.hot_reload_interval {
+            app.insert_resource(DataAssetHotReload::<T>::new(interval))
+                .add_systems(PreUpdate, sys_hot_reload_data_assets::<T>);
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DataAssetChangeKind {
+    Added,
+    Modified,
+    Removed,
+}
+
+/// Sent by hot reloading whenever an asset file is edited, added or deleted.
+/// 
+/// `Removed` assets keep their id & last loaded data, so anything still holding the id will not panic, but it should be refreshed.
+#[derive(Event)]
+pub struct DataAssetChanged<T: Sync + Send + 'static> {
+    pub id: u16,
+    pub name: String,
+    pub kind: DataAssetChangeKind,
+    phantom_data: PhantomData<T>,
+}
+
+impl<T: Sync + Send + 'static> DataAssetChanged<T> {
+    pub fn new(id: u16, name: String, kind: DataAssetChangeKind) -> Self {
+        Self { id, name, kind, phantom_data: PhantomData }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+#[derive(Resource)]
+pub struct DataAssetHotReload<T: Default + Clone + Serialize + for<'a> Deserialize<'a> + std::fmt::Debug + TypePath + FromReflect + Sync + Send + 'static> {
+    timer: Timer,
+    phantom_data: PhantomData<T>,
+}
+
+impl<T: Default + Clone + Serialize + for<'a> Deserialize<'a> + std::fmt::Debug + TypePath + FromReflect + Sync + Send + 'static> DataAssetHotReload<T> {
+    pub fn new(interval: f32) -> Self {
+        Self { timer: Timer::from_seconds(interval, TimerMode::Repeating), phantom_data: PhantomData }
+    }
+}
+
+fn sys_hot_reload_data_assets<T: Default + Clone + Serialize + for<'a> Deserialize<'a> + std::fmt::Debug + TypePath + FromReflect + Sync + Send + 'static>(
+    mut hot_reload: ResMut<DataAssetHotReload<T>>,
+    mut data_assets: ResMut<DataAssets<T>>,
+    mut events: EventWriter<DataAssetChanged<T>>,
+    time: Res<Time>,
+) {
+    if !hot_reload.timer.tick(time.delta()).just_finished() { return; }
+
+    // Polling should not trigger change detection unless something was actually reloaded
+    let changes = data_assets.bypass_change_detection().reload_changed();
+    if changes.is_empty() { return; }
+    data_assets.set_changed();
+
+    for (id, name, kind) in changes {
+        info!("[{}] {:?} [{id}] {name}", data_assets.type_name(), kind);
+        events.send(DataAssetChanged::new(id, name, kind));
     }
 }
 
@@ -29,85 +110,335 @@ impl<T: Default + Clone + Serialize + for<'a> Deserialize<'a> + std::fmt::Debug
 #[reflect(Resource, Default)]
 pub struct DataAssets<T: Default + Clone + Serialize + for<'a> Deserialize<'a> + std::fmt::Debug + TypePath + FromReflect + Sync + Send + 'static> {
     type_name: String,
+    source: DataAssetSource,
     data: Vec<T>,
     asset_id_map: HashMap::<String, u16>,
+    #[reflect(ignore)]
+    modified_times: HashMap::<String, SystemTime>,
+    #[reflect(ignore)]
+    id_manifest: DataAssetIdManifest,
+    #[reflect(ignore)]
+    missing_names: MissingDataAssetNames,
+    #[reflect(ignore)]
+    load_errors: Vec<DataAssetLoadError>,
+    #[reflect(ignore)]
+    migrations: DataMigrations<T>,
+    #[reflect(ignore)]
+    content_roots: ContentRoots,
+    /// Index into `content_roots` of the root each asset was loaded from
+    #[reflect(ignore)]
+    asset_roots: HashMap::<String, usize>,
+    #[reflect(ignore)]
+    conflicts: Vec<ContentConflict>,
 }
 
 impl<T: Default + Clone + Serialize + for<'a> Deserialize<'a> + std::fmt::Debug + TypePath + FromReflect + Sync + Send + 'static> DataAssets<T> {
     pub fn new<S: AsRef<str>>(type_name: S) -> Self {
-        let mut data_assets = Self { type_name: type_name.as_ref().to_owned(), ..default() };
+        Self::new_with_source(type_name, DataAssetSource::Ron)
+    }
+
+    /// Loads from the content roots listed in `mods.ron`.
+    pub fn new_with_source<S: AsRef<str>>(type_name: S, source: DataAssetSource) -> Self {
+        Self::new_with_roots(type_name, source, ContentRoots::load())
+    }
+
+    pub fn new_with_roots<S: AsRef<str>>(type_name: S, source: DataAssetSource, content_roots: ContentRoots) -> Self {
+        let mut data_assets = Self::new_unloaded(type_name, source, content_roots);
         data_assets.init();
         data_assets
     }
 
+    /// Nothing is loaded until `init`.
+    fn new_unloaded<S: AsRef<str>>(type_name: S, source: DataAssetSource, content_roots: ContentRoots) -> Self {
+        Self { type_name: type_name.as_ref().to_owned(), source, content_roots, ..default() }
+    }
+
     pub fn type_name(&self) -> &String { &self.type_name }
-    pub fn full_path(&self) -> String { DATA_ASSET_DIR.to_string() + "/" + &self.type_name }
+    pub fn source(&self) -> DataAssetSource { self.source }
+    /// `data/{name}` of the base content root, which holds the id manifest & new assets.
+    pub fn full_path(&self) -> String { self.content_roots.base().data_path(&self.type_name) }
     pub fn asset_path(&self) -> String { "data/".to_string() + &self.type_name }
     pub fn data(&self) -> &[T] { &self.data }
+    pub fn id_manifest(&self) -> &DataAssetIdManifest { &self.id_manifest }
+    pub fn migrations(&self) -> &DataMigrations<T> { &self.migrations }
+    /// Files that failed to load during the last `load_all`, updated by `reload_changed`.
+    pub fn load_errors(&self) -> &[DataAssetLoadError] { &self.load_errors }
+    pub fn content_roots(&self) -> &ContentRoots { &self.content_roots }
+    /// Assets provided by more than one content root during the last `load_all_ron`, updated by `reload_changed`.
+    pub fn conflicts(&self) -> &[ContentConflict] { &self.conflicts }
+
+    /// The content root `asset` was loaded from. None for unknown assets & assets loaded from a pack.
+    pub fn asset_root<S: AsRef<str>>(&self, asset: S) -> Option<&ContentRoot> {
+        self.content_roots.get(*self.asset_roots.get(asset.as_ref())?)
+    }
 
     /// It is intended that you use this to cache the ID of the desired asset, and then get the asset when needed using `asset_from_id`.
+    /// 
+    /// Unknown names default to id 0 & are recorded in `missing_names`. Prefer `try_id_from_name` or `expect_id_from_name`.
     pub fn id_from_name<S: AsRef<str>>(&self, asset: S) -> u16 {
-        if let Some(id) = self.asset_id_map.get(asset.as_ref()) { *id } else { 0 }
+        if let Some(id) = self.asset_id_map.get(asset.as_ref()) {
+            *id
+        } else {
+            self.missing_names.record(asset);
+            0
+        }
+    }
+
+    pub fn try_id_from_name<S: AsRef<str>>(&self, asset: S) -> Option<DataAssetId<T>> {
+        self.asset_id_map.get(asset.as_ref()).map(|id| DataAssetId::new(*id))
     }
 
+    /// Panics if `asset` does not exist.
+    pub fn expect_id_from_name<S: AsRef<str>>(&self, asset: S) -> DataAssetId<T> {
+        let Some(id) = self.try_id_from_name(asset.as_ref()) else { panic!("[{}] {}", self.type_name, DataAssetLookupError::UnknownName(asset.as_ref().to_owned())) };
+        id
+    }
+
+    /// Tombstoned ids & ids reserved by the manifest without a file hold `T::default()`.
     pub fn get(&self, id: usize) -> &T { &self.data[id] }
 
+    /// Returns None if `id` is out of range or has been tombstoned.
+    pub fn try_get(&self, id: DataAssetId<T>) -> Option<&T> {
+        if self.id_manifest.is_tombstone(id.get()) { return None; }
+        self.data.get(id.index())
+    }
+
     /// Don't use this in performance critical areas
+    /// 
+    /// Unknown names default to id 0 & are recorded in `missing_names`. Prefer `try_get_from_name`.
     pub fn get_from_name<S: AsRef<str>>(&self, asset: S) -> &T {
         self.get(self.id_from_name(asset) as usize)
     }
 
+    /// Don't use this in performance critical areas
+    pub fn try_get_from_name<S: AsRef<str>>(&self, asset: S) -> Result<&T, DataAssetLookupError> {
+        let Some(id) = self.try_id_from_name(asset.as_ref()) else { return Err(DataAssetLookupError::UnknownName(asset.as_ref().to_owned())) };
+        self.try_get(id).ok_or(DataAssetLookupError::InvalidId(id.get()))
+    }
+
+    /// Every name that was requested through `id_from_name` or `get_from_name` and not found, sorted.
+    pub fn missing_names(&self) -> Vec<String> { self.missing_names.get() }
+
     pub fn init(&mut self) {
         Serial::create_directory_path(self.full_path());
-        Serial::save_type_to_ron_file(&T::default(), self.full_path(), "example", 1);
+        self.save_asset_file(&T::default(), "example");
         self.load_all();
     }
 
+    /// Files are saved at the current version of `migrations`, and older files are upgraded on load. Reinitializes, reloading every asset.
+    pub fn set_migrations(&mut self, migrations: DataMigrations<T>) {
+        self.migrations = migrations;
+        self.init();
+    }
+
     /// Saves based on asset names in `asset_id_map`. Will overwrite existing files.
+    /// 
+    /// Assets are saved to the content root they were loaded from.
     pub fn save_all(&self) {
-        let full_path = self.full_path();
-
         for (name, id) in self.asset_id_map.iter() {
-            let asset = self.data[*id as usize].clone();
-            Serial::save_type_to_ron_file(&asset, &full_path, name, 1);
+            self.save_asset_file(&self.data[*id as usize], name);
         }
     }
 
-    /// Loads all .ron files from `assets/data/{name}/*`
+    /// Loads from `assets/data/{name}.bin` if the source is `PackIfExists` and the pack exists, otherwise calls `load_all_ron`.
+    /// 
+    /// A pack that fails to load is reported in `load_errors` & RON is loaded instead.
     pub fn load_all(&mut self) {
+        self.load_errors.clear();
+
+        if self.source == DataAssetSource::PackIfExists && DataAssetPack::<T>::exists(self.content_roots.base().data_directory(), &self.type_name) {
+            match DataAssetPack::<T>::load(self.content_roots.base().data_directory(), &self.type_name) {
+                Ok(pack) => { self.load_pack(pack); return; }
+                Err(error) => { let type_name = self.type_name.clone(); self.push_load_error(type_name, error); }
+            }
+        }
+
+        self.load_all_ron();
+    }
+
+    /// Replaces all assets & the id manifest with the contents of `pack`. Nothing is written to disk.
+    /// 
+    /// Packs are baked from already resolved content roots, so no root is recorded for their assets.
+    pub fn load_pack(&mut self, pack: DataAssetPack<T>) {
+        let (id_manifest, assets) = pack.into_parts();
+        self.id_manifest = id_manifest;
+        self.asset_id_map.clear();
+        self.asset_roots.clear();
+        self.conflicts.clear();
+        self.modified_times.clear();
+        self.data = vec![T::default(); self.id_manifest.id_count()];
+
+        for (name, asset) in assets.into_iter() {
+            let Some(id) = self.id_manifest.get(&name) else { continue };
+            self.asset_id_map.insert(name, id);
+            self.data[id as usize] = asset;
+        }
+    }
+
+    /// Loads all .ron files from `data/{name}/*` of every content root. Later roots override earlier ones by asset name & can add new assets.
+    /// 
+    /// Ids are pinned by `data/{name}/_ids.ron` of the base root, including those of assets only provided by other roots.
+    /// Files missing from the manifest are given fresh ids in name order, and the manifest is saved.
+    /// 
+    /// Files that fail to load are collected in `load_errors`. If the manifest itself fails to load it is not overwritten.
+    pub fn load_all_ron(&mut self) {
         let full_path = self.full_path();
-        let files = Serial::file_paths_from_directory_recursive(&full_path, "");
+        let resolver = self.resolve_files();
         let mut data_asset_map = HashMap::default();
 
-        for file in files.iter() {
-            let Some(file_name) = file.strip_prefix("/") else { continue };
-            let Some(asset) = Serial::load_type_from_ron_file::<&String, &str, T>(&full_path, file_name) else { continue };
-            data_asset_map.insert(file_name.to_string(), asset);
+        self.conflicts = resolver.conflicts(&self.content_roots);
+        for conflict in self.conflicts.iter() { debug!("[{}] {} is provided by {:?}, using the last", self.type_name, conflict.asset, conflict.roots); }
+
+        self.asset_roots.clear();
+        for (file_name, root_index) in resolver.resolved() {
+            self.asset_roots.insert(file_name.clone(), root_index);
+
+            match self.load_asset_file(&file_name) {
+                Ok(asset) => { data_asset_map.insert(file_name.clone(), asset); }
+                Err(error) => { self.push_load_error(&file_name, error); }
+            }
+
+            // Recorded after loading, as migrated files may have been rewritten
+            self.record_modified_time(&file_name);
         }
 
-        data_asset_map.remove("example");
+        let manifest_loaded = match DataAssetIdManifest::load_checked(&full_path) {
+            Ok(manifest) => { self.id_manifest = manifest; true }
+            Err(error) => { self.push_load_error(DataAssetIdManifest::FILE_NAME, error); self.id_manifest = DataAssetIdManifest::default(); false }
+        };
+        let loaded_manifest = self.id_manifest.clone();
+
+        // Sorted so that fresh ids are assigned the same way on every machine
+        let mut names: Vec<&String> = data_asset_map.keys().collect();
+        names.sort();
+        for name in names { self.id_manifest.assign(name); }
+
+        self.asset_id_map.clear();
+        self.data = vec![T::default(); self.id_manifest.id_count()];
+        for (name, asset) in data_asset_map.into_iter() {
+            let Some(id) = self.id_manifest.get(&name) else { continue };
+            self.asset_id_map.insert(name, id);
+            self.data[id as usize] = asset;
+        }
 
-        let mut id = 0;
-        for (name, asset) in data_asset_map.iter() {
-            println!("[{id}] {name}: {:?}", asset);
-            self.asset_id_map.insert(name.clone(), id);
-            self.data.push(asset.clone());
-            id += 1;
+        for mismatch in self.validate_ids() {
+            warn!("[{}] Id manifest mismatch: {:?}", self.type_name, mismatch);
         }
+
+        if manifest_loaded && self.id_manifest != loaded_manifest { self.id_manifest.save(&full_path); }
     }
 
-    /// Will fail & return u16::MAX if `asset_name` already exists.
+    /// All loaded assets & the id manifest, ready to be saved as `assets/data/{name}.bin`.
+    pub fn to_pack(&self) -> DataAssetPack<T> {
+        let assets = self.asset_id_map.iter().map(|(name, id)| (name.clone(), self.data[*id as usize].clone())).collect();
+        DataAssetPack::new(self.id_manifest.clone(), assets)
+    }
+
+    /// Saves all loaded assets as `assets/data/{name}.bin`. Fails without writing if any file failed to load, so broken data is never shipped.
+    /// 
+    /// Intended to be called on `DataAssets` loaded from RON, as part of a build step.
+    pub fn bake(&self) -> SerialResult<()> {
+        if let Some(load_error) = self.load_errors.first() { return Err(load_error.error.clone()); }
+        self.to_pack().save(self.content_roots.base().data_directory(), &self.type_name)
+    }
+
+    /// Returns whether `assets/data/{name}.bin` contains exactly the loaded assets & id manifest.
+    pub fn verify_pack(&self) -> SerialResult<bool> {
+        let baked = DataAssetPack::<T>::load(self.content_roots.base().data_directory(), &self.type_name)?;
+        Ok(baked.to_bytes()? == self.to_pack().to_bytes()?)
+    }
+
+    /// Compares the id manifest against the .ron files currently in `data/{name}/*` of every content root.
+    pub fn validate_ids(&self) -> Vec<DataAssetIdMismatch> {
+        let file_names: HashSet<String> = self.resolve_files().resolved().into_iter().map(|(file_name, _)| file_name).collect();
+
+        let mut mismatches = vec![];
+        for (name, id) in self.id_manifest.ids().iter() {
+            if !file_names.contains(name) { mismatches.push(DataAssetIdMismatch::MissingFile { name: name.clone(), id: *id }); }
+        }
+
+        let mut unregistered: Vec<String> = file_names.into_iter().filter(|name| self.id_manifest.get(name).is_none()).collect();
+        unregistered.sort();
+        for name in unregistered { mismatches.push(DataAssetIdMismatch::Unregistered { name }); }
+
+        mismatches
+    }
+
+    /// Compares the modification time of every resolved .ron file in `data/{name}/*` of every content root against the last load, reloading those that changed.
+    /// An asset whose file is now provided by a different root is always reloaded.
+    /// 
+    /// Ids of existing names are never reassigned. New files are given their pinned id, or a fresh one from the manifest, and deleted
+    /// files lose their name but keep their id reserved in the manifest. Files that fail to parse keep their last loaded value.
+    /// 
+    /// Returns `(id, name, kind)` for every asset that changed.
+    pub fn reload_changed(&mut self) -> Vec<(u16, String, DataAssetChangeKind)> {
+        let resolver = self.resolve_files();
+        let mut found_names = HashSet::default();
+        let mut changes = vec![];
+
+        self.conflicts = resolver.conflicts(&self.content_roots);
+
+        for (file_name, root_index) in resolver.resolved() {
+            let file_name = file_name.as_str();
+            let Some(root) = self.content_roots.get(root_index) else { continue };
+            let Some(modified) = Serial::try_get_modified_time(Serial::path_string(root.data_path(&self.type_name), file_name, "ron")) else { continue };
+            found_names.insert(file_name.to_string());
+
+            let same_root = self.asset_roots.get(file_name) == Some(&root_index);
+            if same_root && self.modified_times.get(file_name) == Some(&modified) { continue; }
+            self.asset_roots.insert(file_name.to_string(), root_index);
+            self.modified_times.insert(file_name.to_string(), modified);
+            self.load_errors.retain(|load_error| load_error.file_name != file_name);
+
+            let asset = match self.load_asset_file(file_name) {
+                Ok(asset) => asset,
+                Err(error) => { self.push_load_error(file_name, error); continue; }
+            };
+            self.record_modified_time(file_name);
+            if let Some(id) = self.asset_id_map.get(file_name).cloned() {
+                self.data[id as usize] = asset;
+                changes.push((id, file_name.to_string(), DataAssetChangeKind::Modified));
+            } else {
+                let id = self.assign_id(file_name);
+                self.asset_id_map.insert(file_name.to_string(), id as u16);
+                self.data[id] = asset;
+                changes.push((id as u16, file_name.to_string(), DataAssetChangeKind::Added));
+            }
+        }
+
+        let removed_names: Vec<String> = self.asset_id_map.keys().filter(|name| !found_names.contains(*name)).cloned().collect();
+        for name in removed_names {
+            let Some(id) = self.asset_id_map.remove(&name) else { continue };
+            self.asset_roots.remove(&name);
+            self.modified_times.remove(&name);
+            self.load_errors.retain(|load_error| load_error.file_name != name);
+            changes.push((id, name, DataAssetChangeKind::Removed));
+        }
+
+        changes
+    }
+
+    /// Returns None if `asset_name` already exists.
     /// 
     /// Saves new asset file on success.
+    pub fn try_add<S: AsRef<str>>(&mut self, asset_name: S, asset: &T) -> Option<DataAssetId<T>> {
+        if self.asset_id_map.contains_key(asset_name.as_ref()) { return None; }
+        Some(DataAssetId::new(self.add(asset_name, asset)))
+    }
+
+    /// Will fail & return u16::MAX if `asset_name` already exists.
+    /// 
+    /// Saves new asset file to the base content root on success.
     /// 
     /// Returns id of added asset.
     pub fn add<S: AsRef<str>>(&mut self, asset_name: S, asset: &T) -> u16 {
         if self.asset_id_map.contains_key(asset_name.as_ref()) { return u16::MAX; }
 
-        let id = self.data.len() as u16;
+        let id = self.assign_id(asset_name.as_ref()) as u16;
         self.asset_id_map.insert(asset_name.as_ref().to_owned(), id);
-        self.data.push(asset.clone());
-        Serial::save_type_to_ron_file(&asset, &self.full_path(), asset_name, 1);
+        self.data[id as usize] = asset.clone();
+        self.save_asset_file(asset, asset_name.as_ref());
+        self.record_modified_time(asset_name);
         id
     }
 
@@ -120,13 +451,14 @@ impl<T: Default + Clone + Serialize + for<'a> Deserialize<'a> + std::fmt::Debug
         let id = if let Some(id) = self.asset_id_map.get(asset_name.as_ref()) {
                 *id as usize
             } else {
-                let id = self.data.len();
+                let id = self.assign_id(asset_name.as_ref());
                 self.asset_id_map.insert(asset_name.as_ref().to_owned(), id as u16);
                 id
             };
 
-        Serial::save_type_to_ron_file(&asset, &self.full_path(), asset_name, 1);
-        self.data.insert(id, asset.clone());
+        self.save_asset_file(asset, asset_name.as_ref());
+        self.record_modified_time(asset_name);
+        self.data[id] = asset.clone();
         id as u16
     }
 
@@ -139,17 +471,18 @@ impl<T: Default + Clone + Serialize + for<'a> Deserialize<'a> + std::fmt::Debug
         let id = if let Some(id) = self.asset_id_map.get(asset_name.as_ref()) {
                 *id as usize
             } else {
-                let id = self.data.len();
+                let id = self.assign_id(asset_name.as_ref());
                 self.asset_id_map.insert(asset_name.as_ref().to_owned(), id as u16);
                 id
             };
 
-        Serial::save_type_to_ron_file(&asset, &self.full_path(), asset_name, 1);
-        self.data.insert(id, asset.clone());
+        self.save_asset_file(asset, asset_name.as_ref());
+        self.record_modified_time(asset_name);
+        self.data[id] = asset.clone();
         id as u16
     }
 
-    /// Removing assets will require the game to be reinitialized, as it will corrupt all cached asset ids.
+    /// The removed id becomes a tombstone in the manifest & is never reused, so cached ids of other assets remain valid.
     /// 
     /// Deletes the associated asset file on success.
     /// 
@@ -164,7 +497,7 @@ impl<T: Default + Clone + Serialize + for<'a> Deserialize<'a> + std::fmt::Debug
         self.remove(&asset_name, id);
     }
 
-    /// Removing assets will require the game to be reinitialized, as it will corrupt all cached asset ids.
+    /// The removed id becomes a tombstone in the manifest & is never reused, so cached ids of other assets remain valid.
     /// 
     /// Deletes the associated asset file on success.
     /// 
@@ -175,10 +508,65 @@ impl<T: Default + Clone + Serialize + for<'a> Deserialize<'a> + std::fmt::Debug
     }
 
     fn remove<S: AsRef<str>>(&mut self, asset_name: S, asset_id: usize) {
-        swap_last_asset_id(asset_id, self.data.len(), &mut self.asset_id_map);
-        Serial::remove_ron_file(&self.full_path(), asset_name.as_ref());
+        Serial::remove_ron_file(self.asset_directory(asset_name.as_ref()), asset_name.as_ref());
         self.asset_id_map.remove(asset_name.as_ref());
-        self.data.swap_remove(asset_id);
+        self.asset_roots.remove(asset_name.as_ref());
+        self.modified_times.remove(asset_name.as_ref());
+        self.id_manifest.tombstone(asset_name.as_ref());
+        self.id_manifest.save(self.full_path());
+        self.data[asset_id] = T::default();
+    }
+
+    /// Returns the pinned id of `asset_name`, assigning & saving a fresh one if needed. `data` is grown to fit.
+    fn assign_id<S: AsRef<str>>(&mut self, asset_name: S) -> usize {
+        let id_count = self.id_manifest.id_count();
+        let id = self.id_manifest.assign(asset_name) as usize;
+        if self.id_manifest.id_count() != id_count { self.id_manifest.save(self.full_path()); }
+        if id >= self.data.len() { self.data.resize(id + 1, T::default()); }
+        id
+    }
+
+    /// `data/{name}` of the content root `asset_name` was loaded from, or of the base root for new assets.
+    fn asset_directory<S: AsRef<str>>(&self, asset_name: S) -> String {
+        if let Some(root) = self.asset_root(asset_name) { root.data_path(&self.type_name) } else { self.full_path() }
+    }
+
+    /// `(file_name, root_index)` of every .ron asset file in `data/{name}/*` of every content root, skipping the example & id manifest.
+    fn resolve_files(&self) -> ContentResolver {
+        let mut resolver = ContentResolver::default();
+
+        for (root_index, root) in self.content_roots.roots().iter().enumerate() {
+            let data_path = root.data_path(&self.type_name);
+            for file in Serial::file_paths_from_directory_recursive(&data_path, "").iter() {
+                let Some(file_name) = file.strip_prefix("/") else { continue };
+                if file_name == "example" || file_name == DataAssetIdManifest::FILE_NAME { continue; }
+                // Anything without a modification time is not a .ron file
+                if Serial::try_get_modified_time(Serial::path_string(&data_path, file_name, "ron")).is_none() { continue; }
+                resolver.provide(file_name, root_index);
+            }
+        }
+
+        resolver
+    }
+
+    fn save_asset_file<S: AsRef<str>>(&self, asset: &T, asset_name: S) {
+        if let Err(error) = self.migrations.save_ron_file(asset, self.asset_directory(asset_name.as_ref()), asset_name, 1) { warn!("[{}] Failed to save: {error}", self.type_name); }
+    }
+
+    fn load_asset_file<S: AsRef<str>>(&self, asset_name: S) -> SerialResult<T> {
+        self.migrations.load_ron_file(self.asset_directory(asset_name.as_ref()), asset_name, 1)
+    }
+
+    fn push_load_error<S: AsRef<str>>(&mut self, file_name: S, error: SerialError) {
+        warn!("[{}] Failed to load: {error}", self.type_name);
+        self.load_errors.push(DataAssetLoadError { file_name: file_name.as_ref().to_owned(), error });
+    }
+
+    /// Files written by this resource should not be picked up as changes by `reload_changed`.
+    fn record_modified_time<S: AsRef<str>>(&mut self, asset_name: S) {
+        let path = Serial::path_string(self.asset_directory(asset_name.as_ref()), asset_name.as_ref(), "ron");
+        let Some(modified) = Serial::try_get_modified_time(path) else { return };
+        self.modified_times.insert(asset_name.as_ref().to_owned(), modified);
     }
 }
 
@@ -198,7 +586,8 @@ impl<T: Default + Clone + TypePath + FromReflect + Sync + Send + 'static> Runtim
 impl<T: Default + Clone + TypePath + FromReflect + Sync + Send + 'static> Plugin for RuntimeDataAssetPlugin<T> {
     fn build(&self, app: &mut App) {
         app.register_type::<RuntimeDataAssets<T>>()
-            .insert_resource(RuntimeDataAssets::<T>::new(&self.type_name));
+            .insert_resource(RuntimeDataAssets::<T>::new(&self.type_name))
+            .add_systems(OnEnter(AppState::Main), stsys_report_missing_runtime_data_asset_names::<T>);
     }
 }
 
@@ -209,28 +598,67 @@ pub struct RuntimeDataAssets<T: Default + Clone + TypePath + FromReflect + Sync
     type_name: String,
     data: Vec<T>,
     asset_id_map: HashMap::<String, u16>,
+    #[reflect(ignore)]
+    missing_names: MissingDataAssetNames,
 }
 
 impl<T: Default + Clone + TypePath + FromReflect + Sync + Send + 'static> RuntimeDataAssets<T> {
     pub fn new<S: AsRef<str>>(type_name: S) -> Self {
-        Self { type_name: type_name.as_ref().to_owned(), data: vec![], asset_id_map: HashMap::default() }
+        Self { type_name: type_name.as_ref().to_owned(), ..default() }
     }
 
     pub fn type_name(&self) -> &String { &self.type_name }
     pub fn data(&self) -> &[T] { &self.data }
 
     /// It is intended that you use this to cache the ID of the desired asset, and then get the asset when needed using `asset_from_id`.
+    /// 
+    /// Unknown names default to id 0 & are recorded in `missing_names`. Prefer `try_id_from_name` or `expect_id_from_name`.
     pub fn id_from_name<S: AsRef<str>>(&self, asset: S) -> u16 {
-        if let Some(id) = self.asset_id_map.get(asset.as_ref()) { *id } else { 0 }
+        if let Some(id) = self.asset_id_map.get(asset.as_ref()) {
+            *id
+        } else {
+            self.missing_names.record(asset);
+            0
+        }
+    }
+
+    pub fn try_id_from_name<S: AsRef<str>>(&self, asset: S) -> Option<DataAssetId<T>> {
+        self.asset_id_map.get(asset.as_ref()).map(|id| DataAssetId::new(*id))
+    }
+
+    /// Panics if `asset` does not exist.
+    pub fn expect_id_from_name<S: AsRef<str>>(&self, asset: S) -> DataAssetId<T> {
+        let Some(id) = self.try_id_from_name(asset.as_ref()) else { panic!("[{}] {}", self.type_name, DataAssetLookupError::UnknownName(asset.as_ref().to_owned())) };
+        id
     }
 
     pub fn get(&self, id: usize) -> &T { &self.data[id] }
 
+    /// Returns None if `id` is out of range.
+    pub fn try_get(&self, id: DataAssetId<T>) -> Option<&T> { self.data.get(id.index()) }
+
     /// Don't use this in performance critical areas
+    /// 
+    /// Unknown names default to id 0 & are recorded in `missing_names`. Prefer `try_get_from_name`.
     pub fn get_from_name<S: AsRef<str>>(&self, asset: S) -> &T {
         self.get(self.id_from_name(asset) as usize)
     }
 
+    /// Don't use this in performance critical areas
+    pub fn try_get_from_name<S: AsRef<str>>(&self, asset: S) -> Result<&T, DataAssetLookupError> {
+        let Some(id) = self.try_id_from_name(asset.as_ref()) else { return Err(DataAssetLookupError::UnknownName(asset.as_ref().to_owned())) };
+        self.try_get(id).ok_or(DataAssetLookupError::InvalidId(id.get()))
+    }
+
+    /// Every name that was requested through `id_from_name` or `get_from_name` and not found, sorted.
+    pub fn missing_names(&self) -> Vec<String> { self.missing_names.get() }
+
+    /// Returns None if `asset_name` already exists.
+    pub fn try_add<S: AsRef<str>>(&mut self, asset_name: S, asset: &T) -> Option<DataAssetId<T>> {
+        if self.asset_id_map.contains_key(asset_name.as_ref()) { return None; }
+        Some(DataAssetId::new(self.add(asset_name, asset)))
+    }
+
     /// Will fail & return u16::MAX if `asset_name` already exists.
     /// 
     /// Returns id of added asset.
@@ -305,6 +733,31 @@ impl<T: Default + Clone + TypePath + FromReflect + Sync + Send + 'static> Runtim
     }
 }
 
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Migrations may be registered on the `App` after [DataAssetPlugin] is added, so assets are only loaded once they are all known.
+fn stsys_init_data_assets<T: Default + Clone + Serialize + for<'a> Deserialize<'a> + std::fmt::Debug + TypePath + FromReflect + Sync + Send + 'static>(migrations: Option<Res<DataMigrations<T>>>, mut data_assets: ResMut<DataAssets<T>>) {
+    if let Some(migrations) = migrations { data_assets.migrations = migrations.clone(); }
+    data_assets.init();
+}
+
+fn sys_update_data_asset_load_report<T: Default + Clone + Serialize + for<'a> Deserialize<'a> + std::fmt::Debug + TypePath + FromReflect + Sync + Send + 'static>(data_assets: Res<DataAssets<T>>, mut report: ResMut<DataAssetLoadReport>) {
+    report.set(data_assets.type_name(), data_assets.load_errors().to_vec());
+    report.set_conflicts(data_assets.type_name(), data_assets.conflicts().to_vec());
+}
+
+fn stsys_report_missing_data_asset_names<T: Default + Clone + Serialize + for<'a> Deserialize<'a> + std::fmt::Debug + TypePath + FromReflect + Sync + Send + 'static>(data_assets: Res<DataAssets<T>>) {
+    report_missing_names(data_assets.type_name(), &data_assets.missing_names());
+}
+
+fn stsys_report_missing_runtime_data_asset_names<T: Default + Clone + TypePath + FromReflect + Sync + Send + 'static>(data_assets: Res<RuntimeDataAssets<T>>) {
+    report_missing_names(data_assets.type_name(), &data_assets.missing_names());
+}
+
+fn report_missing_names(type_name: &str, missing_names: &[String]) {
+    if missing_names.is_empty() { return; }
+    warn!("[{type_name}] {} requested asset names were never found: {}", missing_names.len(), missing_names.join(", "));
+}
+
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 /// The `asset_name` associated with the last `asset_id` is swapped to associate with `remove_id` instead.
 fn swap_last_asset_id(remove_id: usize, data_len: usize, asset_id_map: &mut HashMap<String, u16>) {
@@ -322,4 +775,78 @@ fn swap_last_asset_id(remove_id: usize, data_len: usize, asset_id_map: &mut Hash
     // If there is no name associated with the last id, the database is corrupt and we should panic.
     // Otherwise, replace the id of the last asset with the id of the asset being removed.
     asset_id_map.insert(last_name.unwrap(), remove_id as u16);
-}
\ No newline at end of file
+}
+////////////////////////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, Default, Clone, PartialEq, Debug, Reflect)]
+    struct PackTestAsset {
+        value: u32,
+    }
+
+    #[test]
+    fn baked_pack_reloads_and_verifies() {
+        let directory = std::env::temp_dir().join(format!("tank_pack_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        let content_roots = ContentRoots::new(ContentRoot::new("base", directory.to_str().unwrap()));
+
+        let mut data_assets = DataAssets::<PackTestAsset>::new_with_roots("pack_tests", DataAssetSource::Ron, content_roots.clone());
+        data_assets.save_asset_file(&PackTestAsset { value: 1 }, "one");
+        data_assets.save_asset_file(&PackTestAsset { value: 2 }, "two");
+        data_assets.load_all();
+        assert!(data_assets.load_errors().is_empty());
+
+        data_assets.bake().unwrap();
+        assert!(data_assets.verify_pack().unwrap());
+
+        // Removing the RON files makes sure the reloaded assets can only come from the pack
+        std::fs::remove_file(Serial::path_string(data_assets.full_path(), "one", "ron")).unwrap();
+        std::fs::remove_file(Serial::path_string(data_assets.full_path(), "two", "ron")).unwrap();
+
+        let reloaded = DataAssets::<PackTestAsset>::new_with_roots("pack_tests", DataAssetSource::PackIfExists, content_roots);
+        let verified = reloaded.verify_pack();
+        let _ = std::fs::remove_dir_all(&directory);
+
+        assert!(reloaded.load_errors().is_empty());
+        assert!(verified.unwrap());
+        for name in ["one", "two"] {
+            assert_eq!(reloaded.try_id_from_name(name), data_assets.try_id_from_name(name));
+            assert_eq!(reloaded.try_get_from_name(name).ok(), data_assets.try_get_from_name(name).ok());
+        }
+    }
+
+    #[test]
+    fn plugin_loads_once_with_migrations_registered_after_it() {
+        let directory = std::env::temp_dir().join(format!("tank_migrate_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        let content_roots = ContentRoots::new(ContentRoot::new("base", directory.to_str().unwrap()));
+        let asset_directory = content_roots.base().data_path("migrate_tests");
+        Serial::create_directory_path(&asset_directory);
+        std::fs::write(Serial::path_string(&asset_directory, "old", "ron"), "(count: 4)").unwrap();
+
+        let mut app = App::new();
+        app.insert_resource(content_roots)
+            .add_plugins(DataAssetPlugin::<PackTestAsset>::new("migrate_tests"))
+            .register_data_migration::<PackTestAsset>(0, |value| {
+                let ron::Value::Map(mut map) = value else { return value };
+                if let Some(old) = map.remove(&ron::Value::String("count".to_owned())) { map.insert(ron::Value::String("value".to_owned()), old); }
+                ron::Value::Map(map)
+            });
+        assert!(app.world.resource::<DataAssets<PackTestAsset>>().data().is_empty());
+
+        app.update();
+        let data_assets = app.world.resource::<DataAssets<PackTestAsset>>();
+        let example = std::fs::read_to_string(Serial::path_string(&asset_directory, "example", "ron")).unwrap();
+        let old = std::fs::read_to_string(Serial::path_string(&asset_directory, "old", "ron")).unwrap();
+        let _ = std::fs::remove_dir_all(&directory);
+
+        assert!(data_assets.load_errors().is_empty());
+        assert_eq!(data_assets.migrations().current_version(), 1);
+        assert_eq!(data_assets.try_get_from_name("old"), Ok(&PackTestAsset { value: 4 }));
+        // Only ever written at the current version, & migrated files are left alone unless `rewrite_in_place` is set
+        assert!(example.contains("version: 1"));
+        assert_eq!(old, "(count: 4)");
+    }
+}
@@ -0,0 +1,102 @@
+use crate::*;
+
+use std::{hash::{Hash, Hasher}, sync::Mutex};
+use bevy::utils::HashSet;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Id of an asset in `DataAssets<T>` or `RuntimeDataAssets<T>`, typed so that it can't be used to index the wrong collection.
+///
+/// Serializes as a plain `u16`.
+#[derive(Reflect)]
+pub struct DataAssetId<T: TypePath + Sync + Send + 'static> {
+    id: u16,
+    #[reflect(ignore)]
+    phantom_data: PhantomData<T>,
+}
+
+impl<T: TypePath + Sync + Send + 'static> DataAssetId<T> {
+    pub fn new(id: u16) -> Self { Self { id, phantom_data: PhantomData } }
+    pub fn get(&self) -> u16 { self.id }
+    pub fn index(&self) -> usize { self.id as usize }
+}
+
+impl<T: TypePath + Sync + Send + 'static> Default for DataAssetId<T> {
+    fn default() -> Self { Self::new(0) }
+}
+
+impl<T: TypePath + Sync + Send + 'static> Clone for DataAssetId<T> {
+    fn clone(&self) -> Self { *self }
+}
+
+impl<T: TypePath + Sync + Send + 'static> Copy for DataAssetId<T> {}
+
+impl<T: TypePath + Sync + Send + 'static> PartialEq for DataAssetId<T> {
+    fn eq(&self, other: &Self) -> bool { self.id == other.id }
+}
+
+impl<T: TypePath + Sync + Send + 'static> Eq for DataAssetId<T> {}
+
+impl<T: TypePath + Sync + Send + 'static> Hash for DataAssetId<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) { self.id.hash(state); }
+}
+
+impl<T: TypePath + Sync + Send + 'static> std::fmt::Debug for DataAssetId<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DataAssetId<{}>({})", T::short_type_path(), self.id)
+    }
+}
+
+impl<T: TypePath + Sync + Send + 'static> Serialize for DataAssetId<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> { self.id.serialize(serializer) }
+}
+
+impl<'de, T: TypePath + Sync + Send + 'static> Deserialize<'de> for DataAssetId<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> { Ok(Self::new(u16::deserialize(deserializer)?)) }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum DataAssetLookupError {
+    UnknownName(String),
+    /// The id was removed & is now a tombstone, or was never assigned.
+    InvalidId(u16),
+}
+
+impl std::fmt::Display for DataAssetLookupError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownName(name) => write!(f, "no asset named [{name}]"),
+            Self::InvalidId(id) => write!(f, "no asset with id [{id}]"),
+        }
+    }
+}
+
+impl std::error::Error for DataAssetLookupError {}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Names that were requested through the defaulting lookups (`id_from_name`, `get_from_name`) and not found.
+///
+/// Lookups only take `&self`, so this is behind a mutex to be shareable between systems.
+#[derive(Default, Debug)]
+pub struct MissingDataAssetNames(Mutex<HashSet<String>>);
+
+impl MissingDataAssetNames {
+    pub fn record<S: AsRef<str>>(&self, name: S) {
+        let Ok(mut names) = self.0.lock() else { return };
+        names.insert(name.as_ref().to_owned());
+    }
+
+    /// Sorted
+    pub fn get(&self) -> Vec<String> {
+        let Ok(names) = self.0.lock() else { return vec![] };
+        let mut names: Vec<String> = names.iter().cloned().collect();
+        names.sort();
+        names
+    }
+
+    pub fn clear(&self) {
+        let Ok(mut names) = self.0.lock() else { return };
+        names.clear();
+    }
+}
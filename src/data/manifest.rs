@@ -0,0 +1,78 @@
+use crate::*;
+
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Pins asset names to ids across runs & machines, so ids can be stored in save files & network packets.
+///
+/// Stored as `assets/data/{name}/_ids.ron`. Ids are never reused: removed assets leave a tombstone behind.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+pub struct DataAssetIdManifest {
+    ids: BTreeMap<String, u16>,
+    tombstones: Vec<u16>,
+}
+
+impl DataAssetIdManifest {
+    pub const FILE_NAME: &'static str = "_ids";
+
+    /// Returns an empty manifest if `directory` does not contain one yet, or it fails to load.
+    pub fn load<S: AsRef<str>>(directory: S) -> Self {
+        Self::load_checked(directory).unwrap_or_default()
+    }
+
+    /// Returns an empty manifest if `directory` does not contain one yet. Any other failure is an error.
+    pub fn load_checked<S: AsRef<str>>(directory: S) -> SerialResult<Self> {
+        match Serial::load_type_from_ron_file_checked(directory, Self::FILE_NAME) {
+            Ok(manifest) => Ok(manifest),
+            Err(error) if error.is_not_found() => Ok(Self::default()),
+            Err(error) => Err(error),
+        }
+    }
+
+    pub fn save<S: AsRef<str>>(&self, directory: S) {
+        Serial::save_type_to_ron_file(self, directory, Self::FILE_NAME, 2);
+    }
+
+    pub fn ids(&self) -> &BTreeMap<String, u16> { &self.ids }
+    pub fn tombstones(&self) -> &[u16] { &self.tombstones }
+
+    pub fn get<S: AsRef<str>>(&self, name: S) -> Option<u16> { self.ids.get(name.as_ref()).cloned() }
+
+    pub fn name(&self, id: u16) -> Option<&String> {
+        self.ids.iter().find(|(_, existing_id)| **existing_id == id).map(|(name, _)| name)
+    }
+
+    pub fn is_tombstone(&self, id: u16) -> bool { self.tombstones.contains(&id) }
+
+    /// One past the highest id that has ever been assigned, including tombstones.
+    pub fn id_count(&self) -> usize {
+        let max_id = self.ids.values().chain(self.tombstones.iter()).max();
+        if let Some(max_id) = max_id { *max_id as usize + 1 } else { 0 }
+    }
+
+    /// Returns the pinned id of `name`, assigning a fresh one if it does not have one yet.
+    pub fn assign<S: AsRef<str>>(&mut self, name: S) -> u16 {
+        if let Some(id) = self.get(name.as_ref()) { return id; }
+
+        let id = self.id_count() as u16;
+        self.ids.insert(name.as_ref().to_owned(), id);
+        id
+    }
+
+    /// Returns the id that was tombstoned, if `name` was in the manifest.
+    pub fn tombstone<S: AsRef<str>>(&mut self, name: S) -> Option<u16> {
+        let id = self.ids.remove(name.as_ref())?;
+        self.tombstones.push(id);
+        Some(id)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum DataAssetIdMismatch {
+    /// The manifest pins an id to a name that has no .ron file. The id stays reserved.
+    MissingFile { name: String, id: u16 },
+    /// A .ron file exists that the manifest does not know about.
+    Unregistered { name: String },
+}
@@ -1,4 +1,12 @@
 use crate::*;
 
 mod asset;
-pub use asset::*;
\ No newline at end of file
+pub use asset::*;
+mod id;
+pub use id::*;
+mod manifest;
+pub use manifest::*;
+mod pack;
+pub use pack::*;
+mod report;
+pub use report::*;
\ No newline at end of file
@@ -0,0 +1,57 @@
+use crate::*;
+
+use serde::{Deserialize, Serialize};
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Where `DataAssets<T>` loads from. RON is always the authoring format.
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug, Reflect)]
+pub enum DataAssetSource {
+    /// `assets/data/{name}/*.ron`
+    #[default]
+    Ron,
+    /// `assets/data/{name}.bin` if it exists, otherwise `assets/data/{name}/*.ron`
+    PackIfExists,
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Every asset of a `DataAssets<T>` baked into a single bincode file, along with its id manifest.
+///
+/// Stored as `data/{name}.bin` of the base content root, next to the directory it was baked from.
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct DataAssetPack<T> {
+    id_manifest: DataAssetIdManifest,
+    /// Sorted by id
+    assets: Vec<(String, T)>,
+}
+
+impl<T: Serialize + for<'a> Deserialize<'a>> DataAssetPack<T> {
+    /// `assets` will be sorted by id. Names missing from `id_manifest` are dropped.
+    pub fn new(id_manifest: DataAssetIdManifest, assets: Vec<(String, T)>) -> Self {
+        let mut assets: Vec<(String, T)> = assets.into_iter().filter(|(name, _)| id_manifest.get(name).is_some()).collect();
+        assets.sort_by_key(|(name, _)| id_manifest.get(name));
+        Self { id_manifest, assets }
+    }
+
+    pub fn id_manifest(&self) -> &DataAssetIdManifest { &self.id_manifest }
+    pub fn assets(&self) -> &[(String, T)] { &self.assets }
+    pub fn into_parts(self) -> (DataAssetIdManifest, Vec<(String, T)>) { (self.id_manifest, self.assets) }
+
+    /// `{data_directory}/{type_name}.bin`
+    pub fn path<S0: AsRef<str>, S1: AsRef<str>>(data_directory: S0, type_name: S1) -> String {
+        Serial::path_string(data_directory, type_name, BincodeFormat.extension())
+    }
+
+    pub fn exists<S0: AsRef<str>, S1: AsRef<str>>(data_directory: S0, type_name: S1) -> bool {
+        Serial::try_get_modified_time(Self::path(data_directory, type_name)).is_some()
+    }
+
+    pub fn save<S0: AsRef<str>, S1: AsRef<str>>(&self, data_directory: S0, type_name: S1) -> SerialResult<()> {
+        Serial::save_type_to_file_checked(&BincodeFormat, self, data_directory, type_name)
+    }
+
+    pub fn load<S0: AsRef<str>, S1: AsRef<str>>(data_directory: S0, type_name: S1) -> SerialResult<Self> {
+        Serial::load_type_from_file_checked(&BincodeFormat, data_directory, type_name)
+    }
+
+    pub fn to_bytes(&self) -> SerialResult<Vec<u8>> { BincodeFormat.serialize(self, "<memory>") }
+}
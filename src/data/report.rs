@@ -0,0 +1,53 @@
+use crate::*;
+
+use bevy::utils::HashMap;
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+#[derive(Clone, Debug)]
+pub struct DataAssetLoadError {
+    /// Relative to `assets/data/{name}`, without extension
+    pub file_name: String,
+    pub error: SerialError,
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Per-file load errors & content root conflicts of every `DataAssets<T>`, keyed by type name. Each `DataAssetPlugin<T>` keeps its entry in sync, including after hot reloads.
+#[derive(Resource, Default, Debug)]
+pub struct DataAssetLoadReport {
+    errors: HashMap<String, Vec<DataAssetLoadError>>,
+    conflicts: HashMap<String, Vec<ContentConflict>>,
+}
+
+impl DataAssetLoadReport {
+    pub fn errors<S: AsRef<str>>(&self, type_name: S) -> &[DataAssetLoadError] {
+        if let Some(errors) = self.errors.get(type_name.as_ref()) { errors } else { &[] }
+    }
+
+    /// `(type_name, error)`
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &DataAssetLoadError)> {
+        self.errors.iter().flat_map(|(type_name, errors)| errors.iter().map(move |error| (type_name, error)))
+    }
+
+    pub fn error_count(&self) -> usize { self.errors.values().map(|errors| errors.len()).sum() }
+    pub fn is_empty(&self) -> bool { self.error_count() == 0 }
+
+    pub fn set<S: AsRef<str>>(&mut self, type_name: S, errors: Vec<DataAssetLoadError>) {
+        if errors.is_empty() {
+            self.errors.remove(type_name.as_ref());
+        } else {
+            self.errors.insert(type_name.as_ref().to_owned(), errors);
+        }
+    }
+
+    pub fn conflicts<S: AsRef<str>>(&self, type_name: S) -> &[ContentConflict] {
+        if let Some(conflicts) = self.conflicts.get(type_name.as_ref()) { conflicts } else { &[] }
+    }
+
+    pub fn set_conflicts<S: AsRef<str>>(&mut self, type_name: S, conflicts: Vec<ContentConflict>) {
+        if conflicts.is_empty() {
+            self.conflicts.remove(type_name.as_ref());
+        } else {
+            self.conflicts.insert(type_name.as_ref().to_owned(), conflicts);
+        }
+    }
+}
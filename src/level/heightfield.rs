@@ -0,0 +1,298 @@
+use crate::*;
+
+use std::{io::Cursor, path::Path};
+use bevy::utils::HashSet;
+use ::image::{ImageBuffer, ImageFormat, ImageOutputFormat, Luma, DynamicImage};
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// File formats of heightfields from external terrain tools. Every format stores unsigned 16 bit samples, row by row.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Reflect)]
+pub enum HeightfieldFormat {
+    /// 16 bit grayscale PNG. 8 bit images are scaled up when imported.
+    Png16,
+    /// Samples with no header, so the width must be known or the heightfield must be square.
+    /// `.r16` files & most `.raw` files are little endian.
+    Raw16 { big_endian: bool },
+}
+
+impl HeightfieldFormat {
+    /// From `.png`, `.r16` or `.raw`, ignoring case.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_lowercase();
+        match extension.as_str() {
+            "png" => { Some(Self::Png16) }
+            "r16" | "raw" => { Some(Self::Raw16 { big_endian: false }) }
+            _ => { None }
+        }
+    }
+}
+
+/// How samples map to heights. A sample of 0 is `height_offset`, and 65535 is `height_offset + height_scale`.
+#[derive(Clone, Copy, PartialEq, Debug, Reflect)]
+pub struct HeightfieldParams {
+    /// Heightmap coord of the first sample. Following samples go along x, then each row along y.
+    pub origin: IVec2,
+    pub height_scale: f32,
+    pub height_offset: f32,
+}
+
+impl Default for HeightfieldParams {
+    fn default() -> Self { Self { origin: IVec2::ZERO, height_scale: 256.0, height_offset: 0.0 } }
+}
+
+impl HeightfieldParams {
+    pub fn height_from_sample(&self, sample: u16) -> f32 {
+        self.height_offset + sample as f32 / u16::MAX as f32 * self.height_scale
+    }
+
+    /// Clamped to the range of a sample.
+    pub fn sample_from_height(&self, height: f32) -> u16 {
+        if self.height_scale == 0.0 { return 0; }
+        ((height - self.height_offset) / self.height_scale * u16::MAX as f32).round().clamp(0.0, u16::MAX as f32) as u16
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Samples of an imported or exported heightfield, indexed by `y * width + x`.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Heightfield {
+    width: u32,
+    height: u32,
+    samples: Vec<u16>,
+}
+
+impl Heightfield {
+    /// None if `samples` does not hold `width * height` samples.
+    pub fn new(width: u32, height: u32, samples: Vec<u16>) -> Option<Self> {
+        if samples.len() != (width as usize).checked_mul(height as usize)? { return None; }
+        Some(Self { width, height, samples })
+    }
+
+    #[inline] pub fn width(&self) -> u32 { self.width }
+    #[inline] pub fn height(&self) -> u32 { self.height }
+    #[inline] pub fn samples(&self) -> &[u16] { &self.samples }
+    #[inline] pub fn sample(&self, x: u32, y: u32) -> u16 { self.samples[(y * self.width + x) as usize] }
+
+    /// `width` is only used by raw formats, which are assumed to be square without it. `path` is only used for errors.
+    pub fn from_bytes<P: AsRef<Path>>(bytes: &[u8], format: HeightfieldFormat, width: Option<u32>, path: P) -> SerialResult<Self> {
+        let corrupt = |message: String| SerialError::Corrupt { path: path.as_ref().display().to_string(), message };
+        if bytes.is_empty() { return Err(SerialError::EmptyFile { path: path.as_ref().display().to_string() }); }
+
+        match format {
+            HeightfieldFormat::Png16 => {
+                let image = ::image::load_from_memory_with_format(bytes, ImageFormat::Png).map_err(|error| corrupt(error.to_string()))?.into_luma16();
+                let (width, height) = image.dimensions();
+                Ok(Self { width, height, samples: image.into_raw() })
+            }
+            HeightfieldFormat::Raw16 { big_endian } => {
+                if !bytes.len().is_multiple_of(2) { return Err(corrupt(format!("{} bytes is not a whole number of 16 bit samples", bytes.len()))); }
+                let samples: Vec<u16> = bytes.chunks_exact(2)
+                    .map(|sample| if big_endian { u16::from_be_bytes([sample[0], sample[1]]) } else { u16::from_le_bytes([sample[0], sample[1]]) })
+                    .collect();
+
+                let width = match width {
+                    Some(width) => { width }
+                    None => {
+                        let width = (samples.len() as f64).sqrt().round() as u32;
+                        if width as usize * width as usize != samples.len() { return Err(corrupt(format!("{} samples is not square, so the width is needed", samples.len()))); }
+                        width
+                    }
+                };
+
+                if width == 0 || !samples.len().is_multiple_of(width as usize) { return Err(corrupt(format!("{} samples do not fit rows of {width}", samples.len()))); }
+                Ok(Self { width, height: samples.len() as u32 / width, samples })
+            }
+        }
+    }
+
+    /// `path` is only used for errors.
+    pub fn to_bytes<P: AsRef<Path>>(&self, format: HeightfieldFormat, path: P) -> SerialResult<Vec<u8>> {
+        match format {
+            HeightfieldFormat::Png16 => {
+                let corrupt = |message: String| SerialError::Corrupt { path: path.as_ref().display().to_string(), message };
+                let image = ImageBuffer::<Luma<u16>, Vec<u16>>::from_raw(self.width, self.height, self.samples.clone())
+                    .ok_or_else(|| corrupt("samples do not match the dimensions".to_owned()))?;
+
+                let mut bytes = vec![];
+                DynamicImage::ImageLuma16(image).write_to(&mut Cursor::new(&mut bytes), ImageOutputFormat::Png).map_err(|error| corrupt(error.to_string()))?;
+                Ok(bytes)
+            }
+            HeightfieldFormat::Raw16 { big_endian } => {
+                Ok(self.samples.iter().flat_map(|sample| if big_endian { sample.to_be_bytes() } else { sample.to_le_bytes() }).collect())
+            }
+        }
+    }
+
+    /// Format from the extension, see [HeightfieldFormat::from_path].
+    pub fn load<P: AsRef<Path>>(path: P, width: Option<u32>) -> SerialResult<Self> {
+        let format = Self::format_from_path(&path)?;
+        let bytes = Serial::get_bytes_from_path_checked(&path)?;
+        Self::from_bytes(&bytes, format, width, path)
+    }
+
+    /// Format from the extension, see [HeightfieldFormat::from_path]. Creates the directory if needed.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> SerialResult<()> {
+        let format = Self::format_from_path(&path)?;
+        let bytes = self.to_bytes(format, &path)?;
+        Serial::write_file_and_path_checked(path, &bytes)
+    }
+
+    fn format_from_path<P: AsRef<Path>>(path: P) -> SerialResult<HeightfieldFormat> {
+        HeightfieldFormat::from_path(&path).ok_or_else(|| SerialError::InvalidPath { path: path.as_ref().display().to_string() })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+impl HeightmapRoot {
+    /// Sets the heights covered by `heightfield`, creating chunks as needed, and marks every chunk touched.
+    pub fn import_heightfield(&mut self, changes: &mut HeightmapRootChanges, heightfield: &Heightfield, params: &HeightfieldParams) {
+        let mut chunk_keys = HashSet::<IVec2>::default();
+        for y in 0..heightfield.height() { for x in 0..heightfield.width() {
+            let coord = params.origin + IVec2::new(x as i32, y as i32);
+            self.set_value_at_coord(coord, params.height_from_sample(heightfield.sample(x, y)));
+            chunk_keys.insert(coord & !CHUNK_2D_MASK);
+        }}
+
+        for key in chunk_keys { <HeightmapRoot as StreamedRoot>::mark_chunk_changes(changes, key); }
+    }
+
+    /// Heights from `min` to `max` inclusive, with `params.origin` ignored. Heights in missing chunks are 0.
+    pub fn export_heightfield(&self, min: IVec2, max: IVec2, params: &HeightfieldParams) -> Heightfield {
+        let (min, max) = (min.min(max), min.max(max));
+        let size = (max - min + 1).as_uvec2();
+
+        let mut samples = Vec::with_capacity((size.x * size.y) as usize);
+        for y in min.y..=max.y { for x in min.x..=max.x {
+            samples.push(params.sample_from_height(self.get_value_at_coord(IVec2::new(x, y))));
+        }}
+
+        Heightfield { width: size.x, height: size.y, samples }
+    }
+
+    /// See [Heightfield::load] & [HeightmapRoot::import_heightfield].
+    pub fn import_heightfield_from_path<P: AsRef<Path>>(
+        &mut self,
+        changes: &mut HeightmapRootChanges,
+        path: P,
+        width: Option<u32>,
+        params: &HeightfieldParams,
+    ) -> SerialResult<()> {
+        let heightfield = Heightfield::load(path, width)?;
+        self.import_heightfield(changes, &heightfield, params);
+        Ok(())
+    }
+
+    /// See [HeightmapRoot::export_heightfield] & [Heightfield::save].
+    pub fn export_heightfield_to_path<P: AsRef<Path>>(&self, path: P, min: IVec2, max: IVec2, params: &HeightfieldParams) -> SerialResult<()> {
+        self.export_heightfield(min, max, params).save(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heightfield() -> Heightfield {
+        // Not square, with the extremes of a sample & bytes that differ when swapped
+        Heightfield::new(3, 2, vec![0, 1, 0x1234, 0xff00, 40000, u16::MAX]).unwrap()
+    }
+
+    #[test]
+    fn new_checks_the_sample_count() {
+        assert!(Heightfield::new(2, 2, vec![0; 4]).is_some());
+        assert!(Heightfield::new(2, 2, vec![0; 3]).is_none());
+        assert!(Heightfield::new(u32::MAX, u32::MAX, vec![]).is_none());
+        assert!(Heightfield::new(0, u32::MAX, vec![]).is_some());
+    }
+
+    #[test]
+    fn png16_round_trip() {
+        let heightfield = heightfield();
+        let bytes = heightfield.to_bytes(HeightfieldFormat::Png16, "test.png").unwrap();
+        assert_eq!(Heightfield::from_bytes(&bytes, HeightfieldFormat::Png16, None, "test.png").unwrap(), heightfield);
+    }
+
+    #[test]
+    fn png8_is_scaled_up() {
+        let image = ImageBuffer::<Luma<u8>, Vec<u8>>::from_raw(2, 1, vec![0, 255]).unwrap();
+        let mut bytes = vec![];
+        DynamicImage::ImageLuma8(image).write_to(&mut Cursor::new(&mut bytes), ImageOutputFormat::Png).unwrap();
+
+        let heightfield = Heightfield::from_bytes(&bytes, HeightfieldFormat::Png16, None, "test.png").unwrap();
+        assert_eq!(heightfield.samples(), &[0, u16::MAX]);
+    }
+
+    #[test]
+    fn raw16_round_trip() {
+        let heightfield = heightfield();
+        for big_endian in [false, true] {
+            let format = HeightfieldFormat::Raw16 { big_endian };
+            let bytes = heightfield.to_bytes(format, "test.r16").unwrap();
+            assert_eq!(bytes.len(), 12);
+            assert_eq!(Heightfield::from_bytes(&bytes, format, Some(3), "test.r16").unwrap(), heightfield);
+        }
+
+        let little = heightfield.to_bytes(HeightfieldFormat::Raw16 { big_endian: false }, "test.r16").unwrap();
+        assert_eq!(&little[4..6], &[0x34, 0x12]);
+    }
+
+    #[test]
+    fn raw16_needs_a_width_unless_square() {
+        let format = HeightfieldFormat::Raw16 { big_endian: false };
+        let square = Heightfield::new(2, 2, vec![1, 2, 3, 4]).unwrap();
+        let bytes = square.to_bytes(format, "test.raw").unwrap();
+        assert_eq!(Heightfield::from_bytes(&bytes, format, None, "test.raw").unwrap(), square);
+
+        let bytes = heightfield().to_bytes(format, "test.raw").unwrap();
+        assert!(matches!(Heightfield::from_bytes(&bytes, format, None, "test.raw"), Err(SerialError::Corrupt { .. })));
+        assert!(matches!(Heightfield::from_bytes(&bytes, format, Some(4), "test.raw"), Err(SerialError::Corrupt { .. })));
+        assert!(matches!(Heightfield::from_bytes(&bytes[..5], format, Some(3), "test.raw"), Err(SerialError::Corrupt { .. })));
+        assert!(matches!(Heightfield::from_bytes(&[], format, Some(3), "test.raw"), Err(SerialError::EmptyFile { .. })));
+    }
+
+    #[test]
+    fn formats_from_extensions() {
+        assert_eq!(HeightfieldFormat::from_path("a/b.PNG"), Some(HeightfieldFormat::Png16));
+        assert_eq!(HeightfieldFormat::from_path("b.r16"), Some(HeightfieldFormat::Raw16 { big_endian: false }));
+        assert_eq!(HeightfieldFormat::from_path("b.raw"), Some(HeightfieldFormat::Raw16 { big_endian: false }));
+        assert_eq!(HeightfieldFormat::from_path("b.ron"), None);
+        assert_eq!(HeightfieldFormat::from_path("b"), None);
+    }
+
+    #[test]
+    fn import_then_export_round_trip() {
+        let params = HeightfieldParams { origin: IVec2::new(-2, 30), height_scale: 100.0, height_offset: -20.0 };
+        let heightfield = heightfield();
+        let mut root = HeightmapRoot::default();
+        let mut changes = HeightmapRootChanges::default();
+        root.import_heightfield(&mut changes, &heightfield, &params);
+
+        assert_eq!(root.get_value_at_coord(IVec2::new(-2, 30)), -20.0);
+        assert_eq!(root.get_value_at_coord(IVec2::new(0, 31)), 80.0);
+        assert!(root.chunks().len() > 1);
+        assert!(root.chunks().keys().all(|key| changes.iter().any(|changed| changed == *key)));
+
+        let exported = root.export_heightfield(IVec2::new(0, 31), IVec2::new(-2, 30), &params);
+        assert_eq!(exported, heightfield);
+    }
+
+    #[test]
+    fn files_round_trip() {
+        let directory = std::env::temp_dir().join(format!("tank_heightfield_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        let heightfield = heightfield();
+
+        let png_path = directory.join("terrain.png");
+        let raw_path = directory.join("terrain.r16");
+        heightfield.save(&png_path).unwrap();
+        heightfield.save(&raw_path).unwrap();
+        let png = Heightfield::load(&png_path, None);
+        let raw = Heightfield::load(&raw_path, Some(3));
+        let unknown = heightfield.save(directory.join("terrain.bmp"));
+        let _ = std::fs::remove_dir_all(&directory);
+
+        assert_eq!(png.unwrap(), heightfield);
+        assert_eq!(raw.unwrap(), heightfield);
+        assert!(matches!(unknown, Err(SerialError::InvalidPath { .. })));
+    }
+}
@@ -1,6 +1,6 @@
 use crate::*;
 
-use std::sync::{RwLock, Arc};
+use std::sync::{RwLock, RwLockReadGuard, Arc};
 use bevy::{utils::{HashMap, HashSet}, pbr::wireframe::Wireframe};
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -9,9 +9,11 @@ impl Plugin for TankLevelHeightmapPlugin {
     fn build(&self, app: &mut App) {
         app.register_type::<HeightmapRootChanges>()
             .register_type::<HeightmapRootMesher>()
+            .add_plugins(ChunkStreamerPlugin::<HeightmapRoot>::default())
             .add_systems(PostUpdate, (
+                sys_update_heightmap_lods,
                 sys_update_heightmap_meshes,
-            ));
+            ).chain());
     }
 }
 
@@ -30,6 +32,47 @@ impl HeightmapRoot {
 
     #[inline] pub fn chunk_from_coord(&self, coord: IVec2) -> Option<&Arc<RwLock<HeightmapChunk>>> { self.chunks.get(&(coord & !CHUNK_2D_MASK)) }
 
+    /// Replaces any chunk already at `chunk_key`.
+    pub fn insert_chunk(&mut self, chunk_key: IVec2, chunk: HeightmapChunk) {
+        self.chunks.insert(chunk_key & !CHUNK_2D_MASK, Arc::new(RwLock::new(chunk)));
+    }
+
+    pub fn remove_chunk(&mut self, chunk_key: IVec2) -> Option<Arc<RwLock<HeightmapChunk>>> {
+        self.chunks.remove(&(chunk_key & !CHUNK_2D_MASK))
+    }
+
+    /// `directory/h.{x}.{y}.bin`, holding every height as a little endian f32.
+    pub fn chunk_path<S: AsRef<str>>(directory: S, chunk_key: IVec2) -> String {
+        let chunk_key = chunk_key & !CHUNK_2D_MASK;
+        Serial::path_string(directory, format!("h.{}.{}", chunk_key.x, chunk_key.y), "bin")
+    }
+
+    /// Does nothing if the chunk does not exist.
+    pub fn save_chunk<S: AsRef<str>>(&self, directory: S, chunk_key: IVec2) -> SerialResult<()> {
+        let Some(chunk) = self.chunk_from_coord(chunk_key) else { return Ok(()) };
+        let bytes: Vec<u8> = chunk.read().unwrap().data().iter().flat_map(|height| height.to_le_bytes()).collect();
+        Serial::write_file_and_path_checked(Self::chunk_path(directory, chunk_key), &bytes)
+    }
+
+    /// Replaces any chunk already loaded. Returns false if the chunk was never saved.
+    pub fn load_chunk<S: AsRef<str>>(&mut self, directory: S, chunk_key: IVec2) -> SerialResult<bool> {
+        let path = Self::chunk_path(directory, chunk_key);
+        let bytes = match Serial::get_bytes_from_path_checked(&path) {
+            Ok(bytes) => bytes,
+            Err(error) if error.is_not_found() => { return Ok(false); }
+            Err(error) => { return Err(error); }
+        };
+
+        if bytes.len() != CHUNK_2D_SIZE * 4 {
+            return Err(SerialError::Corrupt { path, message: format!("chunk has {} bytes, expected {}", bytes.len(), CHUNK_2D_SIZE * 4) });
+        }
+
+        let mut chunk = HeightmapChunk::default();
+        for (height, bytes) in chunk.data_mut().iter_mut().zip(bytes.chunks_exact(4)) { *height = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]); }
+        self.insert_chunk(chunk_key, chunk);
+        Ok(true)
+    }
+
     pub fn get_value_at_coord(&self, coord: IVec2) -> f32 {
         let key = coord & !CHUNK_2D_MASK;
         if let Some(chunk) = self.chunks.get(&key) {
@@ -60,18 +103,46 @@ impl HeightmapRoot {
             self.chunks.insert(key, Arc::new(RwLock::new(chunk)));
         }
     }
+}
 
-    // pub fn modify_values_at_pos(&mut self, modifier: f32, pos: Vec2) {
+impl StreamedRoot for HeightmapRoot {
+    type Key = IVec2;
+    type Changes = HeightmapRootChanges;
 
-    // }
+    fn chunk_keys_in_radius(local_pos: Vec3, radius: f32) -> Vec<IVec2> {
+        let min = ((local_pos.xz() - radius) / CHUNK_2D_DIM as f32).floor().as_ivec2();
+        let max = ((local_pos.xz() + radius) / CHUNK_2D_DIM as f32).floor().as_ivec2();
 
-    // pub fn modify_values_in_radius(&mut self, modifier: f32, origin: Vec2, radius: f32) {
+        let mut keys = vec![];
+        for y in min.y..=max.y { for x in min.x..=max.x {
+            let key = IVec2::new(x, y) * CHUNK_2D_DIM as i32;
+            if Self::chunk_distance(key, local_pos) <= radius { keys.push(key); }
+        }}
 
-    // }
+        keys
+    }
 
-    // pub fn modify_values_with_radial_falloff(&mut self, modifier: f32, origin: Vec2, radius: f32) {
+    /// Only along x & z.
+    fn chunk_distance(key: IVec2, local_pos: Vec3) -> f32 {
+        (key.as_vec2() + CHUNK_2D_DIM as f32 * 0.5).distance(local_pos.xz())
+    }
 
-    // }
+    fn chunk_key_from_pos(local_pos: Vec3) -> IVec2 {
+        (local_pos.xz() / CHUNK_2D_DIM as f32).floor().as_ivec2() * CHUNK_2D_DIM as i32
+    }
+
+    fn chunk_keys(&self) -> Vec<IVec2> { self.chunks.keys().copied().collect() }
+    fn has_chunk(&self, key: IVec2) -> bool { self.chunks.contains_key(&key) }
+    fn remove_chunk(&mut self, key: IVec2) { HeightmapRoot::remove_chunk(self, key); }
+
+    /// Every neighbour either stitches its mesh to this one or takes normals from its edge.
+    fn mark_chunk_changes(changes: &mut HeightmapRootChanges, key: IVec2) {
+        let key = key & !CHUNK_2D_MASK;
+        for z in -1..=1 { for x in -1..=1 { changes.0.insert(key + IVec2::new(x, z) * CHUNK_2D_DIM as i32); }}
+    }
+
+    fn save_chunk(&self, directory: &str, key: IVec2) -> SerialResult<()> { HeightmapRoot::save_chunk(self, directory, key) }
+    fn load_chunk(&mut self, directory: &str, key: IVec2) -> SerialResult<bool> { HeightmapRoot::load_chunk(self, directory, key) }
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
@@ -110,8 +181,8 @@ impl HeightmapChunk {
     }
 
     pub fn modify_value_at_coord_with_range(&mut self, coord: IVec2, modifier: f32, min: f32, max: f32) {
-        let mut value = self.data[Self::value_index_from_global_coord(coord)];
-        if value + modifier > max { value = max; } else if value + modifier < min { value = min; } else { value += modifier; }
+        let value = &mut self.data[Self::value_index_from_global_coord(coord)];
+        *value = (*value + modifier).clamp(min, max);
     }
 }
 
@@ -122,20 +193,19 @@ impl HeightmapChunk {
 pub struct HeightmapRootChanges(HashSet<IVec2>);
 
 impl HeightmapRootChanges {
+    /// Also marks neighbours whose meshes use the height at `coord`, either stitching to it or taking normals from it.
     pub fn mark_change(&mut self, coord: IVec2) {
         let chunk_coord = coord & !CHUNK_2D_MASK;
-        self.0.insert(chunk_coord);
-
         let local_coord = HeightmapChunk::local_coord_from_global_coord(coord);
-        if local_coord.x == 0 {
-            self.0.insert(IVec2::new(chunk_coord.x - CHUNK_2D_DIM as i32, chunk_coord.y));
-            if local_coord.y == 0 {
-                self.0.insert(IVec2::new(chunk_coord.x - CHUNK_2D_DIM as i32, chunk_coord.y - CHUNK_2D_DIM as i32));
-                self.0.insert(IVec2::new(chunk_coord.x, chunk_coord.y - CHUNK_2D_DIM as i32));
-            }
-        } else if local_coord.y == 0 {
-            self.0.insert(IVec2::new(chunk_coord.x, chunk_coord.y - CHUNK_2D_DIM as i32));
-        }
+
+        // Chunks to the left & back stitch to the first column & row and take normals from the second, and ones to the right & front take normals from the last
+        let range = |local: u32| -> (i32, i32) { (if local <= 1 { -1 } else { 0 }, if local == CHUNK_2D_DIM - 1 { 1 } else { 0 }) };
+        let (min_x, max_x) = range(local_coord.x);
+        let (min_y, max_y) = range(local_coord.y);
+
+        for y in min_y..=max_y { for x in min_x..=max_x {
+            self.0.insert(chunk_coord + IVec2::new(x, y) * CHUNK_2D_DIM as i32);
+        }}
     }
 
     pub fn clear(&mut self) { self.0.clear() }
@@ -148,10 +218,81 @@ impl HeightmapRootChanges {
 /// You must mark any changes made using [HeightmapRootChanges], or the mesh will not update.
 /// 
 /// If a [Handle<StandardMaterial>] is on the entity, it will be used as the material for the mesh.
-#[derive(Component, Default, Reflect)]
+///
+/// Chunks further from every [Player]'s main camera are meshed with fewer verts, recorded in a [LevelOfDetail] on each mesh.
+/// Colliders stay at full detail near any [RigidBody] that is not fixed.
+#[derive(Component, Reflect)]
 #[reflect(Component)]
 pub struct HeightmapRootMesher {
+    /// Chunks with their center within `lod_distances[n]` of the closest camera use level of detail `n`, with a vert every `2^n` heights.
+    /// Chunks beyond every distance use the next level, and levels are capped so there is at least one quad per chunk.
+    pub lod_distances: Vec<f32>,
+    /// Depth of the skirts hiding cracks between chunks with different levels of detail
+    pub skirt_depth: f32,
+    /// Chunks with their center within this of a physics body get full detail colliders
+    pub collider_radius: f32,
     meshes: HashMap<IVec2, Entity>,
+    /// Level of detail & whether the collider is full detail, for each chunk as of its last mesh
+    lods: HashMap<IVec2, (u8, bool)>,
+}
+
+impl Default for HeightmapRootMesher {
+    fn default() -> Self {
+        Self {
+            lod_distances: vec![48.0, 96.0, 192.0],
+            skirt_depth: 2.0,
+            collider_radius: 32.0,
+            meshes: HashMap::default(),
+            lods: HashMap::default(),
+        }
+    }
+}
+
+impl HeightmapRootMesher {
+    pub fn max_lod() -> u8 { CHUNK_2D_LOG2DIM as u8 }
+
+    /// Level of detail for a chunk with its center `distance` from the closest camera.
+    pub fn lod_from_distance(&self, distance: f32) -> u8 {
+        let lod = self.lod_distances.iter().position(|lod_distance| distance <= *lod_distance).unwrap_or(self.lod_distances.len());
+        (lod as u8).min(Self::max_lod())
+    }
+}
+
+/// Marks chunks whose level of detail or collider detail should change.
+fn sys_update_heightmap_lods(
+    mut heightmap_query: Query<(&HeightmapRoot, &mut HeightmapRootMesher, &mut HeightmapRootChanges, Option<&GlobalTransform>)>,
+    player_query: Query<&PlayerMainCameraRef, With<Player>>,
+    body_query: Query<(&GlobalTransform, &RigidBody)>,
+    transform_query: Query<&GlobalTransform>,
+) {
+    let camera_positions: Vec<Vec3> = player_query.iter()
+        .filter_map(|camera_ref| camera_ref.try_get().and_then(|camera| transform_query.get(camera).ok()))
+        .map(|transform| transform.translation())
+        .collect();
+    let body_positions: Vec<Vec3> = body_query.iter()
+        .filter(|(_, body)| **body != RigidBody::Fixed)
+        .map(|(transform, _)| transform.translation())
+        .collect();
+
+    for (root, mut root_mesher, mut root_changes, root_transform) in heightmap_query.iter_mut() {
+        let inverse = root_transform.map(|transform| transform.affine().inverse()).unwrap_or_default();
+        let camera_positions: Vec<Vec3> = camera_positions.iter().map(|pos| inverse.transform_point3(*pos)).collect();
+        let body_positions: Vec<Vec3> = body_positions.iter().map(|pos| inverse.transform_point3(*pos)).collect();
+        let nearest_distance = |key: IVec2, positions: &[Vec3]| positions.iter().map(|pos| HeightmapRoot::chunk_distance(key, *pos)).fold(f32::INFINITY, f32::min);
+
+        root_mesher.lods.retain(|key, _| root.chunks.contains_key(key));
+        for key in root.chunks.keys().copied() {
+            // Full detail without cameras, such as on servers
+            let lod = if camera_positions.is_empty() { 0 } else { root_mesher.lod_from_distance(nearest_distance(key, &camera_positions)) };
+            let is_collider_full = nearest_distance(key, &body_positions) <= root_mesher.collider_radius;
+
+            if root_mesher.lods.get(&key).is_some_and(|current| *current == (lod, is_collider_full)) { continue; }
+            root_mesher.lods.insert(key, (lod, is_collider_full));
+
+            // Neighbours hang skirts based on their own detail, so only this chunk needs remeshing
+            root_changes.0.insert(key);
+        }
+    }
 }
 
 fn sys_update_heightmap_meshes(
@@ -164,16 +305,24 @@ fn sys_update_heightmap_meshes(
     for (root_entity, root, mut root_mesher, mut root_changes) in heightmap_query.iter_mut() {
         let mut new_mesh_entities = vec![];
         for key in root_changes.iter() {
-            let Some(new_mesh) = try_get_heightmap_mesh(key, root) else { continue };
+            let (lod, is_collider_full) = root_mesher.lods.get(&key).copied().unwrap_or((0, true));
+            let skirt_depth = root_mesher.skirt_depth;
+            let Some((new_mesh, collider)) = try_get_heightmap_mesh(key, root, lod, is_collider_full, skirt_depth) else {
+                if let Some(old_mesh_entity) = root_mesher.meshes.remove(&key) { commands.entity(old_mesh_entity).despawn_recursive(); }
+                continue;
+            };
 
             if let Some(old_mesh_entity) = root_mesher.meshes.get(&key) { commands.entity(*old_mesh_entity).despawn_recursive(); }
 
-            let mesh = meshes.add(new_mesh.clone());
+            let mesh = meshes.add(new_mesh);
             let material = if let Ok(material) = material_query.get(root_entity) { material.clone() } else { materials.add(StandardMaterial { base_color: Color::rgb(0.3, 0.9, 0.6), perceptual_roughness: 0.9, ..default() }) };
             let transform = Transform::from_translation(Vec3::new(key.x as f32, 0.0, key.y as f32));
-            let new_mesh_entity = commands.spawn(PbrBundle { mesh, material, transform, ..default() })
-                .insert(Collider::from_bevy_mesh(&new_mesh, &ComputedColliderShape::TriMesh).unwrap())
-                .id();
+            let mut level_of_detail = LevelOfDetail::default();
+            level_of_detail.set(lod);
+
+            let mut new_mesh_commands = commands.spawn((PbrBundle { mesh, material, transform, ..default() }, level_of_detail));
+            if let Some(collider) = collider { new_mesh_commands.insert(collider); }
+            let new_mesh_entity = new_mesh_commands.id();
 
             root_mesher.meshes.insert(key, new_mesh_entity);
             new_mesh_entities.push(new_mesh_entity);
@@ -185,33 +334,112 @@ fn sys_update_heightmap_meshes(
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Mesh at `lod`, with skirts if it is not full detail, & a collider without skirts.
 fn try_get_heightmap_mesh(
     key: IVec2,
     root: &HeightmapRoot,
-) -> Option<Mesh> {
-    let chunk = if let Some(chunk) = root.chunk_from_coord(key) { chunk.read().unwrap() } else { return None };
-
-    let mut neighbor_flags: u32 = 0;
-    let chunk_r = if let Some(chunk_r) = root.chunk_from_coord(IVec2::new(key.x + CHUNK_2D_DIM as i32, key.y)) { neighbor_flags |= 1; Some(chunk_r.read().unwrap()) } else { None };
-    let chunk_f = if let Some(chunk_f) = root.chunk_from_coord(IVec2::new(key.x, key.y + CHUNK_2D_DIM as i32)) { neighbor_flags |= 2; Some(chunk_f.read().unwrap()) } else { None };
-    let chunk_rf = if let Some(chunk_rf) = root.chunk_from_coord(IVec2::new(key.x + CHUNK_2D_DIM as i32, key.y + CHUNK_2D_DIM as i32)) { neighbor_flags |= 4; Some(chunk_rf.read().unwrap()) } else { None };
-
-    Some(match neighbor_flags {
-        // R neighbor
-        1 => { MeshGen::from_square_heightmap_with_r_neighbor(chunk.data(), chunk_r.unwrap().data(), CHUNK_2D_DIM) },
-        // F neighbor
-        2 => { MeshGen::from_square_heightmap_with_f_neighbor(chunk.data(), chunk_f.unwrap().data(), CHUNK_2D_DIM) },
-        // R & F neighbors
-        3 => { MeshGen::from_square_heightmap_with_r_f_neighbors(chunk.data(), chunk_r.unwrap().data(), chunk_f.unwrap().data(), CHUNK_2D_DIM) }
-        // Only RF neighbor: impossible case which is ignored
-        4 => { MeshGen::from_square_heightmap(chunk.data(), CHUNK_2D_DIM) }
-        // TODO: R & RF neighbors
-        5 => { MeshGen::from_square_heightmap(chunk.data(), CHUNK_2D_DIM) }
-        // TODO: F & RF neighbors
-        6 => { MeshGen::from_square_heightmap(chunk.data(), CHUNK_2D_DIM) }
-        // R & F & RF neighbors
-        7 => { MeshGen::from_square_heightmap_with_r_f_rf_neighbors(chunk.data(), chunk_r.unwrap().data(), chunk_f.unwrap().data(), chunk_rf.unwrap().data(), CHUNK_2D_DIM) }
-        // No neighbors
-        _ => { MeshGen::from_square_heightmap(chunk.data(), CHUNK_2D_DIM) }
-    })
-}
\ No newline at end of file
+    lod: u8,
+    is_collider_full: bool,
+    skirt_depth: f32,
+) -> Option<(Mesh, Option<Collider>)> {
+    root.chunk_from_coord(key)?;
+
+    let chunks: Vec<Option<RwLockReadGuard<HeightmapChunk>>> = (-1..=1).flat_map(|z| (-1..=1).map(move |x| IVec2::new(x, z)))
+        .map(|offset| root.chunk_from_coord(key + offset * CHUNK_2D_DIM as i32).map(|chunk| chunk.read().unwrap()))
+        .collect();
+    let neighborhood: [Option<&[f32]>; 9] = std::array::from_fn(|index| chunks[index].as_ref().map(|chunk| chunk.data().as_slice()));
+
+    // Full detail chunks still get skirts if a neighbour could be at a lower detail
+    let step = 1 << lod;
+    let mesh_data = MeshGen::from_heightmap_neighborhood(&neighborhood, CHUNK_2D_DIM, step, skirt_depth);
+    if mesh_data.indices.is_empty() { return None; }
+
+    let collider_step = if is_collider_full { 1 } else { step };
+    let collider = MeshGen::from_heightmap_neighborhood(&neighborhood, CHUNK_2D_DIM, collider_step, 0.0).trimesh_collider();
+    Some((mesh_data.mesh(), collider))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bumpy_chunk() -> HeightmapChunk {
+        let mut chunk = HeightmapChunk::default();
+        for (index, height) in chunk.data_mut().iter_mut().enumerate() { *height = (index as f32 * 0.37).sin() * 3.0; }
+        chunk
+    }
+
+    fn changed_keys(world: &World, root: Entity) -> Vec<IVec2> {
+        let mut keys: Vec<IVec2> = world.get::<HeightmapRootChanges>(root).unwrap().iter().collect();
+        keys.sort_by_key(|key| (key.x, key.y));
+        keys
+    }
+
+    #[test]
+    fn lod_from_distance() {
+        let mesher = HeightmapRootMesher::default();
+        for (distance, lod) in [(0.0, 0), (48.0, 0), (48.5, 1), (96.0, 1), (150.0, 2), (1000.0, 3)] {
+            assert_eq!(mesher.lod_from_distance(distance), lod, "{distance}");
+        }
+
+        let many = HeightmapRootMesher { lod_distances: (1..20).map(|lod| lod as f32 * 10.0).collect(), ..default() };
+        assert_eq!(many.lod_from_distance(1000.0), HeightmapRootMesher::max_lod());
+    }
+
+    #[test]
+    fn lods_follow_cameras_and_colliders_follow_bodies() {
+        let dim = CHUNK_2D_DIM as i32;
+        let (near_key, far_key) = (IVec2::ZERO, IVec2::new(dim * 20, 0));
+        let far_center = Vec3::new(far_key.x as f32 + dim as f32 * 0.5, 0.0, dim as f32 * 0.5);
+
+        let mut world = World::new();
+        let mut root = HeightmapRoot::default();
+        root.insert_chunk(near_key, bumpy_chunk());
+        root.insert_chunk(far_key, bumpy_chunk());
+        let root = world.spawn((root, HeightmapRootMesher::default(), HeightmapRootChanges::default())).id();
+
+        let camera = world.spawn(GlobalTransform::from_translation(Vec3::new(8.0, 10.0, 8.0))).id();
+        world.spawn((Player, PlayerMainCameraRef::new(Some(camera))));
+        world.spawn((GlobalTransform::from_translation(Vec3::new(8.0, 0.0, 8.0)), RigidBody::Fixed));
+        let body = world.spawn((GlobalTransform::from_translation(far_center), RigidBody::Dynamic)).id();
+
+        let mut schedule = Schedule::default();
+        schedule.add_systems(sys_update_heightmap_lods);
+        schedule.run(&mut world);
+
+        // Full detail near the camera, & a full detail collider only under the moving body
+        let lods = world.get::<HeightmapRootMesher>(root).unwrap().lods.clone();
+        assert_eq!(lods[&near_key], (0, false));
+        assert_eq!(lods[&far_key], (3, true));
+        assert_eq!(changed_keys(&world, root), vec![near_key, far_key]);
+
+        // Nothing is remeshed until the detail changes
+        world.get_mut::<HeightmapRootChanges>(root).unwrap().clear();
+        schedule.run(&mut world);
+        assert!(changed_keys(&world, root).is_empty());
+
+        *world.get_mut::<GlobalTransform>(body).unwrap() = GlobalTransform::from_translation(Vec3::new(8.0, 0.0, 8.0));
+        schedule.run(&mut world);
+        let lods = world.get::<HeightmapRootMesher>(root).unwrap().lods.clone();
+        assert_eq!(lods[&near_key], (0, true));
+        assert_eq!(lods[&far_key], (3, false));
+        assert_eq!(changed_keys(&world, root), vec![near_key, far_key]);
+    }
+
+    #[test]
+    fn colliders_stay_full_detail_near_bodies() {
+        // With the neighbours that the far edges stitch to
+        let mut root = HeightmapRoot::default();
+        for key in [IVec2::ZERO, IVec2::X, IVec2::Y, IVec2::ONE] { root.insert_chunk(key * CHUNK_2D_DIM as i32, bumpy_chunk()); }
+        let triangle_count = |collider: Option<Collider>| collider.unwrap().as_trimesh().unwrap().num_triangles();
+        let full_count = (CHUNK_2D_DIM * CHUNK_2D_DIM * 2) as usize;
+
+        let (_, full) = try_get_heightmap_mesh(IVec2::ZERO, &root, 2, true, 2.0).unwrap();
+        assert_eq!(triangle_count(full), full_count);
+
+        let (_, coarse) = try_get_heightmap_mesh(IVec2::ZERO, &root, 2, false, 2.0).unwrap();
+        assert_eq!(triangle_count(coarse), full_count / 16);
+
+        assert!(try_get_heightmap_mesh(IVec2::NEG_ONE * CHUNK_2D_DIM as i32, &root, 0, true, 2.0).is_none());
+    }
+}
@@ -1,9 +1,17 @@
 use crate::*;
 
+mod heightfield;
+pub use heightfield::*;
 mod heightmap;
 pub use heightmap::*;
 mod overworld;
 pub use overworld::*;
+mod sculpt;
+pub use sculpt::*;
+mod streaming;
+pub use streaming::*;
+mod terrain;
+pub use terrain::*;
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 /// 0. Left
@@ -54,9 +62,13 @@ const CHUNK_2D_MASK: i32 = CHUNK_2D_DIM as i32 - 1;
 pub struct TankLevelPlugin;
 impl Plugin for TankLevelPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins((
+        app.register_type::<ChunkLoader>()
+            .register_type::<ChunkStreamer>()
+            .register_type::<ChunkSaver>()
+            .add_plugins((
                 TankLevelHeightmapPlugin,
                 TankLevelOverworldPlugin,
+                TankLevelTerrainPlugin,
             ));
     }
 }
\ No newline at end of file
@@ -0,0 +1,323 @@
+use crate::*;
+use crate::level::{GRID_2D_DIAGONALS, GRID_2D_DIRECTIONS};
+
+use noise::{Perlin, NoiseFn};
+use bevy::utils::HashSet;
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// How a [HeightmapBrush] fades from its center, where it has full strength, to its radius, where it has none.
+#[derive(Clone, Copy, PartialEq, Debug, Default, Reflect)]
+pub enum HeightmapBrushFalloff {
+    /// Full strength up to the radius
+    Constant,
+    Linear,
+    /// Smoothstep, flat at the center & the edge
+    #[default]
+    Smooth,
+    /// Quarter circle, staying strong until close to the edge
+    Sphere,
+}
+
+impl HeightmapBrushFalloff {
+    /// `distance` is 0 at the center & 1 at the radius.
+    pub fn weight(&self, distance: f32) -> f32 {
+        let t = (1.0 - distance).clamp(0.0, 1.0);
+        match self {
+            Self::Constant => { if distance <= 1.0 { 1.0 } else { 0.0 } }
+            Self::Linear => { t }
+            Self::Smooth => { t * t * (3.0 - 2.0 * t) }
+            Self::Sphere => { (1.0 - (1.0 - t) * (1.0 - t)).sqrt() }
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Reflect)]
+pub enum HeightmapBrushOp {
+    /// Adds `strength` at the center
+    Raise,
+    /// Subtracts `strength` at the center
+    Lower,
+    /// Moves heights towards the average of their 8 neighbours, `strength` of the way at the center
+    Smooth,
+    /// Moves heights towards `height`, `strength` of the way at the center
+    Flatten(f32),
+    /// Adds perlin noise from -`strength` to `strength` at the center, with features roughly `scale` wide
+    Noise { seed: u32, scale: f32 },
+    /// Sets every height with any weight to `height`, ignoring strength & falloff
+    SetHeight(f32),
+}
+
+/// A single stamp of a sculpting operation on a [HeightmapRoot], see [HeightmapRoot::apply_brush].
+#[derive(Clone, Copy, PartialEq, Debug, Reflect)]
+pub struct HeightmapBrush {
+    pub op: HeightmapBrushOp,
+    pub radius: f32,
+    pub falloff: HeightmapBrushFalloff,
+    pub strength: f32,
+}
+
+impl Default for HeightmapBrush {
+    fn default() -> Self { Self { op: HeightmapBrushOp::Raise, radius: 4.0, falloff: HeightmapBrushFalloff::default(), strength: 0.25 } }
+}
+
+impl HeightmapBrush {
+    pub fn new(op: HeightmapBrushOp, radius: f32, falloff: HeightmapBrushFalloff, strength: f32) -> Self {
+        Self { op, radius, falloff, strength }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Heights from before an edit. Apply it to revert the edit.
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct HeightmapUndo {
+    values: Vec<(IVec2, f32)>,
+}
+
+impl HeightmapUndo {
+    #[inline] pub fn values(&self) -> &[(IVec2, f32)] { &self.values }
+    #[inline] pub fn is_empty(&self) -> bool { self.values.is_empty() }
+
+    /// Adds the values of a later edit, so both are reverted together, such as for every stamp of one brush stroke.
+    /// Values already recorded are kept, as they are older.
+    pub fn merge(&mut self, later: HeightmapUndo) {
+        let recorded: HashSet<IVec2> = self.values.iter().map(|(coord, _)| *coord).collect();
+        self.values.extend(later.values.into_iter().filter(|(coord, _)| !recorded.contains(coord)));
+    }
+
+    /// Restores the recorded heights, returning a record that redoes the edit. Heights in chunks that are no longer loaded are skipped.
+    pub fn apply(&self, root: &mut HeightmapRoot, changes: &mut HeightmapRootChanges) -> HeightmapUndo {
+        let mut redo = HeightmapUndo::default();
+        for (coord, value) in self.values.iter() {
+            let Some(chunk) = root.chunk_from_coord(*coord) else { continue };
+            let mut chunk = chunk.write().unwrap();
+
+            redo.values.push((*coord, chunk.get_value_at_coord(*coord)));
+            chunk.set_value_at_coord(*coord, *value);
+            changes.mark_change(*coord);
+        }
+
+        redo
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+impl HeightmapRoot {
+    /// Stamps `brush` centered on `center`, in the root's local x & z. Heights in missing chunks are left alone.
+    ///
+    /// Every changed height is marked in `changes`, including in the chunks whose meshes stitch to it.
+    pub fn apply_brush(&mut self, changes: &mut HeightmapRootChanges, brush: &HeightmapBrush, center: Vec2) -> HeightmapUndo {
+        let radius = brush.radius.max(0.0);
+        let min = (center - radius).ceil().as_ivec2();
+        let max = (center + radius).floor().as_ivec2();
+        let perlin = match brush.op { HeightmapBrushOp::Noise { seed, .. } => Some(Perlin::new(seed)), _ => None };
+
+        // Calculated before writing, so smoothing reads the same heights wherever it starts
+        let mut new_values = vec![];
+        for y in min.y..=max.y { for x in min.x..=max.x {
+            let coord = IVec2::new(x, y);
+            if self.chunk_from_coord(coord).is_none() { continue; }
+
+            let distance = if radius > 0.0 { coord.as_vec2().distance(center) / radius } else { 0.0 };
+            if distance > 1.0 { continue; }
+
+            let weight = brush.falloff.weight(distance);
+            if weight <= 0.0 { continue; }
+
+            let old_value = self.get_value_at_coord(coord);
+            let amount = (brush.strength * weight).clamp(0.0, 1.0);
+            let new_value = match brush.op {
+                HeightmapBrushOp::Raise => { old_value + brush.strength * weight }
+                HeightmapBrushOp::Lower => { old_value - brush.strength * weight }
+                HeightmapBrushOp::Smooth => { Math::lerp(old_value, self.neighbor_average(coord), amount) }
+                HeightmapBrushOp::Flatten(height) => { Math::lerp(old_value, height, amount) }
+                HeightmapBrushOp::Noise { scale, .. } => {
+                    let sample = coord.as_dvec2() / scale.max(0.0001) as f64;
+                    old_value + perlin.as_ref().unwrap().get([sample.x, sample.y]) as f32 * brush.strength * weight
+                }
+                HeightmapBrushOp::SetHeight(height) => { height }
+            };
+
+            if new_value != old_value { new_values.push((coord, old_value, new_value)); }
+        }}
+
+        let mut undo = HeightmapUndo::default();
+        for (coord, old_value, new_value) in new_values {
+            self.set_value_at_coord(coord, new_value);
+            changes.mark_change(coord);
+            undo.values.push((coord, old_value));
+        }
+
+        undo
+    }
+
+    /// Average of the 8 heights around `coord`, skipping missing chunks.
+    fn neighbor_average(&self, coord: IVec2) -> f32 {
+        let mut total = 0.0;
+        let mut count = 0;
+        for offset in GRID_2D_DIRECTIONS.iter().chain(GRID_2D_DIAGONALS.iter()) {
+            let neighbor = coord + *offset;
+            if self.chunk_from_coord(neighbor).is_none() { continue; }
+            total += self.get_value_at_coord(neighbor);
+            count += 1;
+        }
+
+        if count == 0 { self.get_value_at_coord(coord) } else { total / count as f32 }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FALLOFFS: [HeightmapBrushFalloff; 4] = [
+        HeightmapBrushFalloff::Constant,
+        HeightmapBrushFalloff::Linear,
+        HeightmapBrushFalloff::Smooth,
+        HeightmapBrushFalloff::Sphere,
+    ];
+
+    /// Chunks 0 & 1 along x, flat at 0
+    fn root() -> HeightmapRoot {
+        let mut root = HeightmapRoot::default();
+        root.insert_chunk(IVec2::ZERO, HeightmapChunk::default());
+        root.insert_chunk(IVec2::new(CHUNK_2D_DIM as i32, 0), HeightmapChunk::default());
+        root
+    }
+
+    fn heights(root: &HeightmapRoot) -> Vec<(IVec2, Vec<f32>)> {
+        let mut heights: Vec<(IVec2, Vec<f32>)> = root.chunks().iter().map(|(key, chunk)| (*key, chunk.read().unwrap().data().to_vec())).collect();
+        heights.sort_by_key(|(key, _)| (key.x, key.y));
+        heights
+    }
+
+    #[test]
+    fn falloff_weights() {
+        let sphere_half = 0.75f32.sqrt();
+        let cases: [(HeightmapBrushFalloff, [f32; 4]); 4] = [
+            // At distances 0, 0.5, 1 & past the radius
+            (HeightmapBrushFalloff::Constant, [1.0, 1.0, 1.0, 0.0]),
+            (HeightmapBrushFalloff::Linear, [1.0, 0.5, 0.0, 0.0]),
+            (HeightmapBrushFalloff::Smooth, [1.0, 0.5, 0.0, 0.0]),
+            (HeightmapBrushFalloff::Sphere, [1.0, sphere_half, 0.0, 0.0]),
+        ];
+
+        for (falloff, weights) in cases {
+            for (distance, weight) in [0.0, 0.5, 1.0, 1.5].into_iter().zip(weights) {
+                assert!((falloff.weight(distance) - weight).abs() < 0.0001, "{falloff:?} at {distance}");
+            }
+        }
+
+        for falloff in FALLOFFS {
+            for step in 0..10 {
+                let (near, far) = (step as f32 * 0.1, (step + 1) as f32 * 0.1);
+                assert!(falloff.weight(near) >= falloff.weight(far), "{falloff:?} at {near}");
+            }
+        }
+    }
+
+    #[test]
+    fn brush_falls_off_to_nothing_at_its_radius() {
+        let center = Vec2::new(8.0, 8.0);
+        for falloff in FALLOFFS {
+            let mut root = root();
+            let brush = HeightmapBrush::new(HeightmapBrushOp::Raise, 4.0, falloff, 1.0);
+            root.apply_brush(&mut HeightmapRootChanges::default(), &brush, center);
+
+            assert_eq!(root.get_value_at_coord(IVec2::new(8, 8)), 1.0, "{falloff:?}");
+            let edge = if falloff == HeightmapBrushFalloff::Constant { 1.0 } else { 0.0 };
+            for coord in [IVec2::new(12, 8), IVec2::new(8, 4), IVec2::new(4, 8)] {
+                assert_eq!(root.get_value_at_coord(coord), edge, "{falloff:?} at {coord}");
+            }
+            assert_eq!(root.get_value_at_coord(IVec2::new(13, 8)), 0.0, "{falloff:?}");
+            assert_eq!(root.get_value_at_coord(IVec2::new(11, 11)), 0.0, "{falloff:?}");
+        }
+
+        let mut root = root();
+        let brush = HeightmapBrush::new(HeightmapBrushOp::Lower, 4.0, HeightmapBrushFalloff::Linear, 1.0);
+        root.apply_brush(&mut HeightmapRootChanges::default(), &brush, center);
+        assert_eq!(root.get_value_at_coord(IVec2::new(10, 8)), -0.5);
+    }
+
+    #[test]
+    fn brush_ops() {
+        let mut root = root();
+        let mut changes = HeightmapRootChanges::default();
+        root.set_value_at_coord(IVec2::new(8, 8), 9.0);
+
+        let smooth = HeightmapBrush::new(HeightmapBrushOp::Smooth, 0.0, HeightmapBrushFalloff::Constant, 1.0);
+        root.apply_brush(&mut changes, &smooth, Vec2::new(8.0, 8.0));
+        assert_eq!(root.get_value_at_coord(IVec2::new(8, 8)), 0.0);
+
+        let flatten = HeightmapBrush::new(HeightmapBrushOp::Flatten(2.0), 2.0, HeightmapBrushFalloff::Constant, 0.5);
+        root.apply_brush(&mut changes, &flatten, Vec2::new(8.0, 8.0));
+        assert_eq!(root.get_value_at_coord(IVec2::new(9, 8)), 1.0);
+
+        // Strength & falloff don't matter
+        let set_height = HeightmapBrush::new(HeightmapBrushOp::SetHeight(5.0), 2.0, HeightmapBrushFalloff::Linear, 0.1);
+        root.apply_brush(&mut changes, &set_height, Vec2::new(8.0, 8.0));
+        assert_eq!(root.get_value_at_coord(IVec2::new(9, 8)), 5.0);
+        assert_eq!(root.get_value_at_coord(IVec2::new(10, 8)), 1.0);
+    }
+
+    #[test]
+    fn brush_skips_missing_chunks_and_marks_stitched_chunks() {
+        let mut root = root();
+        let mut changes = HeightmapRootChanges::default();
+        let dim = CHUNK_2D_DIM as i32;
+        let brush = HeightmapBrush::new(HeightmapBrushOp::Raise, 3.0, HeightmapBrushFalloff::Constant, 1.0);
+        let undo = root.apply_brush(&mut changes, &brush, Vec2::new(dim as f32, 0.0));
+
+        assert_eq!(root.chunks().len(), 2);
+        assert!(undo.values().iter().all(|(coord, _)| coord.y >= 0));
+        assert_eq!(root.get_value_at_coord(IVec2::new(dim - 1, 0)), 1.0);
+        assert_eq!(root.get_value_at_coord(IVec2::new(dim, 0)), 1.0);
+
+        // Chunks behind the edited edges stitch to them, loaded or not
+        let mut changed: Vec<IVec2> = changes.iter().collect();
+        changed.sort_by_key(|key| (key.x, key.y));
+        assert_eq!(changed, vec![IVec2::new(0, -dim), IVec2::ZERO, IVec2::new(dim, -dim), IVec2::new(dim, 0)]);
+    }
+
+    #[test]
+    fn undo_then_redo_restores_each_state() {
+        let mut root = root();
+        let mut changes = HeightmapRootChanges::default();
+        let original = heights(&root);
+
+        // One stroke across the chunk border, merged into one undo
+        let mut undo = HeightmapUndo::default();
+        let brushes = [
+            HeightmapBrush::new(HeightmapBrushOp::Raise, 5.0, HeightmapBrushFalloff::Smooth, 2.0),
+            HeightmapBrush::new(HeightmapBrushOp::Noise { seed: 3, scale: 4.0 }, 6.0, HeightmapBrushFalloff::Sphere, 1.0),
+            HeightmapBrush::new(HeightmapBrushOp::Smooth, 4.0, HeightmapBrushFalloff::Linear, 0.5),
+            HeightmapBrush::new(HeightmapBrushOp::Flatten(-1.0), 3.0, HeightmapBrushFalloff::Constant, 0.25),
+        ];
+        for (index, brush) in brushes.iter().enumerate() {
+            undo.merge(root.apply_brush(&mut changes, brush, Vec2::new(CHUNK_2D_DIM as f32 + index as f32 - 2.0, 6.0)));
+        }
+        let edited = heights(&root);
+        assert_ne!(edited, original);
+
+        let redo = undo.apply(&mut root, &mut changes);
+        assert_eq!(heights(&root), original);
+
+        let undo_again = redo.apply(&mut root, &mut changes);
+        assert_eq!(heights(&root), edited);
+
+        undo_again.apply(&mut root, &mut changes);
+        assert_eq!(heights(&root), original);
+    }
+
+    #[test]
+    fn undo_skips_unloaded_chunks() {
+        let mut root = root();
+        let mut changes = HeightmapRootChanges::default();
+        let brush = HeightmapBrush::new(HeightmapBrushOp::Raise, 3.0, HeightmapBrushFalloff::Constant, 1.0);
+        let undo = root.apply_brush(&mut changes, &brush, Vec2::new(CHUNK_2D_DIM as f32, 4.0));
+
+        root.remove_chunk(IVec2::new(CHUNK_2D_DIM as i32, 0));
+        let redo = undo.apply(&mut root, &mut changes);
+        assert!(redo.values().iter().all(|(coord, _)| coord.x < CHUNK_2D_DIM as i32));
+        assert!(root.chunks()[&IVec2::ZERO].read().unwrap().data().iter().all(|height| *height == 0.0));
+    }
+}
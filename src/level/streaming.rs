@@ -0,0 +1,205 @@
+use crate::*;
+
+use std::hash::Hash;
+use bevy::utils::{HashMap, HashSet};
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Streams chunks of every `R` with a [ChunkStreamer] around [ChunkLoader]s.
+pub struct ChunkStreamerPlugin<R: StreamedRoot> {
+    phantom_data: PhantomData<R>,
+}
+
+impl<R: StreamedRoot> Default for ChunkStreamerPlugin<R> {
+    fn default() -> Self { Self { phantom_data: PhantomData } }
+}
+
+impl<R: StreamedRoot> Plugin for ChunkStreamerPlugin<R> {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ChunkLoadEvent<R>>()
+            .add_event::<ChunkUnloadEvent<R>>()
+            .add_systems(Update, sys_update_chunk_streamers::<R>.run_if(in_state(AppState::Main)));
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// A root whose chunks can be streamed by a [ChunkStreamer].
+pub trait StreamedRoot: Component {
+    type Key: Copy + Eq + Hash + Send + Sync + std::fmt::Debug + 'static;
+    /// Component used to mark chunks for remeshing
+    type Changes: Component;
+
+    /// Keys of every chunk with its center within `radius` of `local_pos`, loaded or not.
+    fn chunk_keys_in_radius(local_pos: Vec3, radius: f32) -> Vec<Self::Key>;
+    /// From the center of the chunk at `key` to `local_pos`.
+    fn chunk_distance(key: Self::Key, local_pos: Vec3) -> f32;
+    /// Key of the chunk containing `local_pos`, loaded or not.
+    fn chunk_key_from_pos(local_pos: Vec3) -> Self::Key;
+
+    fn chunk_keys(&self) -> Vec<Self::Key>;
+    fn has_chunk(&self, key: Self::Key) -> bool;
+    fn remove_chunk(&mut self, key: Self::Key);
+
+    /// Marks the chunk & any neighbours whose meshes depend on it.
+    fn mark_chunk_changes(changes: &mut Self::Changes, key: Self::Key);
+
+    /// Does nothing if the chunk is not loaded.
+    fn save_chunk(&self, directory: &str, key: Self::Key) -> SerialResult<()>;
+    /// Returns false if the chunk was never saved.
+    fn load_chunk(&mut self, directory: &str, key: Self::Key) -> SerialResult<bool>;
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Marker component. Chunks are loaded around the [GlobalTransform] of entities with this, such as player cameras or controlled things.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct ChunkLoader;
+
+/// Put this on a root, and chunks near [ChunkLoader]s will be loaded & meshed, and the rest unloaded.
+///
+/// Chunks are restored by a [ChunkSaver] on the same entity if there is one. Otherwise, or if the chunk was never saved,
+/// a [ChunkLoadEvent] with `restored: false` is sent so the chunk can be generated.
+///
+/// Only chunks the streamer loaded are unloaded. Chunks created some other way, such as by editing, are kept until they have been
+/// within `load_radius` of a loader, and streamed out like any other chunk after that.
+///
+/// Roots are left alone while there are no loaders, such as before players spawn.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct ChunkStreamer {
+    /// Chunks with their center within this distance of a loader are loaded
+    pub load_radius: f32,
+    /// Chunks further than this from every loader are unloaded. Larger than `load_radius`, so chunks on the edge are not reloaded constantly.
+    pub unload_radius: f32,
+    /// Max chunks loaded, and max chunks unloaded, per frame. Closest chunks are loaded first & furthest are unloaded first.
+    pub chunks_per_frame: usize,
+}
+
+impl Default for ChunkStreamer {
+    fn default() -> Self { Self { load_radius: 128.0, unload_radius: 160.0, chunks_per_frame: 8 } }
+}
+
+impl ChunkStreamer {
+    pub fn new(load_radius: f32, unload_radius: f32, chunks_per_frame: usize) -> Self {
+        Self { load_radius, unload_radius: unload_radius.max(load_radius), chunks_per_frame }
+    }
+}
+
+/// Put this on a root with a [ChunkStreamer], and chunks are saved to `directory` before being unloaded & restored from it when loaded again.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct ChunkSaver {
+    pub directory: String,
+}
+
+impl ChunkSaver {
+    pub fn new<S: AsRef<str>>(directory: S) -> Self { Self { directory: directory.as_ref().to_owned() } }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Sent after a chunk is streamed in.
+#[derive(Event)]
+pub struct ChunkLoadEvent<R: StreamedRoot> {
+    pub root: Entity,
+    pub key: R::Key,
+    /// The chunk exists, either restored by the [ChunkSaver] or created before it was streamed in. Otherwise it should be generated.
+    pub restored: bool,
+}
+
+/// Sent after a chunk is streamed out, once it has been saved.
+#[derive(Event)]
+pub struct ChunkUnloadEvent<R: StreamedRoot> {
+    pub root: Entity,
+    pub key: R::Key,
+}
+
+/// What a [ChunkStreamer] has streamed in for one root, and the chunks within `load_radius` of its loaders.
+///
+/// Keys in radius are only found again when a loader enters another chunk, loaders come or go, or the radius changes,
+/// so they are measured from where each loader was then, up to a chunk from where it is now.
+struct ChunkStreamerState<R: StreamedRoot> {
+    streamed_keys: HashSet<R::Key>,
+    loader_keys: HashMap<Entity, R::Key>,
+    load_radius: f32,
+    keys_in_radius: HashSet<R::Key>,
+}
+
+impl<R: StreamedRoot> Default for ChunkStreamerState<R> {
+    fn default() -> Self {
+        Self { streamed_keys: HashSet::default(), loader_keys: HashMap::default(), load_radius: 0.0, keys_in_radius: HashSet::default() }
+    }
+}
+
+type StreamedRootQuery<'a, R> = (Entity, &'a mut R, &'a ChunkStreamer, Option<&'a GlobalTransform>, Option<&'a ChunkSaver>, Option<&'a mut <R as StreamedRoot>::Changes>);
+
+fn sys_update_chunk_streamers<R: StreamedRoot>(
+    loader_query: Query<(Entity, &GlobalTransform), With<ChunkLoader>>,
+    mut root_query: Query<StreamedRootQuery<R>>,
+    mut states: Local<HashMap<Entity, ChunkStreamerState<R>>>,
+    mut load_events: EventWriter<ChunkLoadEvent<R>>,
+    mut unload_events: EventWriter<ChunkUnloadEvent<R>>,
+) {
+    states.retain(|root_entity, _| root_query.contains(*root_entity));
+    if loader_query.is_empty() { return; }
+
+    for (root_entity, mut root, streamer, root_transform, saver, mut changes) in root_query.iter_mut() {
+        let inverse = root_transform.map(|transform| transform.affine().inverse()).unwrap_or_default();
+        let loaders: Vec<(Entity, Vec3)> = loader_query.iter().map(|(entity, transform)| (entity, inverse.transform_point3(transform.translation()))).collect();
+        let nearest_distance = |key: R::Key| loaders.iter().map(|(_, pos)| R::chunk_distance(key, *pos)).fold(f32::INFINITY, f32::min);
+        let state = states.entry(root_entity).or_default();
+
+        let loader_keys: HashMap<Entity, R::Key> = loaders.iter().map(|(entity, pos)| (*entity, R::chunk_key_from_pos(*pos))).collect();
+        if loader_keys != state.loader_keys || streamer.load_radius != state.load_radius {
+            state.keys_in_radius = loaders.iter().flat_map(|(_, pos)| R::chunk_keys_in_radius(*pos, streamer.load_radius)).collect();
+            state.loader_keys = loader_keys;
+            state.load_radius = streamer.load_radius;
+        }
+
+        let streamed_keys = &mut state.streamed_keys;
+
+        // Unload
+        let mut unload_keys: Vec<(R::Key, f32)> = streamed_keys.iter()
+            .map(|key| (*key, nearest_distance(*key)))
+            .filter(|(_, distance)| *distance > streamer.unload_radius)
+            .collect();
+        unload_keys.sort_by(|(_, distance_0), (_, distance_1)| distance_1.total_cmp(distance_0));
+
+        for (key, _) in unload_keys.into_iter().take(streamer.chunks_per_frame) {
+            if let Some(saver) = saver {
+                if let Err(error) = root.save_chunk(&saver.directory, key) {
+                    warn!("Failed to save chunk {key:?}, keeping it loaded: {error}");
+                    continue;
+                }
+            }
+
+            root.remove_chunk(key);
+            streamed_keys.remove(&key);
+            if let Some(changes) = changes.as_mut() { R::mark_chunk_changes(changes, key); }
+            unload_events.send(ChunkUnloadEvent { root: root_entity, key });
+        }
+
+        // Load
+        let mut load_keys: Vec<(R::Key, f32)> = state.keys_in_radius.iter()
+            .filter(|key| !streamed_keys.contains(*key))
+            .map(|key| (*key, nearest_distance(*key)))
+            // Loaders may have moved since keys in radius were found, so chunks that would be unloaded right away are skipped
+            .filter(|(_, distance)| *distance <= streamer.unload_radius)
+            .collect();
+        load_keys.sort_by(|(_, distance_0), (_, distance_1)| distance_0.total_cmp(distance_1));
+
+        for (key, _) in load_keys.into_iter().take(streamer.chunks_per_frame) {
+            let mut restored = root.has_chunk(key);
+            if !restored {
+                if let Some(saver) = saver {
+                    restored = match root.load_chunk(&saver.directory, key) {
+                        Ok(loaded) => loaded,
+                        Err(error) => { error!("Failed to load chunk {key:?}: {error}"); false }
+                    };
+                }
+            }
+
+            streamed_keys.insert(key);
+            if let Some(changes) = changes.as_mut() { R::mark_chunk_changes(changes, key); }
+            load_events.send(ChunkLoadEvent { root: root_entity, key, restored });
+        }
+    }
+}
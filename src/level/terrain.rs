@@ -0,0 +1,321 @@
+use crate::*;
+
+use noise::{Perlin, Simplex, Worley, NoiseFn, core::worley::ReturnType};
+use serde::{Deserialize, Serialize};
+use bevy::{math::DVec2, utils::HashMap};
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+pub struct TankLevelTerrainPlugin;
+impl Plugin for TankLevelTerrainPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<HeightmapGenerator>()
+            .add_plugins(DataAssetPlugin::<TerrainGraph>::new("terrains"))
+            .add_systems(Update, sys_generate_heightmap_chunks.run_if(in_state(AppState::Main)));
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// A node of a [TerrainGraph]. Inputs are indices of earlier nodes in `TerrainGraph::nodes`, so graphs can't loop.
+///
+/// Noise sources sample world coords, so the same graph & seed give the same height at a coord no matter which chunk asks.
+#[derive(Serialize, Deserialize, Clone, Debug, Reflect)]
+pub enum TerrainNode {
+    Constant(f32),
+    /// From -1 to 1. `seed` is added to the graph's seed, so the same source can be reused without lining up.
+    Perlin { frequency: f32, seed: u32 },
+    /// From -1 to 1.
+    Simplex { frequency: f32, seed: u32 },
+    /// Distance to the closest cell point, from -1 to 1.
+    Worley { frequency: f32, seed: u32 },
+    /// Octaves of `input`, each at `lacunarity` times the frequency & `persistence` times the amplitude of the last, normalized to its range.
+    Fbm { input: usize, octaves: u32, lacunarity: f32, persistence: f32 },
+    /// Like `Fbm`, but folding each octave into sharp ridges, from -1 to 1.
+    Ridged { input: usize, octaves: u32, lacunarity: f32, persistence: f32 },
+    /// Samples `input` at coords moved by `warp_x` & `warp_z`, times `strength`.
+    DomainWarp { input: usize, warp_x: usize, warp_z: usize, strength: f32 },
+    /// `input * scale + bias`
+    ScaleBias { input: usize, scale: f32, bias: f32 },
+    Add(Vec<usize>),
+    Multiply(Vec<usize>),
+    Min(Vec<usize>),
+    Max(Vec<usize>),
+    Clamp { input: usize, min: f32, max: f32 },
+    /// Linear between `(input, output)` points sorted by input, & flat past either end.
+    Curve { input: usize, points: Vec<(f32, f32)> },
+    /// Steps `height` apart. `smoothness` from 0, for flat steps with cliffs, to 1, for no steps.
+    Terrace { input: usize, height: f32, smoothness: f32 },
+    /// `a` where `mask` is 0 or below, `b` where it is 1 or above, & blended between.
+    Mask { mask: usize, a: usize, b: usize },
+}
+
+/// A graph of [TerrainNode]s giving the height at any world coord. Loaded from `assets/data/terrains/*.ron` through `DataAssets<TerrainGraph>`.
+#[derive(Serialize, Deserialize, Clone, Debug, Reflect)]
+pub struct TerrainGraph {
+    pub nodes: Vec<TerrainNode>,
+    /// Index of the node giving the height
+    pub output: usize,
+}
+
+impl Default for TerrainGraph {
+    fn default() -> Self { Self { nodes: vec![TerrainNode::Constant(0.0)], output: 0 } }
+}
+
+impl TerrainGraph {
+    /// Builds the noise sources for `seed`. Build once & reuse it for every chunk with the same seed.
+    pub fn sampler(&self, seed: u32) -> TerrainSampler {
+        let sources = self.nodes.iter().map(|node| {
+            match node {
+                TerrainNode::Perlin { seed: node_seed, .. } => { Some(TerrainNoise::Perlin(Perlin::new(seed.wrapping_add(*node_seed)))) }
+                TerrainNode::Simplex { seed: node_seed, .. } => { Some(TerrainNoise::Simplex(Simplex::new(seed.wrapping_add(*node_seed)))) }
+                TerrainNode::Worley { seed: node_seed, .. } => {
+                    Some(TerrainNoise::Worley(Worley::new(seed.wrapping_add(*node_seed)).set_return_type(ReturnType::Distance)))
+                }
+                _ => { None }
+            }
+        }).collect();
+
+        TerrainSampler { graph: self.clone(), sources }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+enum TerrainNoise {
+    Perlin(Perlin),
+    Simplex(Simplex),
+    Worley(Worley),
+}
+
+impl TerrainNoise {
+    fn get(&self, pos: DVec2) -> f64 {
+        match self {
+            Self::Perlin(noise) => { noise.get(pos.to_array()) }
+            Self::Simplex(noise) => { noise.get(pos.to_array()) }
+            Self::Worley(noise) => { noise.get(pos.to_array()) }
+        }
+    }
+}
+
+/// A [TerrainGraph] with its noise sources built for one seed, see [TerrainGraph::sampler].
+pub struct TerrainSampler {
+    graph: TerrainGraph,
+    sources: Vec<Option<TerrainNoise>>,
+}
+
+impl TerrainSampler {
+    /// Height at the world coord `pos`, along x & z.
+    pub fn sample(&self, pos: Vec2) -> f32 {
+        self.sample_node(self.graph.output, pos.as_dvec2(), usize::MAX) as f32
+    }
+
+    /// Every height of the [HeightmapChunk] at `chunk_key`. Chunks generated separately meet without seams.
+    pub fn generate_chunk(&self, chunk_key: IVec2) -> HeightmapChunk {
+        let chunk_key = chunk_key & !CHUNK_2D_MASK;
+        let mut chunk = HeightmapChunk::default();
+        for (index, height) in chunk.data_mut().iter_mut().enumerate() {
+            let local_coord = IVec2::new((index as u32 % CHUNK_2D_DIM) as i32, (index as u32 / CHUNK_2D_DIM) as i32);
+            *height = self.sample((chunk_key + local_coord).as_vec2());
+        }
+
+        chunk
+    }
+
+    /// Inputs that are not earlier than the node that uses them are 0, so broken graphs can't recurse forever.
+    fn sample_node(&self, index: usize, pos: DVec2, user: usize) -> f64 {
+        if index >= user { return 0.0; }
+        let Some(node) = self.graph.nodes.get(index) else { return 0.0 };
+        let input = |input: usize, pos: DVec2| self.sample_node(input, pos, index);
+
+        match node {
+            TerrainNode::Constant(value) => { *value as f64 }
+            TerrainNode::Perlin { frequency, .. } | TerrainNode::Simplex { frequency, .. } | TerrainNode::Worley { frequency, .. } => {
+                self.sources[index].as_ref().map_or(0.0, |noise| noise.get(pos * *frequency as f64))
+            }
+            TerrainNode::Fbm { input: fbm_input, octaves, lacunarity, persistence } => {
+                let (mut total, mut max, mut amplitude, mut frequency) = (0.0, 0.0, 1.0, 1.0);
+                for octave in 0..(*octaves).clamp(1, 16) {
+                    total += input(*fbm_input, pos * frequency + Self::octave_offset(octave)) * amplitude;
+                    max += amplitude;
+                    amplitude *= *persistence as f64;
+                    frequency *= *lacunarity as f64;
+                }
+                if max > 0.0 { total / max } else { 0.0 }
+            }
+            TerrainNode::Ridged { input: ridged_input, octaves, lacunarity, persistence } => {
+                let (mut total, mut max, mut amplitude, mut frequency) = (0.0, 0.0, 1.0, 1.0);
+                for octave in 0..(*octaves).clamp(1, 16) {
+                    let ridge = 1.0 - input(*ridged_input, pos * frequency + Self::octave_offset(octave)).abs();
+                    total += ridge * ridge * amplitude;
+                    max += amplitude;
+                    amplitude *= *persistence as f64;
+                    frequency *= *lacunarity as f64;
+                }
+                if max > 0.0 { total / max * 2.0 - 1.0 } else { 0.0 }
+            }
+            TerrainNode::DomainWarp { input: warp_input, warp_x, warp_z, strength } => {
+                let warp = DVec2::new(input(*warp_x, pos), input(*warp_z, pos)) * *strength as f64;
+                input(*warp_input, pos + warp)
+            }
+            TerrainNode::ScaleBias { input: scale_input, scale, bias } => { input(*scale_input, pos) * *scale as f64 + *bias as f64 }
+            TerrainNode::Add(inputs) => { inputs.iter().map(|i| input(*i, pos)).sum() }
+            TerrainNode::Multiply(inputs) => { inputs.iter().map(|i| input(*i, pos)).product() }
+            TerrainNode::Min(inputs) => { inputs.iter().map(|i| input(*i, pos)).reduce(f64::min).unwrap_or(0.0) }
+            TerrainNode::Max(inputs) => { inputs.iter().map(|i| input(*i, pos)).reduce(f64::max).unwrap_or(0.0) }
+            TerrainNode::Clamp { input: clamp_input, min, max } => { input(*clamp_input, pos).clamp(*min as f64, (*max).max(*min) as f64) }
+            TerrainNode::Curve { input: curve_input, points } => { Self::curve(points, input(*curve_input, pos) as f32) as f64 }
+            TerrainNode::Terrace { input: terrace_input, height, smoothness } => {
+                let value = input(*terrace_input, pos);
+                if *height <= 0.0 { return value; }
+
+                let height = *height as f64;
+                let step = (value / height).floor();
+                let t = value / height - step;
+
+                // Flat for most of the step, rising to the next over the last `smoothness` of it
+                let smoothness = (*smoothness as f64).clamp(0.0001, 1.0);
+                let rise = ((t - (1.0 - smoothness)) / smoothness).clamp(0.0, 1.0);
+                (step + rise * rise * (3.0 - 2.0 * rise)) * height
+            }
+            TerrainNode::Mask { mask, a, b } => {
+                let mask = input(*mask, pos).clamp(0.0, 1.0);
+                let (a, b) = (input(*a, pos), input(*b, pos));
+                a + (b - a) * mask
+            }
+        }
+    }
+
+    /// Moves each octave to a different part of its input, so octaves don't line up at the origin.
+    fn octave_offset(octave: u32) -> DVec2 {
+        DVec2::new(octave as f64 * 173.31, octave as f64 * -271.73)
+    }
+
+    fn curve(points: &[(f32, f32)], value: f32) -> f32 {
+        let Some(first) = points.first() else { return value };
+        if value <= first.0 { return first.1; }
+
+        for pair in points.windows(2) {
+            let ((x_0, y_0), (x_1, y_1)) = (pair[0], pair[1]);
+            if value <= x_1 {
+                if x_1 <= x_0 { return y_1; }
+                return Math::lerp(y_0, y_1, Math::inverse_lerp(x_0, x_1, value));
+            }
+        }
+
+        points.last().unwrap().1
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Put this on a [HeightmapRoot] with a [ChunkStreamer], and chunks that weren't restored are generated from `graph`.
+///
+/// The seed is the [RngSeed] on the same entity, or 0 without one.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct HeightmapGenerator {
+    pub graph: DataAssetId<TerrainGraph>,
+}
+
+impl HeightmapGenerator {
+    pub fn new(graph: DataAssetId<TerrainGraph>) -> Self { Self { graph } }
+}
+
+fn sys_generate_heightmap_chunks(
+    mut load_events: EventReader<ChunkLoadEvent<HeightmapRoot>>,
+    mut root_query: Query<(&mut HeightmapRoot, &mut HeightmapRootChanges, &HeightmapGenerator, Option<&RngSeed>)>,
+    terrain_graphs: Res<DataAssets<TerrainGraph>>,
+) {
+    // Samplers are built once per root, and only if it has chunks to generate
+    let mut samplers = HashMap::<Entity, TerrainSampler>::default();
+
+    for event in load_events.read() {
+        if event.restored { continue; }
+        let Ok((mut root, mut root_changes, generator, seed)) = root_query.get_mut(event.root) else { continue };
+        let Some(graph) = terrain_graphs.try_get(generator.graph) else { continue };
+
+        let sampler = samplers.entry(event.root).or_insert_with(|| graph.sampler(seed.map_or(0, |seed| seed.get())));
+        root.insert_chunk(event.key, sampler.generate_chunk(event.key));
+        <HeightmapRoot as StreamedRoot>::mark_chunk_changes(&mut root_changes, event.key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph() -> TerrainGraph {
+        TerrainGraph {
+            nodes: vec![
+                TerrainNode::Perlin { frequency: 0.03, seed: 0 },
+                TerrainNode::Fbm { input: 0, octaves: 4, lacunarity: 2.0, persistence: 0.5 },
+                TerrainNode::Simplex { frequency: 0.05, seed: 7 },
+                TerrainNode::Simplex { frequency: 0.05, seed: 13 },
+                TerrainNode::DomainWarp { input: 1, warp_x: 2, warp_z: 3, strength: 8.0 },
+                TerrainNode::ScaleBias { input: 4, scale: 10.0, bias: 2.0 },
+            ],
+            output: 5,
+        }
+    }
+
+    fn height(chunk: &HeightmapChunk, x: i32, z: i32) -> f32 {
+        chunk.data()[(z as u32 * CHUNK_2D_DIM + x as u32) as usize]
+    }
+
+    /// Largest change in height between neighbours along `axis` inside `chunk`
+    fn max_step(chunk: &HeightmapChunk, axis: IVec2) -> f32 {
+        let last = CHUNK_2D_DIM as i32 - 1;
+        let mut max = 0.0f32;
+        for z in 0..=last - axis.y {
+            for x in 0..=last - axis.x {
+                max = max.max((height(chunk, x + axis.x, z + axis.y) - height(chunk, x, z)).abs());
+            }
+        }
+        max
+    }
+
+    #[test]
+    fn same_seed_and_key_give_identical_heights() {
+        let graph = graph();
+        for chunk_key in [IVec2::ZERO, IVec2::new(64, -32), IVec2::new(-96, 160)] {
+            let first = graph.sampler(42).generate_chunk(chunk_key);
+            let second = graph.sampler(42).generate_chunk(chunk_key);
+            assert!(first.data().iter().zip(second.data().iter()).all(|(a, b)| a.to_bits() == b.to_bits()), "{chunk_key}");
+        }
+
+        let other_seed = graph.sampler(43).generate_chunk(IVec2::ZERO);
+        assert_ne!(graph.sampler(42).generate_chunk(IVec2::ZERO).data(), other_seed.data());
+    }
+
+    #[test]
+    fn coords_inside_a_chunk_give_its_key() {
+        let sampler = graph().sampler(42);
+        let from_key = sampler.generate_chunk(IVec2::new(-32, 32));
+        let from_coord = sampler.generate_chunk(IVec2::new(-20, 45));
+        assert_eq!(from_key.data(), from_coord.data());
+    }
+
+    #[test]
+    fn adjacent_chunks_agree_on_shared_border() {
+        let sampler = graph().sampler(42);
+        let dim = CHUNK_2D_DIM as i32;
+        let last = dim - 1;
+
+        for (chunk_key, axis) in [(IVec2::ZERO, IVec2::X), (IVec2::new(-dim, 0), IVec2::X), (IVec2::new(0, -dim), IVec2::Y)] {
+            // Generated separately, each from its own key
+            let chunk = sampler.generate_chunk(chunk_key);
+            let neighbor = sampler.generate_chunk(chunk_key + axis * dim);
+
+            let step = max_step(&chunk, axis).max(max_step(&neighbor, axis));
+            for i in 0..dim {
+                let edge = if axis == IVec2::X { IVec2::new(last, i) } else { IVec2::new(i, last) };
+                let across = edge + axis - axis * dim;
+
+                // Each side of the border is what the other side would generate past its edge
+                assert_eq!(height(&chunk, edge.x, edge.y), sampler.sample((chunk_key + edge).as_vec2()));
+                assert_eq!(height(&neighbor, across.x, across.y), sampler.sample((chunk_key + edge + axis).as_vec2()));
+
+                // & the border is no steeper than inside either chunk
+                let border_step = (height(&neighbor, across.x, across.y) - height(&chunk, edge.x, edge.y)).abs();
+                assert!(border_step <= step * 1.5 + 0.0001, "{chunk_key} {i}: {border_step} > {step}");
+            }
+        }
+    }
+}
@@ -41,6 +41,8 @@ mod player;
 pub use player::*;
 mod render;
 pub use render::*;
+mod save;
+pub use save::*;
 mod state;
 pub use state::*;
 mod thing;
@@ -114,6 +116,7 @@ impl Plugin for TankPlugin {
                 TankPackagesPlugin,
                 TankPlayerPlugin,
                 TankRenderPlugin,
+                TankSavePlugin,
                 TankStatePlugin,
                 TankThingPlugin,
                 TankUtilPlugin,
@@ -0,0 +1,201 @@
+use crate::*;
+
+use bevy::utils::HashMap;
+use serde::{Deserialize, Serialize};
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// A folder laid out like `assets/`: containing `images/`, `sounds/`, `data/{name}/`, etc.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct ContentRoot {
+    pub name: String,
+    /// Relative to the working directory. Must be `assets` or inside it for [Packages] to be able to load from it.
+    pub directory: String,
+    #[serde(default = "ContentRoot::default_enabled")]
+    pub enabled: bool,
+}
+
+impl ContentRoot {
+    pub fn new<S0: AsRef<str>, S1: AsRef<str>>(name: S0, directory: S1) -> Self {
+        Self { name: name.as_ref().to_owned(), directory: directory.as_ref().to_owned(), enabled: true }
+    }
+
+    fn default_enabled() -> bool { true }
+
+    /// Prefix to put in front of paths relative to this root to make them loadable by the `AssetServer`: `""` or `"mods/frost/"`
+    ///
+    /// None if the root is not inside `assets`.
+    pub fn asset_prefix(&self) -> Option<String> {
+        if self.directory == "assets" { return Some(String::new()); }
+        let prefix = self.directory.strip_prefix("assets/")?;
+        Some(prefix.trim_end_matches('/').to_owned() + "/")
+    }
+
+    /// `{directory}/data`
+    pub fn data_directory(&self) -> String { self.directory.clone() + "/data" }
+
+    /// `{directory}/data/{type_name}`
+    pub fn data_path<S: AsRef<str>>(&self, type_name: S) -> String {
+        self.data_directory() + "/" + type_name.as_ref()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Ordered list of content roots. The base game is always first, later roots override earlier ones by asset name & can add new assets.
+///
+/// Loaded from `mods.ron` in the working directory, which lists every root after the base game in load order:
+/// ```ron
+/// (roots: [(name: "frost", directory: "assets/dlc/frost"), (name: "big_guns", directory: "assets/mods/big_guns", enabled: false)])
+/// ```
+#[derive(Resource, Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct ContentRoots {
+    roots: Vec<ContentRoot>,
+}
+
+impl Default for ContentRoots {
+    fn default() -> Self { Self { roots: vec![ContentRoot::new("base", "assets")] } }
+}
+
+impl ContentRoots {
+    pub const LOAD_ORDER_FILE_NAME: &'static str = "mods";
+
+    /// Only `base` in place of the base game, without reading `mods.ron`, such as for tools working outside `assets`.
+    pub fn new(base: ContentRoot) -> Self { Self { roots: vec![base] } }
+
+    /// The base game followed by every enabled root in `mods.ron`. Only the base game if `mods.ron` does not exist or fails to load.
+    pub fn load() -> Self { Self::load_from(".") }
+
+    /// Same as `load`, reading `mods.ron` from `directory` instead of the working directory.
+    pub fn load_from<S: AsRef<str>>(directory: S) -> Self {
+        let mut content_roots = Self::default();
+
+        match Serial::load_type_from_ron_file_checked::<&str, &str, ContentRoots>(directory.as_ref(), Self::LOAD_ORDER_FILE_NAME) {
+            Ok(load_order) => { for root in load_order.roots.into_iter() { content_roots.push(root); } }
+            Err(error) if error.is_not_found() => {}
+            Err(error) => { warn!("Failed to load mod load order: {error}"); }
+        }
+
+        content_roots
+    }
+
+    /// Disabled roots & roots with the same name as an existing root are ignored.
+    pub fn push(&mut self, root: ContentRoot) {
+        if !root.enabled || self.roots.iter().any(|existing| existing.name == root.name) { return; }
+        self.roots.push(root);
+    }
+
+    pub fn roots(&self) -> &[ContentRoot] { &self.roots }
+    pub fn get(&self, index: usize) -> Option<&ContentRoot> { self.roots.get(index) }
+    pub fn base(&self) -> &ContentRoot { &self.roots[0] }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// An asset provided by more than one content root.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ContentConflict {
+    pub asset: String,
+    /// Names of every root providing the asset, in load order. The last one is used.
+    pub roots: Vec<String>,
+}
+
+/// Builds the resolved view of assets provided by a list of content roots, recording which root each asset comes from.
+#[derive(Default, Debug)]
+pub struct ContentResolver {
+    /// Root indices providing each asset, in load order
+    providers: HashMap<String, Vec<usize>>,
+    /// Assets in the order they were first provided
+    order: Vec<String>,
+}
+
+impl ContentResolver {
+    pub fn provide<S: AsRef<str>>(&mut self, asset: S, root_index: usize) {
+        if let Some(providers) = self.providers.get_mut(asset.as_ref()) {
+            if !providers.contains(&root_index) { providers.push(root_index); }
+            return;
+        }
+
+        self.providers.insert(asset.as_ref().to_owned(), vec![root_index]);
+        self.order.push(asset.as_ref().to_owned());
+    }
+
+    /// `(asset, root_index)` for every asset, in the order they were first provided, using the last root that provided each.
+    pub fn resolved(&self) -> Vec<(String, usize)> {
+        self.order.iter().filter_map(|asset| Some((asset.clone(), *self.providers.get(asset)?.last()?))).collect()
+    }
+
+    pub fn conflicts(&self, content_roots: &ContentRoots) -> Vec<ContentConflict> {
+        self.order.iter()
+            .filter_map(|asset| {
+                let providers = self.providers.get(asset)?;
+                if providers.len() < 2 { return None; }
+                let roots = providers.iter().filter_map(|index| content_roots.get(*index)).map(|root| root.name.clone()).collect();
+                Some(ContentConflict { asset: asset.clone(), roots })
+            })
+            .collect()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(content_roots: &ContentRoots) -> Vec<&str> {
+        content_roots.roots().iter().map(|root| root.name.as_str()).collect()
+    }
+
+    fn load_order(name: &str, contents: Option<&str>) -> ContentRoots {
+        let directory = std::env::temp_dir().join(format!("tank_content_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        std::fs::create_dir_all(&directory).unwrap();
+        if let Some(contents) = contents { std::fs::write(directory.join("mods.ron"), contents).unwrap(); }
+
+        let content_roots = ContentRoots::load_from(directory.to_str().unwrap());
+        let _ = std::fs::remove_dir_all(&directory);
+        content_roots
+    }
+
+    #[test]
+    fn asset_prefix_is_relative_to_assets() {
+        assert_eq!(ContentRoot::new("base", "assets").asset_prefix(), Some(String::new()));
+        assert_eq!(ContentRoot::new("frost", "assets/mods/frost/").asset_prefix(), Some("mods/frost/".to_owned()));
+        assert_eq!(ContentRoot::new("outside", "mods/frost").asset_prefix(), None);
+    }
+
+    #[test]
+    fn mods_ron_roots_follow_the_base_game_in_order() {
+        let content_roots = load_order("order", Some(r#"(roots: [
+            (name: "frost", directory: "assets/dlc/frost"),
+            (name: "big_guns", directory: "assets/mods/big_guns", enabled: false),
+            (name: "tweaks", directory: "assets/mods/tweaks"),
+            (name: "frost", directory: "assets/mods/frost_copy"),
+        ])"#));
+
+        assert_eq!(names(&content_roots), ["base", "frost", "tweaks"]);
+        assert_eq!(content_roots.get(1).unwrap().directory, "assets/dlc/frost");
+    }
+
+    #[test]
+    fn missing_or_invalid_mods_ron_gives_only_the_base_game() {
+        assert_eq!(load_order("missing", None), ContentRoots::default());
+        assert_eq!(load_order("invalid", Some("(roots: [")), ContentRoots::default());
+    }
+
+    #[test]
+    fn last_root_providing_an_asset_wins() {
+        let mut content_roots = ContentRoots::default();
+        content_roots.push(ContentRoot::new("frost", "assets/dlc/frost"));
+        content_roots.push(ContentRoot::new("tweaks", "assets/mods/tweaks"));
+
+        // Roots are scanned in load order
+        let mut resolver = ContentResolver::default();
+        resolver.provide("images/tank", 0);
+        resolver.provide("images/tree", 0);
+        resolver.provide("images/tank", 1);
+        resolver.provide("images/snow", 1);
+        resolver.provide("images/tank", 2);
+        resolver.provide("images/tank", 2);
+
+        assert_eq!(resolver.resolved(), [("images/tank".to_owned(), 2), ("images/tree".to_owned(), 0), ("images/snow".to_owned(), 1)]);
+        assert_eq!(resolver.conflicts(&content_roots), [ContentConflict { asset: "images/tank".to_owned(), roots: vec!["base".to_owned(), "frost".to_owned(), "tweaks".to_owned()] }]);
+    }
+}
@@ -3,14 +3,22 @@ use crate::*;
 use std::fs::*;
 use bevy::{utils::HashMap, asset::{Asset, LoadState}, gltf::Gltf};
 
+mod content;
+pub use content::*;
 mod package_type;
 pub use package_type::*;
+mod progress;
+pub use progress::*;
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 pub struct TankPackagesPlugin;
 impl Plugin for TankPackagesPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(PreStartup, stsys_init_packages)
+        app.world.get_resource_or_insert_with(ContentRoots::load);
+        app.init_resource::<PackageLoadPolicy>()
+            .init_resource::<PackageLoadProgress>()
+            .add_event::<PackageLoadFailed>()
+            .add_systems(PreStartup, stsys_init_packages)
             .add_systems(Update, sys_update_load_state.run_if(in_state(AppState::EngineInit)));
     }
 }
@@ -27,21 +35,21 @@ pub struct Packages {
 impl Default for Packages {
     fn default() -> Self {
         Self {
-            fonts: PackageType::<Font>::new("fonts", "ttf"),
-            images: PackageType::<Image>::new("images", "png"),
-            models: PackageType::<Gltf>::new("models", "glb"),
-            sounds: PackageType::<bevy_kira_audio::AudioSource>::new("sounds", "ogg"),
+            fonts: PackageType::<Font>::with_extensions("fonts", &["ttf", "otf"]),
+            images: PackageType::<Image>::with_extensions("images", &["png", "jpg", "jpeg"]),
+            models: PackageType::<Gltf>::with_extensions("models", &["glb", "gltf"]),
+            sounds: PackageType::<bevy_kira_audio::AudioSource>::with_extensions("sounds", &["ogg", "wav", "flac"]),
         }
     }
 }
 
 impl Packages {
-    pub fn load(asset_server: &Res<AssetServer>) -> Self {
+    pub fn load(asset_server: &Res<AssetServer>, content_roots: &ContentRoots) -> Self {
         let mut packages = Self::default();
-        packages.fonts.load(asset_server);
-        packages.images.load(asset_server);
-        packages.models.load(asset_server);
-        packages.sounds.load(asset_server);
+        packages.fonts.load(asset_server, content_roots);
+        packages.images.load(asset_server, content_roots);
+        packages.models.load(asset_server, content_roots);
+        packages.sounds.load(asset_server, content_roots);
         packages
     }
 
@@ -62,23 +70,70 @@ impl Packages {
 
         LoadState::Loaded
     }
+
+    pub fn progress(&self, asset_server: &Res<AssetServer>) -> Vec<PackageTypeProgress> {
+        vec![
+            self.fonts.progress(asset_server),
+            self.images.progress(asset_server),
+            self.models.progress(asset_server),
+            self.sounds.progress(asset_server),
+        ]
+    }
+
+    /// `(package name, failure)` for every asset that failed to load.
+    pub fn failures(&self, asset_server: &Res<AssetServer>) -> Vec<(&'static str, PackageLoadFailure)> {
+        let mut failures = vec![];
+        failures.extend(self.fonts.failures(asset_server).into_iter().map(|failure| (self.fonts.name(), failure)));
+        failures.extend(self.images.failures(asset_server).into_iter().map(|failure| (self.images.name(), failure)));
+        failures.extend(self.models.failures(asset_server).into_iter().map(|failure| (self.models.name(), failure)));
+        failures.extend(self.sounds.failures(asset_server).into_iter().map(|failure| (self.sounds.name(), failure)));
+        failures
+    }
+
+    /// `(package name, conflict)` for every asset provided by more than one content root.
+    pub fn conflicts(&self) -> Vec<(&'static str, ContentConflict)> {
+        let mut conflicts = vec![];
+        conflicts.extend(self.fonts.conflicts().iter().map(|conflict| (self.fonts.name(), conflict.clone())));
+        conflicts.extend(self.images.conflicts().iter().map(|conflict| (self.images.name(), conflict.clone())));
+        conflicts.extend(self.models.conflicts().iter().map(|conflict| (self.models.name(), conflict.clone())));
+        conflicts.extend(self.sounds.conflicts().iter().map(|conflict| (self.sounds.name(), conflict.clone())));
+        conflicts
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
-fn stsys_init_packages(mut commands: Commands, asset_server: Res<AssetServer>, time: Res<Time>) {
+fn stsys_init_packages(mut commands: Commands, asset_server: Res<AssetServer>, content_roots: Res<ContentRoots>, time: Res<Time>) {
     println!("[{}] Started loading packages", time.elapsed_seconds());
-    let packages = Packages::load(&asset_server);
+    let packages = Packages::load(&asset_server, &content_roots);
     commands.insert_resource(packages);
 }
 
+/// Finishes `AppState::EngineInit` once every package is loaded, or earlier according to `PackageLoadPolicy`.
 fn sys_update_load_state(
     mut next_state: ResMut<NextState<AppState>>,
+    mut progress: ResMut<PackageLoadProgress>,
+    mut failed_events: EventWriter<PackageLoadFailed>,
+    policy: Res<PackageLoadPolicy>,
     asset_server: Res<AssetServer>,
     packages: Res<Packages>,
     time: Res<Time>,
 ) {
-    if packages.get_load_state(&asset_server) == LoadState::Loaded {
-        println!("[{}] Finished loading packages", time.elapsed_seconds());
-        next_state.0 = Some(AppState::GameInit);
+    progress.update(&packages, &asset_server, time.delta_seconds());
+
+    for (package, failure) in packages.failures(&asset_server) {
+        if !progress.report_failure(&failure) { continue; }
+        warn!("[{package}] Failed to load {}: {}", failure.path, failure.reason);
+        failed_events.send(PackageLoadFailed { package, failure });
     }
+
+    if !progress.try_finish(&policy) { return; }
+
+    let total = progress.total();
+    if progress.timed_out() {
+        warn!("[{}] Timed out loading packages, {} pending & {} failed of {}", time.elapsed_seconds(), total.pending, total.failed, total.count());
+    } else {
+        info!("[{}] Finished loading packages, {} failed of {}", time.elapsed_seconds(), total.failed, total.count());
+    }
+
+    next_state.0 = Some(AppState::GameInit);
 }
\ No newline at end of file
@@ -1,39 +1,177 @@
 use crate::*;
 use super::*;
 
+use serde::{Deserialize, Serialize};
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Optional `_package.ron` placed in any folder of a package, applying to that folder & all of its subfolders.
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct PackageManifest {
+    /// Replaces the package type's extensions. Earlier extensions win when files share a name.
+    pub extensions: Vec<String>,
+    /// File names without extension, relative to the folder of the manifest, that should not be loaded.
+    pub ignore: Vec<String>,
+}
+
+impl PackageManifest {
+    pub const FILE_NAME: &'static str = "_package";
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+#[derive(Clone, Debug)]
+pub struct PackageLoadFailure {
+    /// Relative to `assets`, with extension
+    pub path: String,
+    /// Whether the file exists & the load states of the asset & its dependencies.
+    /// Bevy does not keep the loader error itself on the handle, it is logged by the `AssetServer` when the load fails.
+    pub reason: String,
+}
+
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 #[derive(Default)]
 pub struct PackageType<T: Asset> {
     name: &'static str,
-    extension: &'static str,
+    extensions: Vec<&'static str>,
     ids: HashMap<String, u32>,
     assets: Vec<Handle<T>>,
+    /// Relative to `assets`, with extension, indexed by id
+    paths: Vec<String>,
+    /// Index into `ContentRoots` of the root each asset was loaded from, indexed by id
+    roots: Vec<usize>,
+    /// Files that were not loaded because a file with the same name & an earlier extension was, relative to the working directory
+    collisions: Vec<String>,
+    /// Assets provided by more than one content root
+    conflicts: Vec<ContentConflict>,
 }
 
 impl<T: Asset> PackageType<T> {
     pub fn new(name: &'static str, extension: &'static str) -> Self {
-        Self { name, extension, ids: HashMap::default(), assets: vec![] }
+        Self::with_extensions(name, &[extension])
     }
 
+    /// Earlier extensions win when files share a name.
+    pub fn with_extensions(name: &'static str, extensions: &[&'static str]) -> Self {
+        Self { name, extensions: extensions.to_vec(), ids: HashMap::default(), assets: vec![], paths: vec![], roots: vec![], collisions: vec![], conflicts: vec![] }
+    }
+
+    pub fn name(&self) -> &'static str { self.name }
+    pub fn extensions(&self) -> &[&'static str] { &self.extensions }
+    pub fn len(&self) -> usize { self.assets.len() }
+    pub fn is_empty(&self) -> bool { self.assets.is_empty() }
+    pub fn handles(&self) -> &[Handle<T>] { &self.assets }
+    pub fn paths(&self) -> &[String] { &self.paths }
+    pub fn collisions(&self) -> &[String] { &self.collisions }
+    pub fn conflicts(&self) -> &[ContentConflict] { &self.conflicts }
+    /// Index into `ContentRoots` of the root asset `id` was loaded from.
+    pub fn root(&self, id: u32) -> Option<usize> { self.roots.get(id as usize).cloned() }
+
     pub fn handle(&self, id: u32) -> &Handle<T> { &self.assets[id as usize] }
+    pub fn try_handle(&self, id: u32) -> Option<&Handle<T>> { self.assets.get(id as usize) }
     pub fn fetch_handle(&self, asset: &str) -> &Handle<T> { self.handle(self.fetch_id(asset)) }
+    pub fn try_fetch_handle(&self, asset: &str) -> Option<&Handle<T>> { self.try_handle(self.try_fetch_id(asset)?) }
 
+    /// Falls back to id 0 if `asset` does not exist. Prefer `try_fetch_id`.
     pub fn fetch_id(&self, asset: &str) -> u32 {
-        if let Some(id) = self.ids.get(&(self.name.to_owned() + "/" + asset)) {
-            *id
+        if let Some(id) = self.try_fetch_id(asset) {
+            id
         } else {
             println!("Could not find asset id for: [{asset}]");
             0
         }
     }
 
-    pub fn load(&mut self, asset_server: &Res<AssetServer>) {
-        let files = Serial::file_paths_from_directory_recursive("assets", self.name);
+    /// `asset` is relative to the package folder, without extension, and may contain subfolders: `"ui/click"`
+    pub fn try_fetch_id(&self, asset: &str) -> Option<u32> {
+        self.ids.get(&(self.name.to_owned() + "/" + asset)).cloned()
+    }
+
+    /// Scans `{name}/` of every content root in order. Later roots override earlier ones by asset name & can add new assets.
+    pub fn load(&mut self, asset_server: &Res<AssetServer>, content_roots: &ContentRoots) {
+        let mut resolver = ContentResolver::default();
+        let mut root_paths: HashMap<(String, usize), String> = HashMap::default();
+
+        for (root_index, root) in content_roots.roots().iter().enumerate() {
+            if root.asset_prefix().is_none() {
+                warn!("[{}] Skipped content root [{}], {} is not inside assets", self.name, root.name, root.directory);
+                continue;
+            }
+
+            for (asset_file, path) in self.scan_root(&root.directory) {
+                resolver.provide(&asset_file, root_index);
+                root_paths.insert((asset_file, root_index), path);
+            }
+        }
+
+        for (asset_file, root_index) in resolver.resolved() {
+            if self.ids.contains_key(&asset_file) { continue; }
+            let Some(path) = root_paths.get(&(asset_file.clone(), root_index)) else { continue };
+            let Some(prefix) = content_roots.get(root_index).and_then(|root| root.asset_prefix()) else { continue };
+            let path = prefix + path;
+
+            self.ids.insert(asset_file, self.assets.len() as u32);
+            self.assets.push(asset_server.load(path.clone()));
+            self.paths.push(path);
+            self.roots.push(root_index);
+        }
+
+        self.conflicts = resolver.conflicts(content_roots);
+        for collision in self.collisions.iter() { warn!("[{}] Skipped {collision}, another file has the same name", self.name); }
+        for conflict in self.conflicts.iter() { debug!("[{}] {} is provided by {:?}, using the last", self.name, conflict.asset, conflict.roots); }
+    }
+
+    /// `(asset_file, path)` for every loadable file in `{directory}/{name}/`, relative to `directory`. Collisions are recorded with `directory` in front.
+    fn scan_root(&mut self, directory: &str) -> Vec<(String, String)> {
+        let files = Serial::file_paths_from_directory_recursive(directory, self.name);
+        let manifests = Self::load_manifests(directory, &files);
+        let mut found: Vec<(String, String)> = vec![];
 
         for asset_file in files.iter() {
-            self.ids.insert(asset_file.clone(), self.assets.len() as u32);
-            self.assets.push(asset_server.load(asset_file.clone() + "." + &self.extension));
+            if found.iter().any(|(existing, _)| existing == asset_file) { continue; }
+
+            let manifest = Self::nearest_manifest(&manifests, asset_file);
+            let extensions: Vec<&str> = if let Some((_, manifest)) = manifest {
+                manifest.extensions.iter().map(|extension| extension.as_str()).collect()
+            } else {
+                self.extensions.clone()
+            };
+
+            if let Some((manifest_directory, manifest)) = manifest {
+                let Some(relative_file) = asset_file.strip_prefix(&(manifest_directory.to_owned() + "/")) else { continue };
+                if manifest.ignore.iter().any(|ignored| ignored == relative_file) { continue; }
+            }
+
+            // Stems are listed once per file, so every extension present is checked the first time a name is seen
+            let existing: Vec<String> = extensions.iter()
+                .map(|extension| asset_file.clone() + "." + extension)
+                .filter(|path| Serial::try_get_modified_time(directory.to_owned() + "/" + path).is_some())
+                .collect();
+            let Some(path) = existing.first() else { continue };
+            self.collisions.extend(existing.iter().skip(1).map(|collision| directory.to_owned() + "/" + collision));
+
+            found.push((asset_file.clone(), path.clone()));
         }
+
+        found
+    }
+
+    /// `(directory, manifest)` for every `_package.ron` in `files`, deepest first.
+    fn load_manifests(root_directory: &str, files: &[String]) -> Vec<(String, PackageManifest)> {
+        let mut manifests: Vec<(String, PackageManifest)> = files.iter()
+            .filter_map(|file| file.strip_suffix(&("/".to_owned() + PackageManifest::FILE_NAME)))
+            .filter_map(|directory| {
+                match Serial::load_type_from_ron_file_checked(root_directory.to_owned() + "/" + directory, PackageManifest::FILE_NAME) {
+                    Ok(manifest) => Some((directory.to_owned(), manifest)),
+                    Err(error) => { warn!("Failed to load package manifest: {error}"); None }
+                }
+            })
+            .collect();
+
+        manifests.sort_by_key(|(directory, _)| std::cmp::Reverse(directory.len()));
+        manifests
+    }
+
+    fn nearest_manifest<'a>(manifests: &'a [(String, PackageManifest)], asset_file: &str) -> Option<&'a (String, PackageManifest)> {
+        manifests.iter().find(|(directory, _)| asset_file.starts_with(&(directory.to_owned() + "/")))
     }
 
     pub fn get_load_state(&self, asset_server: &Res<AssetServer>) -> LoadState {
@@ -47,4 +185,127 @@ impl<T: Asset> PackageType<T> {
 
         LoadState::Loaded
     }
-}
\ No newline at end of file
+
+    /// Assets without a load state are counted as pending, as the `AssetServer` has not started tracking them yet.
+    pub fn progress(&self, asset_server: &Res<AssetServer>) -> PackageTypeProgress {
+        let mut progress = PackageTypeProgress { name: self.name, ..default() };
+        for handle in self.assets.iter() {
+            match asset_server.get_load_state(handle.id()) {
+                Some(LoadState::Loaded) => { progress.loaded += 1; }
+                Some(LoadState::Failed) => { progress.failed += 1; }
+                _ => { progress.pending += 1; }
+            }
+        }
+
+        progress
+    }
+
+    /// Every asset whose load state is `LoadState::Failed`.
+    pub fn failures(&self, asset_server: &Res<AssetServer>) -> Vec<PackageLoadFailure> {
+        self.assets.iter().zip(self.paths.iter())
+            .filter(|(handle, _)| asset_server.get_load_state(handle.id()) == Some(LoadState::Failed))
+            .map(|(handle, path)| PackageLoadFailure { path: path.clone(), reason: Self::failure_reason(asset_server, handle, path) })
+            .collect()
+    }
+
+    fn failure_reason(asset_server: &Res<AssetServer>, handle: &Handle<T>, path: &str) -> String {
+        let full_path = "assets/".to_owned() + path;
+        if !std::path::Path::new(&full_path).is_file() { return format!("{full_path} does not exist"); }
+
+        match asset_server.get_load_states(handle.id()) {
+            Some((load_state, dependencies, recursive_dependencies)) => {
+                format!("{full_path} is {load_state:?}, with dependencies {dependencies:?} & {recursive_dependencies:?} recursively. The loader error is in the AssetServer log")
+            }
+            None => { format!("{full_path} is no longer tracked by the AssetServer") }
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates every file in `files`, relative to a new temp directory that is returned.
+    fn temp_root(name: &str, files: &[(&str, &str)]) -> String {
+        let directory = std::env::temp_dir().join(format!("tank_package_type_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        for (file, contents) in files {
+            let path = directory.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, contents).unwrap();
+        }
+
+        directory.to_str().unwrap().to_owned()
+    }
+
+    fn scan(package_type: &mut PackageType<Font>, directory: &str) -> Vec<(String, String)> {
+        let mut found = package_type.scan_root(directory);
+        let _ = std::fs::remove_dir_all(directory);
+        found.sort();
+        found
+    }
+
+    fn found(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(asset_file, path)| (asset_file.to_string(), path.to_string())).collect()
+    }
+
+    #[test]
+    fn earlier_extensions_win_when_files_share_a_name() {
+        let directory = temp_root("extensions", &[("images/a.png", ""), ("images/a.jpg", ""), ("images/b.jpeg", ""), ("images/c.txt", "")]);
+        let mut package_type = PackageType::<Font>::with_extensions("images", &["png", "jpg", "jpeg"]);
+
+        assert_eq!(scan(&mut package_type, &directory), found(&[("images/a", "images/a.png"), ("images/b", "images/b.jpeg")]));
+        assert_eq!(package_type.collisions(), &[directory + "/images/a.jpg"]);
+    }
+
+    #[test]
+    fn manifest_replaces_extensions_for_its_folder_and_subfolders() {
+        let directory = temp_root("manifest_extensions", &[
+            ("images/ui/_package.ron", "(extensions: [\"jpg\", \"png\"], ignore: [])"),
+            ("images/ui/a.png", ""),
+            ("images/ui/a.jpg", ""),
+            ("images/ui/icons/b.png", ""),
+            ("images/c.png", ""),
+            ("images/c.jpg", ""),
+        ]);
+        let mut package_type = PackageType::<Font>::with_extensions("images", &["png", "jpg"]);
+
+        assert_eq!(scan(&mut package_type, &directory), found(&[
+            ("images/c", "images/c.png"),
+            ("images/ui/a", "images/ui/a.jpg"),
+            ("images/ui/icons/b", "images/ui/icons/b.png"),
+        ]));
+        assert_eq!(package_type.collisions().len(), 2);
+    }
+
+    #[test]
+    fn manifest_ignores_files_relative_to_its_folder() {
+        let directory = temp_root("manifest_ignore", &[
+            ("images/ui/_package.ron", "(extensions: [\"png\"], ignore: [\"skip\", \"icons/skip\"])"),
+            ("images/ui/skip.png", ""),
+            ("images/ui/keep.png", ""),
+            ("images/ui/icons/skip.png", ""),
+            ("images/skip.png", ""),
+        ]);
+        let mut package_type = PackageType::<Font>::new("images", "png");
+
+        assert_eq!(scan(&mut package_type, &directory), found(&[("images/skip", "images/skip.png"), ("images/ui/keep", "images/ui/keep.png")]));
+    }
+
+    #[test]
+    fn deepest_manifest_applies() {
+        let directory = temp_root("manifest_nested", &[
+            ("images/_package.ron", "(extensions: [\"png\"], ignore: [])"),
+            ("images/ui/_package.ron", "(extensions: [\"jpg\"], ignore: [])"),
+            ("images/a.png", ""),
+            ("images/a.jpg", ""),
+            ("images/ui/b.png", ""),
+            ("images/ui/b.jpg", ""),
+        ]);
+        let mut package_type = PackageType::<Font>::new("images", "jpeg");
+
+        assert_eq!(scan(&mut package_type, &directory), found(&[("images/a", "images/a.png"), ("images/ui/b", "images/ui/b.jpg")]));
+        assert!(package_type.collisions().is_empty());
+    }
+}
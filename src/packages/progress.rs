@@ -0,0 +1,167 @@
+use crate::*;
+
+use bevy::utils::HashSet;
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// How `AppState::EngineInit` finishes when packages fail or take too long to load.
+#[derive(Resource, Clone, Copy, Debug)]
+pub struct PackageLoadPolicy {
+    /// Failed assets count as finished. Otherwise loading waits for `timeout`, or forever if there is none.
+    pub continue_on_failure: bool,
+    /// Seconds after which loading finishes regardless of pending & failed assets.
+    pub timeout: Option<f32>,
+}
+
+impl Default for PackageLoadPolicy {
+    fn default() -> Self { Self { continue_on_failure: true, timeout: Some(60.0) } }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+#[derive(Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PackageTypeProgress {
+    pub name: &'static str,
+    pub loaded: usize,
+    pub failed: usize,
+    pub pending: usize,
+}
+
+impl PackageTypeProgress {
+    pub fn count(&self) -> usize { self.loaded + self.failed + self.pending }
+
+    /// Loaded & failed assets over all assets, 1.0 if there are none.
+    pub fn fraction(&self) -> f32 {
+        if self.count() == 0 { 1.0 } else { (self.loaded + self.failed) as f32 / self.count() as f32 }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Updated every frame during `AppState::EngineInit`, intended for loading screens.
+#[derive(Resource, Default, Debug)]
+pub struct PackageLoadProgress {
+    types: Vec<PackageTypeProgress>,
+    /// Seconds since packages started loading
+    elapsed: f32,
+    finished: bool,
+    timed_out: bool,
+    reported_failures: HashSet<String>,
+}
+
+impl PackageLoadProgress {
+    pub fn types(&self) -> &[PackageTypeProgress] { &self.types }
+    pub fn get(&self, name: &str) -> Option<&PackageTypeProgress> { self.types.iter().find(|progress| progress.name == name) }
+    pub fn elapsed(&self) -> f32 { self.elapsed }
+    pub fn finished(&self) -> bool { self.finished }
+    /// Loading finished because `PackageLoadPolicy::timeout` was reached with assets still pending or failed.
+    pub fn timed_out(&self) -> bool { self.timed_out }
+
+    /// Sum of every package type, named `"total"`.
+    pub fn total(&self) -> PackageTypeProgress {
+        self.types.iter().fold(PackageTypeProgress { name: "total", ..default() }, |total, progress| PackageTypeProgress {
+            name: total.name,
+            loaded: total.loaded + progress.loaded,
+            failed: total.failed + progress.failed,
+            pending: total.pending + progress.pending,
+        })
+    }
+
+    pub(super) fn update(&mut self, packages: &Packages, asset_server: &Res<AssetServer>, delta_seconds: f32) {
+        self.types = packages.progress(asset_server);
+        self.elapsed += delta_seconds;
+    }
+
+    /// Returns whether `failure` has not been reported yet.
+    pub(super) fn report_failure(&mut self, failure: &PackageLoadFailure) -> bool {
+        self.reported_failures.insert(failure.path.clone())
+    }
+
+    /// Returns whether loading should finish, marking it finished if so.
+    pub(super) fn try_finish(&mut self, policy: &PackageLoadPolicy) -> bool {
+        let total = self.total();
+        let complete = total.pending == 0 && (total.failed == 0 || policy.continue_on_failure);
+        let timed_out = !complete && policy.timeout.is_some_and(|timeout| self.elapsed >= timeout);
+
+        self.finished = complete || timed_out;
+        self.timed_out = timed_out;
+        self.finished
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Sent once for every asset that fails to load during `AppState::EngineInit`.
+#[derive(Event, Clone, Debug)]
+pub struct PackageLoadFailed {
+    pub package: &'static str,
+    pub failure: PackageLoadFailure,
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn progress_with(loaded: usize, failed: usize, pending: usize, elapsed: f32) -> PackageLoadProgress {
+        PackageLoadProgress { types: vec![PackageTypeProgress { name: "images", loaded, failed, pending }], elapsed, ..default() }
+    }
+
+    fn failure(path: &str) -> PackageLoadFailure {
+        PackageLoadFailure { path: path.to_owned(), reason: "missing".to_owned() }
+    }
+
+    #[test]
+    fn total_sums_every_type() {
+        let mut progress = progress_with(2, 1, 1, 0.0);
+        progress.types.push(PackageTypeProgress { name: "sounds", loaded: 3, failed: 0, pending: 1 });
+
+        assert_eq!(progress.total(), PackageTypeProgress { name: "total", loaded: 5, failed: 1, pending: 2 });
+        assert_eq!(progress.total().fraction(), 0.75);
+        assert_eq!(PackageTypeProgress::default().fraction(), 1.0);
+    }
+
+    #[test]
+    fn finishes_once_nothing_is_pending() {
+        let policy = PackageLoadPolicy::default();
+        assert!(!progress_with(3, 0, 1, 0.0).try_finish(&policy));
+
+        let mut progress = progress_with(4, 0, 0, 0.0);
+        assert!(progress.try_finish(&policy));
+        assert!(progress.finished() && !progress.timed_out());
+    }
+
+    #[test]
+    fn failures_only_finish_when_continuing_on_failure() {
+        let mut progress = progress_with(3, 1, 0, 0.0);
+        assert!(progress.try_finish(&PackageLoadPolicy { continue_on_failure: true, timeout: None }));
+        assert!(!progress.timed_out());
+
+        let mut progress = progress_with(3, 1, 0, 1000.0);
+        assert!(!progress.try_finish(&PackageLoadPolicy { continue_on_failure: false, timeout: None }));
+        assert!(!progress.finished());
+    }
+
+    #[test]
+    fn timeout_finishes_with_pending_or_failed_assets() {
+        let policy = PackageLoadPolicy { continue_on_failure: false, timeout: Some(10.0) };
+        assert!(!progress_with(3, 1, 1, 9.0).try_finish(&policy));
+
+        let mut pending = progress_with(3, 0, 1, 10.0);
+        assert!(pending.try_finish(&policy));
+        assert!(pending.timed_out());
+
+        let mut failed = progress_with(3, 1, 0, 10.0);
+        assert!(failed.try_finish(&policy));
+        assert!(failed.timed_out());
+
+        // Finishing in time is not a timeout
+        let mut loaded = progress_with(4, 0, 0, 10.0);
+        assert!(loaded.try_finish(&policy));
+        assert!(!loaded.timed_out());
+    }
+
+    #[test]
+    fn failures_are_reported_once() {
+        let mut progress = PackageLoadProgress::default();
+        assert!(progress.report_failure(&failure("images/a.png")));
+        assert!(!progress.report_failure(&failure("images/a.png")));
+        assert!(progress.report_failure(&failure("images/b.png")));
+    }
+}
@@ -1,6 +1,6 @@
 use crate::*;
 
-use bevy::window::PrimaryWindow;
+use bevy::{window::PrimaryWindow, ecs::{entity::{EntityMapper, MapEntities}, reflect::ReflectMapEntities}};
 
 mod manager;
 pub use manager::*;
@@ -65,9 +65,15 @@ impl Player {
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 /// Can be Camera3d or Camera2d, as long as it's a camera.
 #[derive(Component, Default, Reflect)]
-#[reflect(Component)]
+#[reflect(Component, MapEntities)]
 pub struct PlayerMainCameraRef(Option<Entity>);
 
+impl MapEntities for PlayerMainCameraRef {
+    fn map_entities(&mut self, entity_mapper: &mut EntityMapper) {
+        if let Some(camera) = &mut self.0 { *camera = entity_mapper.get_or_reserve(*camera); }
+    }
+}
+
 impl PlayerMainCameraRef {
     pub fn new(camera: Option<Entity>) -> Self { Self { 0: camera } }
     pub fn try_get(&self) -> &Option<Entity> { &self.0 }
@@ -77,19 +83,31 @@ impl PlayerMainCameraRef {
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 /// A list of the [GuiData] entities this [Player] is viewing
 #[derive(Component, Default, Deref, DerefMut, Debug, Reflect)]
-#[reflect(Component, Default)]
+#[reflect(Component, Default, MapEntities)]
 pub struct PlayerGuiViewer(pub Vec<Entity>);
 
+impl MapEntities for PlayerGuiViewer {
+    fn map_entities(&mut self, entity_mapper: &mut EntityMapper) {
+        for entity in self.0.iter_mut() { *entity = entity_mapper.get_or_reserve(*entity); }
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 /// Collects entities the player has selected.
 /// 
 /// TODO: Store fundementally different selections separately? (RTS Units vs. Menu elements)
 #[derive(Component, Default, Reflect)]
-#[reflect(Component)]
+#[reflect(Component, MapEntities)]
 pub struct PlayerSelector {
     pub selected_entities: Vec<Entity>,
 }
 
+impl MapEntities for PlayerSelector {
+    fn map_entities(&mut self, entity_mapper: &mut EntityMapper) {
+        for entity in self.selected_entities.iter_mut() { *entity = entity_mapper.get_or_reserve(*entity); }
+    }
+}
+
 impl PlayerSelector {
     pub fn new(selected_entities: Vec<Entity>) -> Self {
         Self { selected_entities }
@@ -101,11 +119,17 @@ impl PlayerSelector {
 /// 
 /// It's up to the game to implement how inputs are sent by reading [InputActions] alongside this.
 #[derive(Component, Default, Reflect)]
-#[reflect(Component)]
+#[reflect(Component, MapEntities)]
 pub struct PlayerController {
     pub controlled_entity: Option<Entity>,
 }
 
+impl MapEntities for PlayerController {
+    fn map_entities(&mut self, entity_mapper: &mut EntityMapper) {
+        if let Some(controlled) = &mut self.controlled_entity { *controlled = entity_mapper.get_or_reserve(*controlled); }
+    }
+}
+
 impl PlayerController {
     pub fn new(controlled_entity: Option<Entity>) -> Self {
         Self { controlled_entity }
@@ -0,0 +1,269 @@
+use crate::*;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+use bevy::{
+    render::view::screenshot::ScreenshotManager,
+    scene::serde::SceneDeserializer,
+    utils::HashMap,
+    window::PrimaryWindow,
+};
+use serde::{de::DeserializeSeed, Deserialize, Serialize};
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Saves & loads numbered slots in `SAVE_DATA_DIR` through [SaveGameEvent]s.
+///
+/// Every entity with [Saveable] is captured along with all of its registered, reflected components.
+/// Components holding [Entity] references must implement [MapEntities](bevy::ecs::entity::MapEntities) & reflect it to be restored correctly.
+pub struct TankSavePlugin;
+impl Plugin for TankSavePlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Saveable>()
+            .init_resource::<SaveGamePlaytime>()
+            .add_event::<SaveGameEvent>()
+            .add_event::<SaveGameResultEvent>()
+            .add_systems(Update, sys_update_save_game_playtime.run_if(in_state(AppState::Main)))
+            .add_systems(Last, evsys_handle_save_game_events);
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Marker component. Only entities with this are captured in save games.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct Saveable;
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Seconds spent in [AppState::Main], restored when a slot is loaded.
+#[derive(Resource, Default, Debug)]
+pub struct SaveGamePlaytime(pub f64);
+
+fn sys_update_save_game_playtime(mut playtime: ResMut<SaveGamePlaytime>, time: Res<Time>) {
+    playtime.0 += time.delta_seconds_f64();
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+#[derive(Event, Clone, Copy, Debug)]
+pub enum SaveGameEvent {
+    Save { slot: u32 },
+    /// Despawns all existing [Saveable] entities before restoring the slot.
+    Load { slot: u32 },
+    Delete { slot: u32 },
+}
+
+/// Sent once for every [SaveGameEvent] after it has been handled.
+#[derive(Event, Clone, Debug)]
+pub enum SaveGameResultEvent {
+    Saved(SaveSlotMeta),
+    Loaded(SaveSlotMeta),
+    Deleted { slot: u32 },
+    Failed { slot: u32, error: SerialError },
+}
+
+fn evsys_handle_save_game_events(world: &mut World) {
+    let events: Vec<SaveGameEvent> = world.resource_mut::<Events<SaveGameEvent>>().drain().collect();
+
+    for event in events {
+        let result = match event {
+            SaveGameEvent::Save { slot } => SaveGames::save(world, slot).map(SaveGameResultEvent::Saved),
+            SaveGameEvent::Load { slot } => SaveGames::load(world, slot).map(|(meta, _)| SaveGameResultEvent::Loaded(meta)),
+            SaveGameEvent::Delete { slot } => SaveGames::delete(slot).map(|_| SaveGameResultEvent::Deleted { slot }),
+        };
+
+        let result = result.unwrap_or_else(|error| {
+            error!("Save game failed: {error}");
+            let (SaveGameEvent::Save { slot } | SaveGameEvent::Load { slot } | SaveGameEvent::Delete { slot }) = event;
+            SaveGameResultEvent::Failed { slot, error }
+        });
+
+        world.send_event(result);
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+#[derive(Serialize, Deserialize, Default, Debug, Clone)]
+pub struct SaveSlotMeta {
+    pub slot: u32,
+    /// Seconds since the unix epoch
+    pub timestamp: u64,
+    /// Seconds, from [SaveGamePlaytime]
+    pub playtime: f64,
+    /// Requested when saving, but written by the renderer a frame or more later
+    pub screenshot_path: Option<String>,
+    pub entity_count: usize,
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Slots are stored as `SAVE_DATA_DIR/slot_{n}/` containing `meta.ron`, `scene.ron` & `screenshot.png`.
+pub struct SaveGames;
+impl SaveGames {
+    pub fn slot_directory(slot: u32) -> String { SAVE_DATA_DIR.to_string() + "/slot_" + &slot.to_string() }
+
+    /// Every [Saveable] entity & its reflected components. Components that are not registered are skipped.
+    pub fn capture(world: &mut World) -> DynamicScene {
+        let entities: Vec<Entity> = world.query_filtered::<Entity, With<Saveable>>().iter(world).collect();
+        DynamicSceneBuilder::from_world(world)
+            .extract_entities(entities.into_iter())
+            .build()
+    }
+
+    /// Spawns every entity in `scene` as a new entity, remapping [Entity] references through [MapEntities](bevy::ecs::entity::MapEntities).
+    ///
+    /// Returns the map from saved entities to spawned entities.
+    pub fn restore(world: &mut World, scene: &DynamicScene) -> std::result::Result<HashMap<Entity, Entity>, String> {
+        let mut entity_map = HashMap::default();
+        scene.write_to_world(world, &mut entity_map).map_err(|error| error.to_string())?;
+        Ok(entity_map)
+    }
+
+    pub fn save(world: &mut World, slot: u32) -> SerialResult<SaveSlotMeta> {
+        let directory = Self::slot_directory(slot);
+        let scene = Self::capture(world);
+        let scene_path = Serial::path_string(&directory, "scene", "ron");
+        let contents = scene.serialize_ron(world.resource::<AppTypeRegistry>())
+            .map_err(|error| SerialError::RonSerialize { path: scene_path.clone(), error })?;
+        Serial::write_file_and_path_checked(&scene_path, contents.as_bytes())?;
+
+        let screenshot_path = Serial::path_string(&directory, "screenshot", "png");
+        let screenshot_requested = world.query_filtered::<Entity, With<PrimaryWindow>>().get_single(world).ok()
+            .and_then(|window| Some(world.get_resource_mut::<ScreenshotManager>()?.save_screenshot_to_disk(window, &screenshot_path).is_ok()))
+            .unwrap_or(false);
+
+        let meta = SaveSlotMeta {
+            slot,
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0),
+            playtime: world.get_resource::<SaveGamePlaytime>().map(|playtime| playtime.0).unwrap_or(0.0),
+            screenshot_path: if screenshot_requested { Some(screenshot_path) } else { None },
+            entity_count: scene.entities.len(),
+        };
+
+        Serial::save_type_to_ron_file_checked(&meta, &directory, "meta", 1)?;
+        Ok(meta)
+    }
+
+    /// Despawns all existing [Saveable] entities, then restores the slot & its playtime.
+    ///
+    /// Returns the slot metadata & the map from saved entities to spawned entities.
+    pub fn load(world: &mut World, slot: u32) -> SerialResult<(SaveSlotMeta, HashMap<Entity, Entity>)> {
+        let directory = Self::slot_directory(slot);
+        let meta = Self::load_meta(slot)?;
+        let scene_path = Serial::path_string(&directory, "scene", "ron");
+        let contents = Serial::get_bytes_from_path_checked(&scene_path)?;
+
+        let scene = {
+            let type_registry = world.resource::<AppTypeRegistry>().read();
+            let mut deserializer = ron::de::Deserializer::from_bytes(&contents).map_err(|error| SerialError::ron_parse(&scene_path, error))?;
+            SceneDeserializer { type_registry: &type_registry }.deserialize(&mut deserializer)
+                .map_err(|error| SerialError::ron_parse(&scene_path, deserializer.span_error(error)))?
+        };
+
+        let existing: Vec<Entity> = world.query_filtered::<Entity, With<Saveable>>().iter(world).collect();
+        // Saveable entities may be children of other saveable entities, so some may already be gone
+        for entity in existing {
+            if let Some(entity) = world.get_entity_mut(entity) { entity.despawn_recursive(); }
+        }
+
+        let entity_map = Self::restore(world, &scene)
+            .map_err(|message| SerialError::Scene { path: scene_path.clone(), message })?;
+        if let Some(mut playtime) = world.get_resource_mut::<SaveGamePlaytime>() { playtime.0 = meta.playtime; }
+
+        Ok((meta, entity_map))
+    }
+
+    pub fn load_meta(slot: u32) -> SerialResult<SaveSlotMeta> {
+        Serial::load_type_from_ron_file_checked(Self::slot_directory(slot), "meta")
+    }
+
+    /// Metadata of every slot that can be read, sorted by slot.
+    pub fn list_slots() -> Vec<SaveSlotMeta> {
+        let (directories, _) = Serial::paths_from_directory(SAVE_DATA_DIR, "");
+        let mut slots: Vec<SaveSlotMeta> = directories.iter()
+            .filter_map(|directory| directory.strip_prefix("/slot_")?.parse::<u32>().ok())
+            .filter_map(|slot| Self::load_meta(slot).ok())
+            .collect();
+        slots.sort_by_key(|meta| meta.slot);
+        slots
+    }
+
+    pub fn delete(slot: u32) -> SerialResult<()> {
+        let directory = Self::slot_directory(slot);
+        std::fs::remove_dir_all(&directory).map_err(|error| SerialError::io(&directory, error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world() -> World {
+        let mut world = World::new();
+        let type_registry = AppTypeRegistry::default();
+        {
+            let mut registry = type_registry.write();
+            registry.register::<Saveable>();
+            registry.register::<Name>();
+            registry.register::<std::borrow::Cow<'static, str>>();
+            registry.register::<PlayerController>();
+            registry.register::<SocketConnection>();
+            registry.register::<Entity>();
+            registry.register::<Option<Entity>>();
+        }
+        world.insert_resource(type_registry);
+        world
+    }
+
+    fn named(world: &mut World, name: &str) -> Entity {
+        world.query::<(Entity, &Name)>().iter(world).find(|(_, entity_name)| entity_name.as_str() == name).unwrap().0
+    }
+
+    #[test]
+    fn capture_and_restore_remaps_entity_references() {
+        let mut world = world();
+        let hull = world.spawn((Saveable, Name::new("hull"))).id();
+        let turret = world.spawn((Saveable, Name::new("turret"), SocketConnection(Some(hull)))).id();
+        world.spawn((Saveable, Name::new("player"), PlayerController::new(Some(turret))));
+        world.spawn(Name::new("unsaved"));
+
+        let scene = SaveGames::capture(&mut world);
+        assert_eq!(scene.entities.len(), 3);
+
+        // Through ron, as in a slot's `scene.ron`
+        let contents = scene.serialize_ron(world.resource::<AppTypeRegistry>()).unwrap();
+        let scene = {
+            let type_registry = world.resource::<AppTypeRegistry>().read();
+            let mut deserializer = ron::de::Deserializer::from_str(&contents).unwrap();
+            SceneDeserializer { type_registry: &type_registry }.deserialize(&mut deserializer).unwrap()
+        };
+
+        let saved: Vec<Entity> = world.query_filtered::<Entity, With<Saveable>>().iter(&world).collect();
+        for entity in saved { world.despawn(entity); }
+        // Take the freed entity slots, so restored references can't still point at them by accident
+        for _ in 0..3 { world.spawn_empty(); }
+
+        let entity_map = SaveGames::restore(&mut world, &scene).unwrap();
+        assert_eq!(entity_map.len(), 3);
+
+        let (hull_restored, turret_restored, player_restored) = (named(&mut world, "hull"), named(&mut world, "turret"), named(&mut world, "player"));
+        assert_eq!(entity_map[&hull], hull_restored);
+        assert_eq!(entity_map[&turret], turret_restored);
+        assert_ne!(hull_restored, hull);
+
+        assert_eq!(world.get::<SocketConnection>(turret_restored), Some(&SocketConnection(Some(hull_restored))));
+        assert_eq!(world.get::<PlayerController>(player_restored).unwrap().controlled_entity, Some(turret_restored));
+        assert_eq!(world.query_filtered::<Entity, With<Saveable>>().iter(&world).count(), 3);
+    }
+
+    #[test]
+    fn restore_keeps_missing_references_out_of_the_world() {
+        let mut world = world();
+        let gone = world.spawn_empty().id();
+        world.spawn((Saveable, Name::new("player"), PlayerController::new(Some(gone))));
+        let scene = SaveGames::capture(&mut world);
+        world.clear_entities();
+
+        SaveGames::restore(&mut world, &scene).unwrap();
+        let player = named(&mut world, "player");
+        let controlled = world.get::<PlayerController>(player).unwrap().controlled_entity.unwrap();
+        assert_ne!(controlled, gone);
+        assert!(world.get_entity(controlled).is_none());
+    }
+}
@@ -1,10 +1,18 @@
 use crate::*;
 
+use bevy::ecs::{entity::{EntityMapper, MapEntities}, reflect::ReflectMapEntities};
+
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 #[derive(Component, Default, PartialEq, Eq, Clone, Copy, Hash, Debug, Reflect)]
-#[reflect(Component, Default)]
+#[reflect(Component, Default, MapEntities)]
 pub struct SocketConnection(pub Option<Entity>);
 
+impl MapEntities for SocketConnection {
+    fn map_entities(&mut self, entity_mapper: &mut EntityMapper) {
+        if let Some(connected) = &mut self.0 { *connected = entity_mapper.get_or_reserve(*connected); }
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 #[derive(Component, Default, PartialEq, Eq, Clone, Copy, Hash, Debug, Reflect)]
 #[reflect(Component, Default)]
@@ -36,6 +36,9 @@ impl<const WORD_NUM: usize> Bitmask<WORD_NUM> {
         Self { 0: if active { [u64::MAX; WORD_NUM] } else { [0; WORD_NUM] } }
     }
 
+    pub fn from_words(words: [u64; WORD_NUM]) -> Self { Self(words) }
+    pub fn words(&self) -> &[u64; WORD_NUM] { &self.0 }
+
     pub fn get_next_bit_on(&self, start: u32) -> u32 {
         let mut n = start as u64 >> 6;
         if n >= WORD_NUM as u64 { return WORD_NUM as u32 * 64; }
@@ -84,6 +87,10 @@ impl<const WORD_NUM: usize> Bitmask<WORD_NUM> {
         word.trailing_zeros()
     }
 
+    pub fn count_on(&self) -> u32 {
+        self.0.iter().map(|word| word.count_ones()).sum()
+    }
+
     pub fn is_on(&self) -> bool {
         for word in self.0 { if word != u64::MAX { return false; } }
         true
@@ -5,6 +5,8 @@ use bevy::render::mesh::*;
 
 mod gltf;
 pub use gltf::*;
+mod smooth;
+pub use smooth::*;
 mod tile;
 pub use tile::*;
 mod voxel;
@@ -53,18 +55,60 @@ pub const CUBE_QUAD_VERTS: [[usize; 4]; 6] = [ // Read as: 0, 1, 2, 2, 1, 3
 
 pub const CUBE_QUAD_INDICES: [u32; 6] = [0, 2, 1, 1, 2, 3];
 
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// A square texture atlas of `dim` x `dim` tiles. Tile ids go left to right, then top to bottom.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct TexAtlas {
+    dim: u32,
+}
+
+impl Default for TexAtlas {
+    fn default() -> Self { Self::new(TEX_ATLAS_DIM as u32) }
+}
+
+impl TexAtlas {
+    pub fn new(dim: u32) -> Self { Self { dim } }
+
+    #[inline] pub fn dim(&self) -> u32 { self.dim }
+    #[inline] pub fn uv_dim(&self) -> f32 { 1.0 / self.dim as f32 }
+
+    /// UV of the top left corner of `texture_id`
+    #[inline]
+    pub fn tile_origin(&self, texture_id: u16) -> [f32; 2] {
+        let texture_id = texture_id as u32;
+        [(texture_id % self.dim) as f32 * self.uv_dim(), (texture_id / self.dim) as f32 * self.uv_dim()]
+    }
+
+    /// UVs of `texture_id` in the corner order of `CUBE_UVS`, inset slightly to avoid bleeding from neighbouring tiles.
+    pub fn tile_uvs(&self, texture_id: u16) -> [[f32; 2]; 4] {
+        let origin = self.tile_origin(texture_id);
+        let uv_dim = self.uv_dim();
+        let near = uv_dim * 0.001;
+        let far = uv_dim - near;
+
+        [
+            [origin[0] + far , origin[1] + far ],
+            [origin[0] + near, origin[1] + far ],
+            [origin[0] + far , origin[1] + near],
+            [origin[0] + near, origin[1] + near],
+        ]
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 pub struct MeshData {
     pub verts: Vec<[f32; 3]>,
     pub indices: Vec<u32>,
     pub normals: Vec<[f32; 3]>,
     pub uvs: Vec<[f32; 2]>,
+    /// Optional second UV channel, such as the atlas tile origin of greedy quads
+    pub uvs_1: Vec<[f32; 2]>,
     pub colors: Vec<[f32; 4]>,
 }
 
 impl Default for MeshData {
     fn default() -> Self {
-        Self { verts: vec![], indices: vec![], normals: vec![], uvs: vec![], colors: vec![] }
+        Self { verts: vec![], indices: vec![], normals: vec![], uvs: vec![], uvs_1: vec![], colors: vec![] }
     }
 }
 
@@ -77,16 +121,34 @@ impl MeshData {
         mesh.set_indices(Some(Indices::U32(self.indices.clone())));
         mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals.clone());
         mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs.clone());
+        if !self.uvs_1.is_empty() { mesh.insert_attribute(Mesh::ATTRIBUTE_UV_1, self.uvs_1.clone()); }
         if !self.colors.is_empty() { mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, self.colors.clone()); }
         mesh
     }
 
+    /// Uses only verts & indices. None if there are no triangles.
+    pub fn trimesh_collider(&self) -> Option<Collider> {
+        if self.indices.len() < 3 { return None; }
+        let verts = self.verts.iter().map(|vert| Vec3::from_array(*vert)).collect();
+        let indices = self.indices.chunks_exact(3).map(|tri| [tri[0], tri[1], tri[2]]).collect();
+        Some(Collider::trimesh(verts, indices))
+    }
+
     pub fn add_triangle(&mut self, a: u32, b: u32, c: u32) {
         self.indices.push(a);
         self.indices.push(b);
         self.indices.push(c);
     }
 
+    /// Copy of the vert at `index`, lowered by `depth`, keeping its normal so it is lit like the surface above. Returns its index.
+    fn add_skirt_vert(&mut self, index: u32, depth: f32) -> u32 {
+        let [x, y, z] = self.verts[index as usize];
+        self.verts.push([x, y - depth, z]);
+        self.normals.push(self.normals[index as usize]);
+        self.uvs.push(self.uvs[index as usize]);
+        self.verts.len() as u32 - 1
+    }
+
     pub fn calculate_normals(&mut self) {
         let mut normals = vec![Vec3::ZERO; self.verts.len()];
 
@@ -117,203 +179,93 @@ impl MeshData {
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 pub struct MeshGen;
 impl MeshGen {
-    /// - data: The chunk being meshed
-    /// - data_r: Right neighbor
-    /// - data_f: Front neighbor
-    /// - data_rf: Right & Front neighbor
-    pub fn from_square_heightmap_with_r_f_rf_neighbors(
-        data: &[f32],
-        data_r: &[f32],
-        data_f: &[f32],
-        data_rf: &[f32],
-        dim: u32,
-    ) -> Mesh {
-        let mut mesh_data = MeshData::default();
-        let mesh_dim = dim + 1;
-
-        // Verts
-        for z in 0..dim {
-            for x in 0..dim {
-                // Verts from internal
-                let i = z * dim + x;
-                mesh_data.verts.push([x as f32, data[i as usize], z as f32]);
-            }
-
-            // Verts from left side of right neighbor
-            mesh_data.verts.push([dim as f32, data_r[(z * dim) as usize], z as f32]);
-        }
-
-        // Verts from bottom of front neighbor
-        for x in 0..dim { mesh_data.verts.push([x as f32, data_f[x as usize], dim as f32]); }
-
-        // Vert from bottom left corner of right & front neighbor
-        mesh_data.verts.push([dim as f32, data_rf[0], dim as f32]);
-
-        // UVs
-        for z in 0..mesh_dim { for x in 0..mesh_dim {
-            mesh_data.uvs.push([x as f32 / mesh_dim as f32, z as f32 / mesh_dim as f32]);
-        }}
-
-        for z in 0..dim { for x in 0..dim {
-            let i = z * mesh_dim + x;
-            mesh_data.add_triangle(i, i + mesh_dim, i + 1);
-            mesh_data.add_triangle(i + mesh_dim, i + mesh_dim + 1, i + 1);
-        }}
-
-        mesh_data.calculate_normals();
-        mesh_data.mesh()
-    }
-
-    /// - data: The chunk being meshed
-    /// - data_r: Right neighbor
-    /// - data_f: Front neighbor
-    pub fn from_square_heightmap_with_r_f_neighbors(
-        data: &[f32],
-        data_r: &[f32],
-        data_f: &[f32],
-        dim: u32,
-    ) -> Mesh {
+    /// Meshes the center of a 3x3 neighbourhood of square heightmaps, each `dim` by `dim` & indexed by `z * dim + x`.
+    ///
+    /// `neighborhood` is indexed by `(z + 1) * 3 + (x + 1)` for chunk offsets from -1 to 1, so the center is 4, right is 5 & front is 7.
+    /// Missing neighbours are None, and the center must be Some.
+    ///
+    /// Quads stitching the center to its right & front neighbours are included, so neighbouring meshes meet without seams.
+    /// Normals come from the heights on both sides of each vert, including neighbours, so verts shared by two chunks get the same normal.
+    ///
+    /// - step: Distance between verts, a power of 2 no larger than `dim`. Larger steps are for lower levels of detail.
+    /// - skirt_depth: If above 0, walls this deep hang from every edge, hiding cracks against neighbours meshed with a different step.
+    pub fn from_heightmap_neighborhood(neighborhood: &[Option<&[f32]>; 9], dim: u32, step: u32, skirt_depth: f32) -> MeshData {
         let mut mesh_data = MeshData::default();
-        let mesh_dim = dim + 1;
-
-        // Verts
-        for z in 0..dim {
-            for x in 0..dim {
-                // Verts from internal
-                let i = z * dim + x;
-                mesh_data.verts.push([x as f32, data[i as usize], z as f32]);
-            }
-
-            // Verts from left side of right neighbor
-            mesh_data.verts.push([dim as f32, data_r[(z * dim) as usize], z as f32]);
-        }
-
-        // Verts from bottom of front neighbor
-        for x in 0..dim { mesh_data.verts.push([x as f32, data_f[x as usize], dim as f32]); }
-
-        // UVs
-        for z in 0..mesh_dim { for x in 0..mesh_dim {
-            mesh_data.uvs.push([x as f32 / mesh_dim as f32, z as f32 / mesh_dim as f32]);
+        let dim = dim as i32;
+        let step = (step.max(1).min(dim as u32)) as i32;
+
+        // Height at x & z relative to the center, which may be in a neighbour
+        let height = |x: i32, z: i32| -> Option<f32> {
+            let (chunk_x, chunk_z) = (x.div_euclid(dim), z.div_euclid(dim));
+            if chunk_x.abs() > 1 || chunk_z.abs() > 1 { return None; }
+            let data = neighborhood[((chunk_z + 1) * 3 + chunk_x + 1) as usize]?;
+            Some(data[(z.rem_euclid(dim) * dim + x.rem_euclid(dim)) as usize])
+        };
+
+        // Verts, with an index for each one that exists
+        let mesh_dim = dim / step + 1;
+        let mut vert_indices = vec![None; (mesh_dim * mesh_dim) as usize];
+        for mesh_z in 0..mesh_dim { for mesh_x in 0..mesh_dim {
+            let (x, z) = (mesh_x * step, mesh_z * step);
+            let Some(center) = height(x, z) else { continue };
+
+            // Normals always use the closest heights, so they match whatever step neighbours are meshed with
+            let (left, right) = (height(x - 1, z), height(x + 1, z));
+            let (back, front) = (height(x, z - 1), height(x, z + 1));
+
+            // Central differences, or one sided where a neighbour is missing
+            let slope_x = match (left, right) { (Some(l), Some(r)) => (r - l) * 0.5, (None, Some(r)) => r - center, (Some(l), None) => center - l, _ => 0.0 };
+            let slope_z = match (back, front) { (Some(b), Some(f)) => (f - b) * 0.5, (None, Some(f)) => f - center, (Some(b), None) => center - b, _ => 0.0 };
+
+            vert_indices[(mesh_z * mesh_dim + mesh_x) as usize] = Some(mesh_data.verts.len() as u32);
+            mesh_data.verts.push([x as f32, center, z as f32]);
+            mesh_data.normals.push(Vec3::new(-slope_x, 1.0, -slope_z).normalize().to_array());
+            mesh_data.uvs.push([x as f32 / dim as f32, z as f32 / dim as f32]);
         }}
 
-        // Get rid of last UV because vert doesn't exist
-        mesh_data.uvs.pop();
-
-        // All triangles except rightmost column
-        for z in 0..dim { for x in 0..dim-1 {
-            let i = z * mesh_dim + x;
-            mesh_data.add_triangle(i, i + mesh_dim, i + 1);
-            mesh_data.add_triangle(i + mesh_dim, i + mesh_dim + 1, i + 1);
+        // Triangles, skipping any with a vert in a missing neighbour
+        for mesh_z in 0..mesh_dim - 1 { for mesh_x in 0..mesh_dim - 1 {
+            let i = (mesh_z * mesh_dim + mesh_x) as usize;
+            let [lb, rb, lf, rf] = [i, i + 1, i + mesh_dim as usize, i + mesh_dim as usize + 1].map(|index| vert_indices[index]);
+            if let (Some(lb), Some(lf), Some(rb)) = (lb, lf, rb) { mesh_data.add_triangle(lb, lf, rb); }
+            if let (Some(lf), Some(rf), Some(rb)) = (lf, rf, rb) { mesh_data.add_triangle(lf, rf, rb); }
         }}
 
-        // Triangles in rightmost column except top right corner
-        for z in 0..dim-1 {
-            let i = z * mesh_dim + dim-1;
-            mesh_data.add_triangle(i, i + mesh_dim, i + 1);
-            mesh_data.add_triangle(i + mesh_dim, i + mesh_dim + 1, i + 1);
-        }
-
-        // Triangle in top right corner
-        let i = (dim-1) * mesh_dim + dim-1;
-        mesh_data.add_triangle(i, i + mesh_dim, i + 1);
-
-        mesh_data.calculate_normals();
-        mesh_data.mesh()
-    }
-
-    /// - data: The chunk being meshed
-    /// - data_r: Right neighbor
-    pub fn from_square_heightmap_with_r_neighbor(
-        data: &[f32],
-        data_r: &[f32],
-        dim: u32,
-    ) -> Mesh {
-        let mut mesh_data = MeshData::default();
-        let mesh_dims = UVec2::new(dim+1, dim);
-
-        // Verts
-        for z in 0..dim {
-            for x in 0..dim {
-                // Verts from internal
-                let i = z * dim + x;
-                mesh_data.verts.push([x as f32, data[i as usize], z as f32]);
+        if skirt_depth <= 0.0 { return mesh_data; }
+
+        // Skirts, as (first vert index, vert index step along the edge, whether the wall faces away from increasing mesh coords) for each edge: Left, Right, Back, Front
+        let last = mesh_dim - 1;
+        let edges: [(i32, i32, bool); 4] = [
+            (0, mesh_dim, true),
+            (last, mesh_dim, false),
+            (0, 1, false),
+            (last * mesh_dim, 1, true),
+        ];
+
+        for (start, step, flip) in edges.iter() {
+            let edge_index = |i: i32| (start + i * step) as usize;
+            for i in 0..last {
+                let (Some(a), Some(b)) = (vert_indices[edge_index(i)], vert_indices[edge_index(i + 1)]) else { continue };
+                let skirt_a = mesh_data.add_skirt_vert(a, skirt_depth);
+                let skirt_b = mesh_data.add_skirt_vert(b, skirt_depth);
+
+                if *flip {
+                    mesh_data.add_triangle(a, skirt_a, b);
+                    mesh_data.add_triangle(b, skirt_a, skirt_b);
+                } else {
+                    mesh_data.add_triangle(a, b, skirt_a);
+                    mesh_data.add_triangle(b, skirt_b, skirt_a);
+                }
             }
-
-            // Verts from left side of right neighbor
-            mesh_data.verts.push([dim as f32, data_r[(z * dim) as usize], z as f32]);
         }
 
-        // UVs
-        for z in 0..mesh_dims.y { for x in 0..mesh_dims.x {
-            mesh_data.uvs.push([x as f32 / mesh_dims.x as f32, z as f32 / mesh_dims.y as f32]);
-        }}
-
-        // Triangles
-        for z in 0..mesh_dims.y-1 { for x in 0..mesh_dims.x-1 {
-            let i = z * mesh_dims.x + x;
-            mesh_data.add_triangle(i, i + mesh_dims.x, i + 1);
-            mesh_data.add_triangle(i + mesh_dims.x, i + mesh_dims.x + 1, i + 1);
-        }}
-
-        mesh_data.calculate_normals();
-        mesh_data.mesh()
-    }
-
-    /// - data: The chunk being meshed
-    /// - data_f: Front neighbor
-    pub fn from_square_heightmap_with_f_neighbor(
-        data: &[f32],
-        data_f: &[f32],
-        dim: u32,
-    ) -> Mesh {
-        let mut mesh_data = MeshData::default();
-        let mesh_dims = UVec2::new(dim, dim+1);
-
-        // Verts
-        for z in 0..dim { for x in 0..dim {
-            // Verts from internal
-            let i = z * dim + x;
-            mesh_data.verts.push([x as f32, data[i as usize], z as f32]);
-        }}
-
-        // Verts from bottom of front neighbor
-        for x in 0..dim { mesh_data.verts.push([x as f32, data_f[x as usize], dim as f32]); }
-
-        // UVs
-        for z in 0..mesh_dims.y { for x in 0..mesh_dims.x {
-            mesh_data.uvs.push([x as f32 / mesh_dims.x as f32, z as f32 / mesh_dims.y as f32]);
-        }}
-
-        // Triangles
-        for z in 0..mesh_dims.y-1 { for x in 0..mesh_dims.x-1 {
-            let i = z * mesh_dims.x + x;
-            mesh_data.add_triangle(i, i + mesh_dims.x, i + 1);
-            mesh_data.add_triangle(i + mesh_dims.x, i + mesh_dims.x + 1, i + 1);
-        }}
-
-        mesh_data.calculate_normals();
-        mesh_data.mesh()
+        mesh_data
     }
 
     pub fn from_square_heightmap(data: &[f32], dim: u32) -> Mesh {
-        let mut mesh_data = MeshData::default();
-
-        // Verts & UVs
-        for z in 0..dim { for x in 0..dim {
-            let i = z * dim + x;
-            mesh_data.verts.push([x as f32, data[i as usize], z as f32]);
-            mesh_data.uvs.push([x as f32 / dim as f32, z as f32 / dim as f32]);
-        }}
-
-        // Triangles
-        for z in 0..dim-1 { for x in 0..dim-1 {
-            let i = z * dim + x;
-            mesh_data.add_triangle(i, i + dim, i + 1);
-            mesh_data.add_triangle(i + dim, i + dim + 1, i + 1);
-        }}
-
-        mesh_data.calculate_normals();
-        mesh_data.mesh()
+        let mut neighborhood = [None; 9];
+        neighborhood[4] = Some(data);
+        Self::from_heightmap_neighborhood(&neighborhood, dim, 1, 0.0).mesh()
     }
 
     // pub fn from_flat_sparse_chunk_3d<T: Default + Clone + Copy + Sync + Send + 'static>(
@@ -349,4 +301,115 @@ impl MeshGen {
     //     if mesh_data.is_empty() { return None; }
     //     Some(mesh_data.mesh())
     // }
-}
\ No newline at end of file
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIM: u32 = 8;
+
+    /// Heights of the chunk at `chunk`, from a slope with bumps along both axes so normals differ along every border.
+    fn chunk_heights(chunk: IVec2) -> Vec<f32> {
+        (0..DIM * DIM).map(|index| {
+            let (x, z) = ((index % DIM) as i32 + chunk.x * DIM as i32, (index / DIM) as i32 + chunk.y * DIM as i32);
+            (x as f32 * 0.7).sin() * 2.0 + (z as f32 * 0.5).cos() + z as f32 * 0.3 + (x * z) as f32 * 0.05
+        }).collect()
+    }
+
+    /// Meshes the chunk at `chunk` with all 8 of its neighbours.
+    fn mesh_chunk(chunk: IVec2, step: u32, skirt_depth: f32) -> MeshData {
+        let heights: Vec<Vec<f32>> = (0..9).map(|slot| chunk_heights(chunk + IVec2::new(slot % 3 - 1, slot / 3 - 1))).collect();
+        let neighborhood: [Option<&[f32]>; 9] = std::array::from_fn(|slot| Some(heights[slot].as_slice()));
+        MeshGen::from_heightmap_neighborhood(&neighborhood, DIM, step, skirt_depth)
+    }
+
+    /// Verts of the surface, without skirts, which are added after them.
+    fn surface_verts(mesh_data: &MeshData, step: u32) -> MeshData {
+        let count = ((DIM / step + 1) * (DIM / step + 1)) as usize;
+        MeshData { verts: mesh_data.verts[..count].to_vec(), normals: mesh_data.normals[..count].to_vec(), ..default() }
+    }
+
+    /// Position & normal of every vert where `on_border` is true, sorted by x & z.
+    fn border_verts(mesh_data: &MeshData, on_border: impl Fn(&[f32; 3]) -> bool) -> Vec<([f32; 3], [f32; 3])> {
+        let mut verts: Vec<([f32; 3], [f32; 3])> = mesh_data.verts.iter().zip(mesh_data.normals.iter())
+            .filter(|(vert, _)| on_border(vert))
+            .map(|(vert, normal)| (*vert, *normal))
+            .collect();
+        verts.sort_by(|a, b| a.0[0].total_cmp(&b.0[0]).then(a.0[2].total_cmp(&b.0[2])));
+        verts
+    }
+
+    /// The chunk at the origin & its neighbour at `offset` put the same verts, with the same normals, where they meet.
+    fn assert_seamless(offset: IVec2, step: u32) {
+        let (near, far) = (mesh_chunk(IVec2::ZERO, step, 0.0), mesh_chunk(offset, step, 0.0));
+        let dim = DIM as f32;
+
+        // Along each axis the chunks are offset on, the edge of each chunk facing the other
+        let edge = |offset: i32| if offset > 0 { dim } else { 0.0 };
+        let near_border: Vec<([f32; 3], [f32; 3])> = border_verts(&near, |vert| (offset.x == 0 || vert[0] == edge(offset.x)) && (offset.y == 0 || vert[2] == edge(offset.y)))
+            .into_iter()
+            .map(|(vert, normal)| ([vert[0] - offset.x as f32 * dim, vert[1], vert[2] - offset.y as f32 * dim], normal))
+            .collect();
+        let far_border = border_verts(&far, |vert| (offset.x == 0 || vert[0] == edge(-offset.x)) && (offset.y == 0 || vert[2] == edge(-offset.y)));
+
+        assert!(!near_border.is_empty());
+        assert_eq!(near_border, far_border);
+    }
+
+    #[test]
+    fn adjacent_chunks_share_border_verts() { assert_seamless(IVec2::X, 1); }
+
+    #[test]
+    fn adjacent_chunks_share_border_verts_at_lower_detail() { assert_seamless(IVec2::X, 2); }
+
+    #[test]
+    fn z_neighbors_share_border_verts() {
+        assert_seamless(IVec2::Y, 1);
+        assert_seamless(IVec2::Y, 2);
+    }
+
+    #[test]
+    fn lower_detail_borders_line_up_within_skirts() {
+        let skirt_depth = 2.0;
+        let fine = mesh_chunk(IVec2::ZERO, 1, skirt_depth);
+        let coarse = mesh_chunk(IVec2::X, 2, skirt_depth);
+
+        let fine_border = border_verts(&surface_verts(&fine, 1), |vert| vert[0] == DIM as f32);
+        let coarse_border = border_verts(&surface_verts(&coarse, 2), |vert| vert[0] == 0.0);
+        assert_eq!(fine_border.len(), DIM as usize + 1);
+        assert_eq!(coarse_border.len(), DIM as usize / 2 + 1);
+
+        for (z, (fine_vert, fine_normal)) in fine_border.iter().enumerate() {
+            let coarse_index = z / 2;
+            if z % 2 == 0 {
+                // Every coarse vert is also a fine vert, with the same normal
+                let (coarse_vert, coarse_normal) = coarse_border[coarse_index];
+                assert_eq!([fine_vert[0] - DIM as f32, fine_vert[1], fine_vert[2]], coarse_vert);
+                assert_eq!(*fine_normal, coarse_normal);
+            } else {
+                // Between coarse verts, the crack to the coarse edge is shallower than the skirts covering it
+                let coarse_height = (coarse_border[coarse_index].0[1] + coarse_border[coarse_index + 1].0[1]) * 0.5;
+                assert!((coarse_height - fine_vert[1]).abs() <= skirt_depth, "crack of {} at z {z}", coarse_height - fine_vert[1]);
+            }
+        }
+
+        // Both sides hang skirts down from every vert along the border
+        for (mesh_data, step, x) in [(&fine, 1, DIM as f32), (&coarse, 2, 0.0)] {
+            let surface = border_verts(&surface_verts(mesh_data, step), |vert| vert[0] == x);
+            let skirts = border_verts(mesh_data, |vert| vert[0] == x);
+            for (vert, _) in surface {
+                assert!(skirts.iter().any(|(skirt, _)| *skirt == [vert[0], vert[1] - skirt_depth, vert[2]]), "{vert:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn diagonal_neighbors_share_corner_verts() {
+        for step in [1, 2] {
+            assert_seamless(IVec2::ONE, step);
+            assert_seamless(IVec2::new(1, -1), step);
+        }
+    }
+}
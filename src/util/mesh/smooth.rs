@@ -0,0 +1,218 @@
+use crate::*;
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Corner pairs of the 12 edges of a cell, with corner `i` at `(i & 1, (i >> 1) & 1, (i >> 2) & 1)`.
+const CELL_EDGES: [(usize, usize); 12] = [
+    (0, 1), (2, 3), (4, 5), (6, 7), // x
+    (0, 2), (1, 3), (4, 6), (5, 7), // y
+    (0, 4), (1, 5), (2, 6), (3, 7), // z
+];
+
+#[inline]
+fn cell_corner_offset(corner: usize) -> IVec3 { IVec3::new(corner as i32 & 1, (corner as i32 >> 1) & 1, (corner as i32 >> 2) & 1) }
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+pub struct SmoothVoxelMesher;
+impl SmoothVoxelMesher {
+    /// Meshes the chunk at `chunk_key` with surface nets, treating every value as a density sampled at its coord.
+    ///
+    /// Each chunk adds the quads crossing the sample edges that start inside it, so neighbouring chunks never overlap,
+    /// and verts on chunk borders are computed from the same samples on both sides, so seams are watertight.
+    /// Values in neighbouring chunks up to one voxel away are read, including diagonal neighbours.
+    ///
+    /// Verts are relative to `chunk_key`. Normals come from the density gradient, & UVs are the global x & z of each vert.
+    ///
+    /// Returns None if the surface does not cross the chunk. The chunk does not need to exist, as its neighbours may still cross it.
+    pub fn mesh_chunk_surface_nets<T: DensityData + Default + Clone + Copy + Sync + Send + 'static>(
+        root: &FlatSparseRoot3d<T>,
+        chunk_key: &IVec3,
+    ) -> Option<MeshData> {
+        const DIM: i32 = CHUNK_3D_DIM as i32;
+        // Samples from -1 to DIM, & cells with their min corner from -1 to DIM - 1, along each axis
+        const SAMPLE_DIM: usize = CHUNK_3D_DIM_USIZE + 2;
+        const CELL_DIM: usize = CHUNK_3D_DIM_USIZE + 1;
+
+        let sample_index = |coord: IVec3| ((coord.z + 1) as usize * SAMPLE_DIM + (coord.y + 1) as usize) * SAMPLE_DIM + (coord.x + 1) as usize;
+        let cell_index = |coord: IVec3| ((coord.z + 1) as usize * CELL_DIM + (coord.y + 1) as usize) * CELL_DIM + (coord.x + 1) as usize;
+
+        let mut samples = vec![0.0; SAMPLE_DIM * SAMPLE_DIM * SAMPLE_DIM];
+        for coord in FlatSparseRoot3dMath::box_coords(IVec3::splat(-1), IVec3::splat(DIM)) {
+            samples[sample_index(coord)] = root.get_active_value(&(coord + *chunk_key)).density();
+        }
+
+        let inside_count = samples.iter().filter(|density| **density > 0.0).count();
+        if inside_count == 0 || inside_count == samples.len() { return None; }
+
+        // A vert in every cell the surface crosses, at the average of the crossings on its edges
+        let mut mesh_data = MeshData::default();
+        let mut cell_verts = vec![u32::MAX; CELL_DIM * CELL_DIM * CELL_DIM];
+        for cell in FlatSparseRoot3dMath::box_coords(IVec3::splat(-1), IVec3::splat(DIM - 1)) {
+            let corners: [f32; 8] = std::array::from_fn(|corner| samples[sample_index(cell + cell_corner_offset(corner))]);
+            let corners_inside = corners.iter().filter(|density| **density > 0.0).count();
+            if corners_inside == 0 || corners_inside == 8 { continue; }
+
+            let mut crossing_sum = Vec3::ZERO;
+            let mut crossing_count = 0;
+            for (corner_0, corner_1) in CELL_EDGES {
+                if (corners[corner_0] > 0.0) == (corners[corner_1] > 0.0) { continue; }
+                let t = corners[corner_0] / (corners[corner_0] - corners[corner_1]);
+                crossing_sum += cell_corner_offset(corner_0).as_vec3().lerp(cell_corner_offset(corner_1).as_vec3(), t);
+                crossing_count += 1;
+            }
+
+            let gradient = Vec3::new(
+                corners[1] - corners[0] + corners[3] - corners[2] + corners[5] - corners[4] + corners[7] - corners[6],
+                corners[2] - corners[0] + corners[3] - corners[1] + corners[6] - corners[4] + corners[7] - corners[5],
+                corners[4] - corners[0] + corners[5] - corners[1] + corners[6] - corners[2] + corners[7] - corners[3],
+            );
+
+            let vert = cell.as_vec3() + crossing_sum / crossing_count as f32;
+            cell_verts[cell_index(cell)] = mesh_data.verts.len() as u32;
+            mesh_data.verts.push(vert.to_array());
+            // Density increases into the surface, so normals point down the gradient
+            mesh_data.normals.push((-gradient).try_normalize().unwrap_or(Vec3::Y).to_array());
+            mesh_data.uvs.push([vert.x + chunk_key.x as f32, vert.z + chunk_key.z as f32]);
+        }
+
+        // A quad for every sample edge starting in the chunk that the surface crosses, joining the verts of the 4 cells around it
+        for coord in FlatSparseRoot3dMath::box_coords(IVec3::ZERO, IVec3::splat(DIM - 1)) {
+            let inside = samples[sample_index(coord)] > 0.0;
+
+            for axis in 0..3 {
+                let mut next_coord = coord;
+                next_coord[axis] += 1;
+                if inside == (samples[sample_index(next_coord)] > 0.0) { continue; }
+
+                let (mut b_offset, mut c_offset) = (IVec3::ZERO, IVec3::ZERO);
+                b_offset[(axis + 1) % 3] = 1;
+                c_offset[(axis + 2) % 3] = 1;
+
+                // Counter clockwise around the edge when looking down `axis`
+                let verts = [coord - b_offset - c_offset, coord - c_offset, coord, coord - b_offset].map(|cell| cell_verts[cell_index(cell)]);
+                if inside {
+                    mesh_data.add_triangle(verts[0], verts[1], verts[2]);
+                    mesh_data.add_triangle(verts[0], verts[2], verts[3]);
+                } else {
+                    mesh_data.add_triangle(verts[0], verts[2], verts[1]);
+                    mesh_data.add_triangle(verts[0], verts[3], verts[2]);
+                }
+            }
+        }
+
+        if mesh_data.indices.is_empty() { return None; }
+        Some(mesh_data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::utils::HashMap;
+
+    /// Densities of a sphere, positive inside, set on around it
+    fn sphere_root(center: Vec3, radius: f32) -> FlatSparseRoot3d<f32> {
+        let mut root = FlatSparseRoot3d::new(CHUNK_3D_LOG2DIM as u8, 0.0);
+        let extent = IVec3::splat(radius.ceil() as i32 + 2);
+        for coord in FlatSparseRoot3dMath::box_coords(center.as_ivec3() - extent, center.as_ivec3() + extent) {
+            root.set_value_on(&coord, radius - coord.as_vec3().distance(center));
+        }
+        root
+    }
+
+    fn vert_key(vert: Vec3) -> IVec3 { (vert * 1000.0).round().as_ivec3() }
+
+    /// Global triangles of every chunk, with verts merged by position
+    fn triangles(root: &FlatSparseRoot3d<f32>, chunk_keys: &[IVec3]) -> Vec<[IVec3; 3]> {
+        let mut triangles = Vec::new();
+        for chunk_key in chunk_keys {
+            let Some(mesh_data) = SmoothVoxelMesher::mesh_chunk_surface_nets(root, chunk_key) else { continue };
+            let global = |index: u32| vert_key(Vec3::from_array(mesh_data.verts[index as usize]) + chunk_key.as_vec3());
+            for triangle in mesh_data.indices.chunks_exact(3) { triangles.push([global(triangle[0]), global(triangle[1]), global(triangle[2])]); }
+        }
+        triangles
+    }
+
+    /// Closed & consistently wound: every directed edge is used once, & its reverse once
+    fn assert_closed(triangles: &[[IVec3; 3]]) {
+        assert!(!triangles.is_empty());
+        let mut edges = HashMap::<(IVec3, IVec3), u32>::default();
+        for triangle in triangles {
+            for i in 0..3 { *edges.entry((triangle[i], triangle[(i + 1) % 3])).or_default() += 1; }
+        }
+
+        for ((a, b), count) in edges.iter() {
+            assert_eq!(*count, 1, "edge {a} {b} used {count} times");
+            assert_eq!(edges.get(&(*b, *a)), Some(&1), "edge {a} {b} has no twin");
+        }
+    }
+
+    /// Positive for closed meshes wound counter clockwise from outside
+    fn signed_volume(triangles: &[[IVec3; 3]]) -> f32 {
+        triangles.iter().map(|triangle| {
+            let [a, b, c] = triangle.map(|vert| vert.as_vec3() / 1000.0);
+            a.dot(b.cross(c)) / 6.0
+        }).sum()
+    }
+
+    #[test]
+    fn sphere_gives_closed_mesh() {
+        let (center, radius) = (Vec3::splat(4.0), 2.5);
+        let root = sphere_root(center, radius);
+
+        let triangles = triangles(&root, &[IVec3::ZERO]);
+        assert_closed(&triangles);
+
+        let volume = signed_volume(&triangles);
+        let sphere_volume = 4.0 / 3.0 * std::f32::consts::PI * radius * radius * radius;
+        assert!((volume - sphere_volume).abs() < sphere_volume * 0.2, "{volume} vs {sphere_volume}");
+
+        // The sphere doesn't reach past the chunk's edges
+        for chunk_key in [IVec3::new(-8, 0, 0), IVec3::new(8, 0, 0), IVec3::new(0, 8, 0), IVec3::new(0, 0, -8)] {
+            assert!(SmoothVoxelMesher::mesh_chunk_surface_nets(&root, &chunk_key).is_none());
+        }
+    }
+
+    #[test]
+    fn normals_point_out_of_the_sphere() {
+        let center = Vec3::splat(4.0);
+        let mesh_data = SmoothVoxelMesher::mesh_chunk_surface_nets(&sphere_root(center, 2.5), &IVec3::ZERO).unwrap();
+        for (vert, normal) in mesh_data.verts.iter().zip(mesh_data.normals.iter()) {
+            assert!(Vec3::from_array(*normal).dot(Vec3::from_array(*vert) - center) > 0.0);
+        }
+    }
+
+    #[test]
+    fn empty_and_full_fields_give_no_geometry() {
+        let empty = FlatSparseRoot3d::<f32>::new(CHUNK_3D_LOG2DIM as u8, 0.0);
+        assert!(SmoothVoxelMesher::mesh_chunk_surface_nets(&empty, &IVec3::ZERO).is_none());
+
+        let mut full = FlatSparseRoot3d::<f32>::new(CHUNK_3D_LOG2DIM as u8, 0.0);
+        for coord in FlatSparseRoot3dMath::box_coords(IVec3::splat(-8), IVec3::splat(15)) { full.set_value_on(&coord, 1.0); }
+        assert!(SmoothVoxelMesher::mesh_chunk_surface_nets(&full, &IVec3::ZERO).is_none());
+    }
+
+    #[test]
+    fn adjacent_chunks_share_border_verts() {
+        // Centered on the border between chunks 0 & 8 along x
+        let root = sphere_root(Vec3::new(8.0, 4.0, 4.0), 2.5);
+        let left = SmoothVoxelMesher::mesh_chunk_surface_nets(&root, &IVec3::ZERO).unwrap();
+        let right = SmoothVoxelMesher::mesh_chunk_surface_nets(&root, &IVec3::new(8, 0, 0)).unwrap();
+
+        // Cells from x 7 to 8 are meshed by both chunks
+        let border_verts = |mesh_data: &MeshData, offset: f32| {
+            let mut verts: Vec<IVec3> = mesh_data.verts.iter()
+                .map(|vert| Vec3::from_array(*vert) + Vec3::new(offset, 0.0, 0.0))
+                .filter(|vert| (7.0..=8.0).contains(&vert.x))
+                .map(vert_key)
+                .collect();
+            verts.sort_by_key(|vert| (vert.x, vert.y, vert.z));
+            verts
+        };
+        let left_border = border_verts(&left, 0.0);
+        assert!(!left_border.is_empty());
+        assert_eq!(left_border, border_verts(&right, 8.0));
+
+        // Together they are one closed surface, with no quads doubled on the border
+        assert_closed(&triangles(&root, &[IVec3::ZERO, IVec3::new(8, 0, 0)]));
+    }
+}
@@ -1,4 +1,5 @@
 use crate::*;
+use crate::voxel::GRID_2D_DIRECTIONS;
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 pub const TILE_VERTS: [[f32; 2]; 4] = [
@@ -30,12 +31,13 @@ const TILE_HEIGHT_INDICES: [[u8; 4]; 17] = [
     [2, 1, 1, 0], // 16 Right Front Rhombus
 ];
 
-const TILE_QUAD_TRI_INDICES: [[u8; 6]; 2] = [ // Ramp type &= 1 to get the index
+const TILE_QUAD_TRI_INDICES: [[u8; 6]; 2] = [ // Use tile_tri_indices to get the index
     [0, 2, 1, 1, 2, 3], // Right Back Slope & Left Front Slope
     [2, 3, 0, 0, 3, 1], // Left Back Slope & Right Front Slope
 ];
 
-const TILE_TEMP_TEXTURE_IDS: [u8; 17] = [
+/// Atlas texture id of the top of each shape
+pub const TILE_SHAPE_TEXTURE_IDS: [u16; 17] = [
     0,
     4, 2, 4, 4,
     1, 3, 3, 5,
@@ -43,36 +45,175 @@ const TILE_TEMP_TEXTURE_IDS: [u8; 17] = [
     1, 3, 3, 5,
 ];
 
+/// Atlas texture id of side walls
+pub const TILE_SIDE_TEXTURE_ID: u16 = 6;
+
+/// Shape for each set of raised corners, with bit `i` set if corner `i` of `TILE_VERTS` is raised.
+///
+/// Saddles & tiles with every corner raised are left as cubes, and rhombus shapes are never picked.
+const TILE_AUTO_SHAPES: [u8; 16] = [
+    0,  // None
+    8,  // Left  Back
+    7,  // Right Back
+    3,  // Back
+    6,  // Left  Front
+    1,  // Left
+    0,  // Right Back & Left Front saddle
+    12, // All but Right Front
+    5,  // Right Front
+    0,  // Left Back & Right Front saddle
+    2,  // Right
+    11, // All but Left Front
+    4,  // Front
+    10, // All but Right Back
+    9,  // All but Left Back
+    0,  // All
+];
+
+/// Cube faces of the walls facing each of `GRID_2D_DIRECTIONS`
+const TILE_SIDE_FACES: [usize; 4] = [0, 1, 4, 5];
+
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 pub struct Tile3dMesher;
 impl Tile3dMesher {
+    /// Heights of the 4 corners of a tile, in the order of `TILE_VERTS`. Each height step is half a cube.
+    pub fn corner_heights<T: HeightData + ShapeData>(value: &T) -> [f32; 4] {
+        let base = (value.height() as f32 - 127.0) * 0.5;
+        let height_indices = TILE_HEIGHT_INDICES[value.shape() as usize % TILE_HEIGHT_INDICES.len()];
+        height_indices.map(|height_index| base + TILE_HEIGHT_VALUES[height_index as usize])
+    }
+
+    /// Splits the top quad along the diagonal its shape folds on, so corners & internal corners keep their shape.
+    fn tile_tri_indices(height_indices: &[u8; 4]) -> &'static [u8; 6] {
+        if height_indices[1] == height_indices[2] { &TILE_QUAD_TRI_INDICES[0] }
+        else if height_indices[0] == height_indices[3] { &TILE_QUAD_TRI_INDICES[1] }
+        else { &TILE_QUAD_TRI_INDICES[0] }
+    }
+
+    /// Adds the top of the tile at `global_coord`, with a normal per triangle, and walls down to any lower neighbours.
+    ///
+    /// Verts are relative to the chunk, at `value_coord`.
     pub fn add_tile<T: HeightData + ShapeData + Default + Clone + Copy + Sync + Send + 'static>(
         mesh_data: &mut MeshData,
         value_coord: &IVec2,
         global_coord: &IVec2,
         value: &T,
         root: &FlatSparseRoot2d<T>,
+        atlas: &TexAtlas,
     ) {
-        let shape = value.shape() as usize;
-        let vert_count = mesh_data.verts.len();
-
-        for vert_index in 0..4 {
-            let vert = TILE_VERTS[vert_index];
-            mesh_data.verts.push([
-                vert[0] + value_coord.x as f32,
-                (value.height() as f32 - 127.0) * 0.5 + TILE_HEIGHT_VALUES[TILE_HEIGHT_INDICES[shape][vert_index] as usize],
-                vert[1] + value_coord.y as f32,
-            ]);
-
-            let uv = CUBE_UVS[vert_index];
-            let uv_offset = TEX_ATLAS_UV_DIM * 4.0; // voxel.face_texture_id(face, defs) as f32;
-            let uv_offset_floor = uv_offset.floor() as f32;
-            mesh_data.uvs.push([uv[0] + uv_offset - uv_offset_floor, uv[1] + uv_offset_floor * TEX_ATLAS_UV_DIM as f32]);
+        let shape = value.shape() as usize % TILE_HEIGHT_INDICES.len();
+        let heights = Self::corner_heights(value);
+        let corner_verts: [Vec3; 4] = std::array::from_fn(|corner| {
+            let vert = TILE_VERTS[corner];
+            Vec3::new(vert[0] + value_coord.x as f32, heights[corner], vert[1] + value_coord.y as f32)
+        });
+
+        // Top, with unshared verts so each triangle of a slope can have its own normal
+        let uvs = atlas.tile_uvs(TILE_SHAPE_TEXTURE_IDS[shape]);
+        for tri in Self::tile_tri_indices(&TILE_HEIGHT_INDICES[shape]).chunks_exact(3) {
+            let [a, b, c] = [tri[0] as usize, tri[1] as usize, tri[2] as usize];
+            let normal = (corner_verts[b] - corner_verts[a]).cross(corner_verts[c] - corner_verts[a]).normalize_or_zero();
+
+            for corner in [a, b, c] {
+                mesh_data.indices.push(mesh_data.verts.len() as u32);
+                mesh_data.verts.push(corner_verts[corner].to_array());
+                mesh_data.normals.push(normal.to_array());
+                mesh_data.uvs.push(uvs[corner]);
+            }
         }
 
-        mesh_data.normals.extend(vec![CUBE_NORMALS[3]; 4]);
+        // Side walls, from the neighbour's heights along the shared edge up to this tile's heights
+        let side_uvs = atlas.tile_uvs(TILE_SIDE_TEXTURE_ID);
+        for (direction_index, neighbor) in root.get_adjacent_values(*global_coord).iter().enumerate() {
+            let direction = GRID_2D_DIRECTIONS[direction_index];
+            let neighbor_heights = Self::corner_heights(neighbor);
 
-        for tri_index in 0..6 { mesh_data.indices.push(CUBE_QUAD_INDICES[tri_index] + vert_count as u32); }
+            let mut bottoms = heights;
+            let mut has_wall = false;
+            for corner in 0..4 {
+                let corner_offset = IVec2::new(corner as i32 & 1, corner as i32 >> 1);
+                let neighbor_offset = corner_offset - direction;
+                if neighbor_offset.cmplt(IVec2::ZERO).any() || neighbor_offset.cmpgt(IVec2::ONE).any() { continue; }
+
+                bottoms[corner] = heights[corner].min(neighbor_heights[(neighbor_offset.x + neighbor_offset.y * 2) as usize]);
+                has_wall |= bottoms[corner] < heights[corner];
+            }
+
+            if !has_wall { continue; }
+
+            let face = TILE_SIDE_FACES[direction_index];
+            let vert_count = mesh_data.verts.len();
+            for vert_index in 0..4 {
+                let cube_vert = CUBE_QUAD_VERTS[face][vert_index];
+                let corner = (cube_vert & 1) | ((cube_vert >> 1) & 2);
+                let is_top = cube_vert & 2 != 0;
+
+                let mut vert = corner_verts[corner];
+                if !is_top { vert.y = bottoms[corner]; }
+                mesh_data.verts.push(vert.to_array());
+                mesh_data.normals.push(CUBE_NORMALS[face]);
+                mesh_data.uvs.push(side_uvs[vert_index]);
+            }
+
+            mesh_data.indices.extend(CUBE_QUAD_INDICES.iter().map(|index| index + vert_count as u32));
+        }
+    }
+
+    /// Meshes every tile that is on in the chunk at `chunk_key`. Verts are relative to `chunk_key`.
+    ///
+    /// Returns None if the chunk does not exist or has no tiles on.
+    pub fn mesh_chunk<T: HeightData + ShapeData + Default + Clone + Copy + Sync + Send + 'static>(
+        root: &FlatSparseRoot2d<T>,
+        chunk_key: IVec2,
+        atlas: &TexAtlas,
+    ) -> Option<MeshData> {
+        // Copied so the chunk is not locked while neighbours are read through the root
+        let chunk = root.chunk_from_coord(chunk_key)?.read().unwrap().clone();
+        let mut mesh_data = MeshData::default();
+
+        for index in OnMaskIter::new(0, chunk.active_mask()) {
+            let value_coord = root.value_local_coord_from_index(index);
+            let value = chunk.get_value(index as usize);
+            Self::add_tile(&mut mesh_data, &value_coord, &(value_coord + chunk_key), &value, root, atlas);
+        }
+
+        if mesh_data.is_empty() { return None; }
+        Some(mesh_data)
+    }
+
+    //==============================================================================================
+    /// Ramp or corner shape for the tile at `global_coord`, raising each corner touching a neighbour exactly one height step higher.
+    pub fn auto_shape<T: HeightData + ShapeData + Default + Clone + Copy + Sync + Send + 'static>(root: &FlatSparseRoot2d<T>, global_coord: IVec2) -> u8 {
+        let height = root.get_value(global_coord).height();
+        let is_step_up = |offset: IVec2| height < u8::MAX && root.get_value(global_coord + offset).height() == height + 1;
+
+        let mut raised_corners = 0;
+        for corner in 0..4 {
+            // Offsets to the 3 neighbours sharing the corner
+            let corner_direction = IVec2::new(if corner & 1 == 0 { -1 } else { 1 }, if corner & 2 == 0 { -1 } else { 1 });
+            if is_step_up(IVec2::new(corner_direction.x, 0)) || is_step_up(IVec2::new(0, corner_direction.y)) || is_step_up(corner_direction) {
+                raised_corners |= 1 << corner;
+            }
+        }
+
+        TILE_AUTO_SHAPES[raised_corners]
+    }
+
+    /// Sets the shape of every tile that is on in the chunk at `chunk_key` with `auto_shape`.
+    ///
+    /// Shapes only depend on heights, so neighbouring chunks can be shaped in any order.
+    pub fn auto_shape_chunk<T: HeightData + ShapeData + Default + Clone + Copy + Sync + Send + 'static>(root: &mut FlatSparseRoot2d<T>, chunk_key: IVec2) {
+        let Some(chunk) = root.chunk_from_coord(chunk_key) else { return };
+        let indices: Vec<u32> = OnMaskIter::new(0, chunk.read().unwrap().active_mask()).collect();
+
+        let shaped: Vec<(IVec2, T)> = indices.into_iter().map(|index| {
+            let global_coord = root.value_local_coord_from_index(index) + chunk_key;
+            let mut value = root.get_value(global_coord);
+            value.set_shape(Self::auto_shape(root, global_coord));
+            (global_coord, value)
+        }).collect();
+
+        for (global_coord, value) in shaped { root.set_value_on(global_coord, value); }
     }
 }
 
@@ -97,7 +238,7 @@ impl Tile3dMesher {
 //             ]);
 
 //             let uv = CUBE_UVS[vert_index];
-//             let uv_offset = TEX_ATLAS_UV_DIM * TILE_TEMP_TEXTURE_IDS[shape] as f32; // voxel.face_texture_id(face, defs) as f32;
+//             let uv_offset = TEX_ATLAS_UV_DIM * TILE_SHAPE_TEXTURE_IDS[shape] as f32; // voxel.face_texture_id(face, defs) as f32;
 //             let uv_offset_floor = uv_offset.floor() as f32;
 //             mesh_data.base.uvs.push([uv[0] + uv_offset - uv_offset_floor, uv[1] + uv_offset_floor * TEX_ATLAS_UV_DIM as f32]);
 //         }
@@ -106,4 +247,167 @@ impl Tile3dMesher {
 
 //         for tri_index in 0..6 { mesh_data.base.indices.push(CUBE_QUAD_INDICES[tri_index] + vert_count as u32); }
 //     }
-// }
\ No newline at end of file
+// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default, Clone, Copy, PartialEq, Debug)]
+    struct Tile {
+        height: u8,
+        shape: u8,
+    }
+
+    impl HeightData for Tile { fn height(&self) -> u8 { self.height } }
+    impl ShapeData for Tile {
+        fn shape(&self) -> u8 { self.shape }
+        fn set_shape(&mut self, shape: u8) { self.shape = shape; }
+    }
+
+    fn tile(height: u8, shape: u8) -> Tile { Tile { height, shape } }
+
+    /// Every tile around the ones set is flat at height 127, so at y 0
+    fn root_with(tiles: &[(IVec2, Tile)]) -> FlatSparseRoot2d<Tile> {
+        let mut root = FlatSparseRoot2d::new(4, tile(127, 0));
+        let dim = CHUNK_2D_DIM as i32;
+        for z in -dim..dim {
+            for x in -dim..dim { root.set_value_on(IVec2::new(x, z), tile(127, 0)); }
+        }
+        for (coord, value) in tiles { root.set_value_on(*coord, *value); }
+        root
+    }
+
+    fn mesh_tile(root: &FlatSparseRoot2d<Tile>, coord: IVec2) -> MeshData {
+        let mut mesh_data = MeshData::default();
+        Tile3dMesher::add_tile(&mut mesh_data, &coord, &coord, &root.get_value(coord), root, &TexAtlas::new(4));
+        mesh_data
+    }
+
+    /// Normals of the walls after the 6 verts of the top, one per wall
+    fn wall_normals(mesh_data: &MeshData) -> Vec<[f32; 3]> {
+        mesh_data.normals[6..].chunks_exact(4).map(|normals| normals[0]).collect()
+    }
+
+    #[test]
+    fn auto_shapes_raise_exactly_the_raised_corners() {
+        for (raised_corners, shape) in TILE_AUTO_SHAPES.iter().enumerate() {
+            let expected: [u8; 4] = std::array::from_fn(|corner| (raised_corners >> corner) as u8 & 1);
+            match raised_corners {
+                0b0110 | 0b1001 | 0b1111 => assert_eq!(*shape, 0, "{raised_corners:04b}"),
+                _ => assert_eq!(TILE_HEIGHT_INDICES[*shape as usize], expected, "{raised_corners:04b}"),
+            }
+        }
+    }
+
+    #[test]
+    fn corner_heights_follow_shapes() {
+        let cases: [(Tile, [f32; 4]); 7] = [
+            (tile(127, 0), [0.0, 0.0, 0.0, 0.0]),
+            (tile(128, 0), [0.5, 0.5, 0.5, 0.5]),
+            (tile(127, 1), [0.5, 0.0, 0.5, 0.0]),
+            (tile(127, 4), [0.0, 0.0, 0.5, 0.5]),
+            (tile(129, 8), [1.5, 1.0, 1.0, 1.0]),
+            (tile(127, 13), [0.0, 0.5, 0.5, 1.0]),
+            // Out of range shapes wrap around
+            (tile(126, 17), [-0.5, -0.5, -0.5, -0.5]),
+        ];
+
+        for (value, heights) in cases { assert_eq!(Tile3dMesher::corner_heights(&value), heights, "{value:?}"); }
+    }
+
+    #[test]
+    fn auto_shape_steps_up_to_higher_neighbours() {
+        let cases: [(&[IVec2], u8); 7] = [
+            (&[], 0),
+            (&[IVec2::new(-1, 0)], 1),
+            (&[IVec2::new(0, 1)], 4),
+            (&[IVec2::new(1, 1)], 5),
+            (&[IVec2::new(-1, 0), IVec2::new(0, -1)], 12),
+            // Saddles stay flat
+            (&[IVec2::new(-1, -1), IVec2::new(1, 1)], 0),
+            (&[IVec2::new(-1, 0), IVec2::new(1, 0)], 0),
+        ];
+
+        for (raised, shape) in cases {
+            let tiles: Vec<(IVec2, Tile)> = raised.iter().map(|offset| (*offset, tile(128, 0))).collect();
+            assert_eq!(Tile3dMesher::auto_shape(&root_with(&tiles), IVec2::ZERO), shape, "{raised:?}");
+        }
+
+        // Only neighbours exactly one step higher count
+        assert_eq!(Tile3dMesher::auto_shape(&root_with(&[(IVec2::new(-1, 0), tile(129, 0))]), IVec2::ZERO), 0);
+        assert_eq!(Tile3dMesher::auto_shape(&root_with(&[(IVec2::ZERO, tile(255, 0)), (IVec2::new(-1, 0), tile(0, 0))]), IVec2::ZERO), 0);
+    }
+
+    #[test]
+    fn auto_shape_chunk_makes_ramps_meet_the_step() {
+        let mut root = root_with(&[(IVec2::new(1, 0), tile(128, 0))]);
+        Tile3dMesher::auto_shape_chunk(&mut root, IVec2::ZERO);
+        assert_eq!(root.get_value(IVec2::ZERO).shape, 2);
+        assert_eq!(root.get_value(IVec2::new(1, 0)).shape, 0);
+
+        // The ramp rises to the step, so there is no wall between them
+        let mesh_data = mesh_tile(&root, IVec2::ZERO);
+        assert!(!wall_normals(&mesh_data).contains(&CUBE_NORMALS[1]));
+    }
+
+    #[test]
+    fn flat_tiles_have_no_walls_between_equal_neighbours() {
+        let mesh_data = mesh_tile(&root_with(&[]), IVec2::ZERO);
+        assert_eq!(mesh_data.verts.len(), 6);
+        assert_eq!(mesh_data.indices.len(), 6);
+        assert!(mesh_data.normals.iter().all(|normal| *normal == CUBE_NORMALS[3]));
+        assert!(mesh_data.verts.iter().all(|vert| vert[1] == 0.0));
+    }
+
+    #[test]
+    fn raised_tiles_have_walls_down_to_each_neighbour() {
+        let root = root_with(&[(IVec2::ZERO, tile(129, 0)), (IVec2::new(0, 1), tile(128, 0))]);
+        let mesh_data = mesh_tile(&root, IVec2::ZERO);
+
+        assert_eq!(mesh_data.verts.len(), 6 + 4 * 4);
+        assert_eq!(mesh_data.indices.len(), 6 + 4 * 6);
+        assert_eq!(wall_normals(&mesh_data), vec![CUBE_NORMALS[0], CUBE_NORMALS[1], CUBE_NORMALS[4], CUBE_NORMALS[5]]);
+
+        // From the top at 1 down to each neighbour's height, in the plane of the shared edge
+        for (wall, verts) in mesh_data.verts[6..].chunks_exact(4).enumerate() {
+            let bottom = if wall == 3 { 0.5 } else { 0.0 };
+            let mut heights: Vec<f32> = verts.iter().map(|vert| vert[1]).collect();
+            heights.sort_by(f32::total_cmp);
+            assert_eq!(heights, vec![bottom, bottom, 1.0, 1.0], "wall {wall}");
+
+            let axis = if wall < 2 { 0 } else { 2 };
+            let side = if wall % 2 == 0 { -CUBE_HALF_DIM } else { CUBE_HALF_DIM };
+            assert!(verts.iter().all(|vert| vert[axis] == side), "wall {wall}");
+        }
+    }
+
+    #[test]
+    fn slopes_have_a_normal_per_triangle_and_partial_walls() {
+        // A right ramp, raised along x, between flat tiles
+        let mesh_data = mesh_tile(&root_with(&[(IVec2::ZERO, tile(127, 2))]), IVec2::ZERO);
+        let slope_normal = Vec3::new(-0.5, 1.0, 0.0).normalize();
+        for normal in &mesh_data.normals[..6] { assert!(Vec3::from_array(*normal).distance(slope_normal) < 0.0001); }
+
+        // The raised right edge & the raised corners of the back & front edges are above their neighbours, the left edge is not
+        assert_eq!(wall_normals(&mesh_data), vec![CUBE_NORMALS[1], CUBE_NORMALS[4], CUBE_NORMALS[5]]);
+    }
+
+    #[test]
+    fn corner_slopes_fold_along_their_diagonal() {
+        // Only the right front corner is raised, so the fold must leave the other 3 corners on one flat triangle
+        let mesh_data = mesh_tile(&root_with(&[(IVec2::ZERO, tile(127, 5))]), IVec2::ZERO);
+        let flat = mesh_data.normals[..6].chunks_exact(3).filter(|normals| normals[0] == CUBE_NORMALS[3]).count();
+        assert_eq!(flat, 1);
+    }
+
+    #[test]
+    fn mesh_chunk_meshes_tiles_that_are_on() {
+        let root = root_with(&[(IVec2::new(3, 3), tile(128, 0))]);
+        let atlas = TexAtlas::new(4);
+
+        // Every tile's top, & only the raised tile has walls
+        let mesh_data = Tile3dMesher::mesh_chunk(&root, IVec2::ZERO, &atlas).unwrap();
+        assert_eq!(mesh_data.indices.len(), CHUNK_2D_SIZE * 6 + 4 * 6);
+        assert!(Tile3dMesher::mesh_chunk(&root, IVec2::splat(64), &atlas).is_none());
+    }
+}
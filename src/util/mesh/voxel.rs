@@ -1,50 +1,322 @@
 use crate::*;
 
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Tangent axes `(u, v)` of the quads on each axis, with `v` pointing up on side faces.
+const GREEDY_QUAD_TANGENTS: [(usize, usize); 3] = [(2, 1), (0, 2), (0, 1)];
+
+/// Meshes of a single chunk built from voxel defs. Empty meshes have no verts.
+#[derive(Default)]
+pub struct VoxelChunkMeshData {
+    pub opaque: MeshData,
+    /// Faces of transparent voxels, to be drawn with alpha blending
+    pub transparent: MeshData,
+    /// Verts & indices only, with faces of collidable voxels that touch non-collidable ones
+    pub collider: MeshData,
+}
+
+impl VoxelChunkMeshData {
+    pub fn is_empty(&self) -> bool { self.opaque.is_empty() && self.transparent.is_empty() && self.collider.is_empty() }
+}
+
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 pub struct VoxelMesher;
 impl VoxelMesher {
-    pub fn add_cube<T: Default + Clone + Copy + Sync + Send + 'static>(
+    pub fn add_cube<T: VoxelData + PartialEq + Default + Clone + Copy + Sync + Send + 'static>(
         mesh_data: &mut MeshData,
         voxel_coord: &IVec3,
         chunk_coord: &IVec3,
         root: &FlatSparseRoot3d<T>,
+        defs: &[VoxelDef],
+        atlas: &TexAtlas,
     ) {
         let global_coord = *voxel_coord + *chunk_coord;
-        let neighbors = root.get_adjacent_values(&global_coord);
-    
+        let value = root.get_active_value(&global_coord);
+        let neighbors = root.get_adjacent_active_values(&global_coord);
+
         for face in 0..6 {
-            // if neighbors[face as usize] != 0 { continue; } // Opaque Test
+            if !Self::is_face_visible(&value, &neighbors[face], root.background(), defs) { continue; }
 
             let vert_count = mesh_data.verts.len();
-    
+            let uvs = atlas.tile_uvs(value.face_texture_id(face, defs));
+
             for vert_index in 0..4 {
-                let vert = CUBE_VERTS[CUBE_QUAD_VERTS[face as usize][vert_index]];
+                let vert = CUBE_VERTS[CUBE_QUAD_VERTS[face][vert_index]];
                 mesh_data.verts.push([
                     vert[0] + voxel_coord.x as f32,
                     vert[1] + voxel_coord.y as f32,
                     vert[2] + voxel_coord.z as f32,
                 ]);
-    
-                let uv = CUBE_UVS[vert_index];
-                let uv_offset = TEX_ATLAS_UV_DIM * 0.0; // voxel.face_texture_id(face, defs) as f32;
-                let uv_offset_floor = uv_offset.floor() as f32;
-                mesh_data.uvs.push([
-                    uv[0] + uv_offset - uv_offset_floor,
-                    uv[1] + uv_offset_floor * TEX_ATLAS_UV_DIM as f32,
-                ]);
+
+                mesh_data.uvs.push(uvs[vert_index]);
             }
-    
-            for tri_index in 0..6 { mesh_data.indices.push(CUBE_QUAD_INDICES[tri_index] + vert_count as u32); }
+
+            mesh_data.indices.extend(CUBE_QUAD_INDICES.iter().map(|index| index + vert_count as u32));
         }
     }
 
-    pub fn add_cube_with_normals<T: Default + Clone + Copy + Sync + Send + 'static>(
+    pub fn add_cube_with_normals<T: VoxelData + PartialEq + Default + Clone + Copy + Sync + Send + 'static>(
         mesh_data: &mut MeshData,
         voxel_coord: &IVec3,
         chunk_coord: &IVec3,
         root: &FlatSparseRoot3d<T>,
+        defs: &[VoxelDef],
+        atlas: &TexAtlas,
     ) {
-        Self::add_cube(mesh_data, voxel_coord, chunk_coord, root);
-        for face in 0..6 { mesh_data.normals.extend(vec![CUBE_NORMALS[face as usize]; 4]); }
+        let global_coord = *voxel_coord + *chunk_coord;
+        let value = root.get_active_value(&global_coord);
+        let neighbors = root.get_adjacent_active_values(&global_coord);
+
+        Self::add_cube(mesh_data, voxel_coord, chunk_coord, root, defs, atlas);
+        for face in 0..6 {
+            if !Self::is_face_visible(&value, &neighbors[face], root.background(), defs) { continue; }
+            mesh_data.normals.extend([CUBE_NORMALS[face]; 4]);
+        }
+    }
+
+    /// Background neighbours show every face. Opaque neighbours hide it, and transparent voxels hide faces between voxels of the same def.
+    pub fn is_face_visible<T: VoxelData + PartialEq>(value: &T, neighbor: &T, background: T, defs: &[VoxelDef]) -> bool {
+        if *neighbor == background { return true; }
+        if neighbor.is_opaque(defs) { return false; }
+        value.voxel_def_id() != neighbor.voxel_def_id()
+    }
+
+    //==============================================================================================
+    /// Meshes the chunk at `chunk_key`, merging coplanar faces of equal values into single quads.
+    ///
+    /// Faces are only added where the neighbouring value is the background, including neighbours in other chunks.
+    /// Verts are relative to `chunk_key`. UVs are in voxels, so textures repeat once per voxel across merged quads & need a repeating sampler.
+    ///
+    /// Returns None if the chunk does not exist or has no visible faces.
+    pub fn mesh_chunk_greedy<T: PartialEq + Default + Clone + Copy + Sync + Send + 'static>(
+        root: &FlatSparseRoot3d<T>,
+        chunk_key: &IVec3,
+    ) -> Option<MeshData> {
+        let background = root.background();
+        let mut mesh_data = MeshData::default();
+
+        Self::greedy_quads(
+            root,
+            chunk_key,
+            |value, neighbor, _| if *neighbor == background { Some(*value) } else { None },
+            |_, face, slice, min, size| Self::add_quad(&mut mesh_data, face, slice, min, size),
+        )?;
+
+        if mesh_data.is_empty() { return None; }
+        Some(mesh_data)
+    }
+
+    /// Meshes the chunk at `chunk_key` using `defs`, merging coplanar faces with the same texture into single quads.
+    ///
+    /// Faces are culled with `is_face_visible`. Verts are relative to `chunk_key`.
+    /// UV 0 is in voxels & UV 1 is the atlas tile origin of the quad, so atlas materials should sample at `uv_1 + fract(uv_0) * atlas.uv_dim()`.
+    ///
+    /// Returns None if the chunk does not exist.
+    pub fn mesh_chunk_greedy_with_defs<T: VoxelData + PartialEq + Default + Clone + Copy + Sync + Send + 'static>(
+        root: &FlatSparseRoot3d<T>,
+        chunk_key: &IVec3,
+        defs: &[VoxelDef],
+        atlas: &TexAtlas,
+    ) -> Option<VoxelChunkMeshData> {
+        let background = root.background();
+        let mut chunk_mesh_data = VoxelChunkMeshData::default();
+
+        Self::greedy_quads(
+            root,
+            chunk_key,
+            |value, neighbor, face| {
+                if !Self::is_face_visible(value, neighbor, background, defs) { return None; }
+                Some((value.face_texture_id(face, defs), value.is_transparent(defs)))
+            },
+            |(texture_id, transparent), face, slice, min, size| {
+                let mesh_data = if transparent { &mut chunk_mesh_data.transparent } else { &mut chunk_mesh_data.opaque };
+                Self::add_quad(mesh_data, face, slice, min, size);
+                mesh_data.uvs_1.extend([atlas.tile_origin(texture_id); 4]);
+            },
+        )?;
+
+        Self::greedy_quads(
+            root,
+            chunk_key,
+            |value, neighbor, _| {
+                let neighbor_collidable = *neighbor != background && neighbor.is_collidable(defs);
+                if value.is_collidable(defs) && !neighbor_collidable { Some(()) } else { None }
+            },
+            |_, face, slice, min, size| Self::add_collider_quad(&mut chunk_mesh_data.collider, face, slice, min, size),
+        )?;
+
+        Some(chunk_mesh_data)
+    }
+
+    /// Calls `add_quad(key, face, slice, min, size)` for the widest, then tallest, rectangles of faces in the chunk at `chunk_key` that share a key.
+    ///
+    /// `face_key(value, neighbor, face)` returns None for faces that should not be added. Voxels equal to the background never have faces.
+    ///
+    /// Returns None if the chunk does not exist.
+    fn greedy_quads<T: PartialEq + Default + Clone + Copy + Sync + Send + 'static, K: PartialEq + Copy>(
+        root: &FlatSparseRoot3d<T>,
+        chunk_key: &IVec3,
+        face_key: impl Fn(&T, &T, usize) -> Option<K>,
+        mut add_quad: impl FnMut(K, usize, usize, UVec2, UVec2),
+    ) -> Option<()> {
+        const DIM: usize = CHUNK_3D_DIM_USIZE;
+
+        // Copied so the chunk is not locked while neighbours are read through the root
+        let chunk = root.chunk_from_coord(chunk_key)?.read().unwrap().clone();
+        let background = root.background();
+
+        // Key of every face, indexed by face then by voxel
+        let mut face_keys = [[None; CHUNK_3D_SIZE]; 6];
+        for index in OnMaskIter::new(0, chunk.active_mask()) {
+            let value = chunk.get_value(index as usize);
+            if value == background { continue; }
+
+            let global_coord = root.value_local_coord_from_index(index) + *chunk_key;
+            let neighbors = root.get_adjacent_active_values(&global_coord);
+            for face in 0..6 { face_keys[face][index as usize] = face_key(&value, &neighbors[face], face); }
+        }
+
+        for (face, keys) in face_keys.iter().enumerate() {
+            let axis = face >> 1;
+            let (u_axis, v_axis) = GREEDY_QUAD_TANGENTS[axis];
+
+            for slice in 0..DIM {
+                let mut mask = [None; DIM * DIM];
+                for v in 0..DIM { for u in 0..DIM {
+                    let mut coord = IVec3::ZERO;
+                    coord[axis] = slice as i32;
+                    coord[u_axis] = u as i32;
+                    coord[v_axis] = v as i32;
+                    mask[v * DIM + u] = keys[root.value_index_from_coord(&coord) as usize];
+                }}
+
+                for v in 0..DIM {
+                    let mut u = 0;
+                    while u < DIM {
+                        let Some(key) = mask[v * DIM + u] else { u += 1; continue };
+
+                        let mut width = 1;
+                        while u + width < DIM && mask[v * DIM + u + width] == Some(key) { width += 1; }
+
+                        let mut height = 1;
+                        'grow: while v + height < DIM {
+                            for du in 0..width { if mask[(v + height) * DIM + u + du] != Some(key) { break 'grow; } }
+                            height += 1;
+                        }
+
+                        for dv in 0..height { for du in 0..width { mask[(v + dv) * DIM + u + du] = None; } }
+                        add_quad(key, face, slice, UVec2::new(u as u32, v as u32), UVec2::new(width as u32, height as u32));
+                        u += width;
+                    }
+                }
+            }
+        }
+
+        Some(())
+    }
+
+    /// Adds a `face` quad on `slice` covering `size` voxels from `min`, keeping the winding of `CUBE_QUAD_VERTS`.
+    fn add_quad(mesh_data: &mut MeshData, face: usize, slice: usize, min: UVec2, size: UVec2) {
+        let vert_count = mesh_data.verts.len();
+
+        for vert_index in 0..4 {
+            let (vert, uv) = Self::quad_vert(face, vert_index, slice, min, size);
+            mesh_data.verts.push(vert);
+            mesh_data.normals.push(CUBE_NORMALS[face]);
+            mesh_data.uvs.push(uv);
+        }
+
+        mesh_data.indices.extend(CUBE_QUAD_INDICES.iter().map(|index| index + vert_count as u32));
+    }
+
+    fn add_collider_quad(mesh_data: &mut MeshData, face: usize, slice: usize, min: UVec2, size: UVec2) {
+        let vert_count = mesh_data.verts.len();
+        for vert_index in 0..4 { mesh_data.verts.push(Self::quad_vert(face, vert_index, slice, min, size).0); }
+        mesh_data.indices.extend(CUBE_QUAD_INDICES.iter().map(|index| index + vert_count as u32));
+    }
+
+    /// Position & UV, in voxels, of corner `vert_index` of a greedy quad.
+    fn quad_vert(face: usize, vert_index: usize, slice: usize, min: UVec2, size: UVec2) -> ([f32; 3], [f32; 2]) {
+        let axis = face >> 1;
+        let (u_axis, v_axis) = GREEDY_QUAD_TANGENTS[axis];
+        let corner = CUBE_VERTS[CUBE_QUAD_VERTS[face][vert_index]];
+        let u_offset = if corner[u_axis] < 0.0 { 0.0 } else { size.x as f32 };
+        let v_offset = if corner[v_axis] < 0.0 { 0.0 } else { size.y as f32 };
+
+        let mut vert = [0.0; 3];
+        vert[axis] = slice as f32 + corner[axis];
+        vert[u_axis] = min.x as f32 - CUBE_HALF_DIM + u_offset;
+        vert[v_axis] = min.y as f32 - CUBE_HALF_DIM + v_offset;
+
+        (vert, [u_offset, size.y as f32 - v_offset])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn root_with(values: &[(IVec3, u16)]) -> FlatSparseRoot3d<u16> {
+        let mut root = FlatSparseRoot3d::new(4, 0);
+        for (coord, value) in values { root.set_value_on(coord, *value); }
+        root
+    }
+
+    fn quad_count(mesh_data: &MeshData) -> usize { mesh_data.indices.len() / 6 }
+
+    /// Background, two opaque materials & a transparent one
+    fn defs() -> Vec<VoxelDef> {
+        vec![
+            VoxelDef::MISSING,
+            VoxelDef::uniform(1),
+            VoxelDef::uniform(2),
+            VoxelDef { opaque: false, transparent: true, ..VoxelDef::uniform(3) },
+        ]
+    }
+
+    fn mesh_with_defs(root: &FlatSparseRoot3d<u16>) -> VoxelChunkMeshData {
+        VoxelMesher::mesh_chunk_greedy_with_defs(root, &IVec3::ZERO, &defs(), &TexAtlas::new(4)).unwrap()
+    }
+
+    /// The chunk at the origin meshed by `mesh_chunk_greedy`, & the opaque mesh of `mesh_chunk_greedy_with_defs`, which should agree for opaque voxels.
+    fn mesh_both(values: &[(IVec3, u16)]) -> [MeshData; 2] {
+        let root = root_with(values);
+        [VoxelMesher::mesh_chunk_greedy(&root, &IVec3::ZERO).unwrap(), mesh_with_defs(&root).opaque]
+    }
+
+    #[test]
+    fn full_leaf_gives_6_quads() {
+        let values: Vec<(IVec3, u16)> = FlatSparseRoot3dMath::box_coords(IVec3::ZERO, IVec3::splat(CHUNK_3D_DIM as i32 - 1)).map(|coord| (coord, 1)).collect();
+        for mesh_data in mesh_both(&values) { assert_eq!(quad_count(&mesh_data), 6); }
+    }
+
+    #[test]
+    fn single_voxel_gives_6_quads() {
+        for mesh_data in mesh_both(&[(IVec3::new(3, 4, 5), 1)]) { assert_eq!(quad_count(&mesh_data), 6); }
+    }
+
+    #[test]
+    fn adjacent_voxels_of_different_materials_are_not_merged() {
+        for same in mesh_both(&[(IVec3::ZERO, 1), (IVec3::X, 1)]) { assert_eq!(quad_count(&same), 6); }
+
+        // Both ends, plus 4 sides that can't merge, & no face between them
+        for different in mesh_both(&[(IVec3::ZERO, 1), (IVec3::X, 2)]) { assert_eq!(quad_count(&different), 10); }
+    }
+
+    #[test]
+    fn solid_neighbor_chunk_culls_border_face() {
+        let border = IVec3::new(CHUNK_3D_DIM as i32 - 1, 0, 0);
+        assert_eq!(root_with(&[(border, 1), (border + IVec3::X, 2)]).get_adjacent_active_values(&border)[1], 2);
+
+        for mesh_data in mesh_both(&[(border, 1), (border + IVec3::X, 2)]) {
+            assert_eq!(quad_count(&mesh_data), 5);
+            assert!(!mesh_data.normals.contains(&CUBE_NORMALS[1]));
+        }
+    }
+
+    #[test]
+    fn transparent_voxels_show_opaque_neighbors() {
+        let chunk_mesh_data = mesh_with_defs(&root_with(&[(IVec3::ZERO, 3), (IVec3::X, 1)]));
+        assert_eq!(quad_count(&chunk_mesh_data.opaque), 6);
+        assert_eq!(quad_count(&chunk_mesh_data.transparent), 5);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,292 @@
+use crate::*;
+
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Upgrades data saved at `from_version` to `from_version + 1`.
+pub type DataMigrationFn = fn(ron::Value) -> ron::Value;
+
+pub trait DataMigrationAppExt {
+    /// Registers a migration from `from_version` to `from_version + 1`. The current version of `T` is one past the highest registered `from_version`.
+    fn register_data_migration<T: Serialize + for<'a> Deserialize<'a> + Sync + Send + 'static>(&mut self, from_version: u32, migration: DataMigrationFn) -> &mut Self;
+    /// Whether files of `T` that were migrated on load are saved again at the current version.
+    /// Files with enum variants or struct names are never rewritten, see [DataMigrations].
+    fn rewrite_migrated_data<T: Serialize + for<'a> Deserialize<'a> + Sync + Send + 'static>(&mut self, rewrite_in_place: bool) -> &mut Self;
+}
+
+impl DataMigrationAppExt for App {
+    fn register_data_migration<T: Serialize + for<'a> Deserialize<'a> + Sync + Send + 'static>(&mut self, from_version: u32, migration: DataMigrationFn) -> &mut Self {
+        self.world.get_resource_or_insert_with(DataMigrations::<T>::default).add(from_version, migration);
+        self
+    }
+
+    fn rewrite_migrated_data<T: Serialize + for<'a> Deserialize<'a> + Sync + Send + 'static>(&mut self, rewrite_in_place: bool) -> &mut Self {
+        self.world.get_resource_or_insert_with(DataMigrations::<T>::default).rewrite_in_place = rewrite_in_place;
+        self
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+#[derive(Serialize)]
+struct VersionedRef<'a, T> {
+    version: u32,
+    data: &'a T,
+}
+
+#[derive(Deserialize)]
+struct Versioned<T> {
+    version: u32,
+    data: T,
+}
+
+/// Parses only the version of an envelope, ignoring the data.
+#[derive(Deserialize)]
+struct VersionHeader {
+    version: u32,
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Reads & writes RON files of `T` wrapped in a `(version: u32, data: T)` envelope, upgrading old files through registered migrations.
+///
+/// Files without an envelope are version 0, and version 0 is written without one, so existing files stay valid until the first migration is registered.
+///
+/// Migrations operate on `ron::Value`, which does not keep struct or enum variant names. Files already at the current version are deserialized directly.
+/// As the names can't be recovered, files that have any are not rewritten in place, even with `rewrite_in_place` set, so they are never saved with the wrong variant.
+#[derive(Resource)]
+pub struct DataMigrations<T: Serialize + for<'a> Deserialize<'a> + Sync + Send + 'static> {
+    migrations: BTreeMap<u32, DataMigrationFn>,
+    pub rewrite_in_place: bool,
+    phantom_data: PhantomData<T>,
+}
+
+impl<T: Serialize + for<'a> Deserialize<'a> + Sync + Send + 'static> Default for DataMigrations<T> {
+    fn default() -> Self { Self { migrations: BTreeMap::new(), rewrite_in_place: false, phantom_data: PhantomData } }
+}
+
+impl<T: Serialize + for<'a> Deserialize<'a> + Sync + Send + 'static> Clone for DataMigrations<T> {
+    fn clone(&self) -> Self { Self { migrations: self.migrations.clone(), rewrite_in_place: self.rewrite_in_place, phantom_data: PhantomData } }
+}
+
+impl<T: Serialize + for<'a> Deserialize<'a> + Sync + Send + 'static> std::fmt::Debug for DataMigrations<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DataMigrations")
+            .field("versions", &self.migrations.keys().collect::<Vec<_>>())
+            .field("rewrite_in_place", &self.rewrite_in_place)
+            .finish()
+    }
+}
+
+impl<T: Serialize + for<'a> Deserialize<'a> + Sync + Send + 'static> DataMigrations<T> {
+    /// Replaces any migration already registered for `from_version`.
+    pub fn add(&mut self, from_version: u32, migration: DataMigrationFn) {
+        self.migrations.insert(from_version, migration);
+    }
+
+    pub fn current_version(&self) -> u32 {
+        if let Some(from_version) = self.migrations.keys().last() { *from_version + 1 } else { 0 }
+    }
+
+    //==============================================================================================
+    /// - `depth_limit` max indentations of the data, not counting the envelope
+    pub fn to_ron_string(&self, data: &T, depth_limit: usize) -> ron::Result<String> {
+        let version = self.current_version();
+        if version == 0 {
+            ron::ser::to_string_pretty(data, ron::ser::PrettyConfig::new().depth_limit(depth_limit))
+        } else {
+            ron::ser::to_string_pretty(&VersionedRef { version, data }, ron::ser::PrettyConfig::new().depth_limit(depth_limit + 1))
+        }
+    }
+
+    /// `path` is only used for error reporting.
+    ///
+    /// Returns the data upgraded to the current version, and the version it was saved at.
+    pub fn from_ron_str(&self, contents: &str, path: &str) -> SerialResult<(T, u32)> {
+        let header = ron::from_str::<VersionHeader>(contents).ok();
+        let version = if let Some(header) = &header { header.version } else { 0 };
+        let current_version = self.current_version();
+
+        if version == current_version {
+            let data = if header.is_some() {
+                ron::from_str::<Versioned<T>>(contents).map(|versioned| versioned.data)
+            } else {
+                ron::from_str::<T>(contents)
+            };
+
+            return data.map(|data| (data, version)).map_err(|error| SerialError::ron_parse(path, error));
+        }
+
+        if version > current_version {
+            return Err(SerialError::Migration { path: path.to_owned(), version, message: format!("newer than current version {current_version}") });
+        }
+
+        let mut value: ron::Value = ron::from_str(contents).map_err(|error| SerialError::ron_parse(path, error))?;
+        if header.is_some() {
+            let ron::Value::Map(mut map) = value else {
+                return Err(SerialError::Migration { path: path.to_owned(), version, message: "envelope is not a map".to_owned() });
+            };
+
+            let Some(data) = map.remove(&ron::Value::String("data".to_owned())) else {
+                return Err(SerialError::Migration { path: path.to_owned(), version, message: "envelope has no data".to_owned() });
+            };
+
+            value = data;
+        }
+
+        for from_version in version..current_version {
+            let Some(migration) = self.migrations.get(&from_version) else {
+                return Err(SerialError::Migration { path: path.to_owned(), version, message: format!("no migration registered from version {from_version}") });
+            };
+
+            value = migration(value);
+        }
+
+        let data = value.into_rust::<T>().map_err(|error| SerialError::Migration { path: path.to_owned(), version, message: error.to_string() })?;
+        Ok((data, version))
+    }
+
+    //==============================================================================================
+    /// - `directory` should not end with `/`
+    /// - `file_name` can contain directories, but should not start with `/`, and the end should be only the name of the desired file
+    /// - `depth_limit` max indentations of the data, not counting the envelope
+    pub fn save_ron_file<S0: AsRef<str>, S1: AsRef<str>>(&self, data: &T, directory: S0, file_name: S1, depth_limit: usize) -> SerialResult<()> {
+        let path = Serial::path_string(directory, file_name, "ron");
+        let contents = self.to_ron_string(data, depth_limit).map_err(|error| SerialError::RonSerialize { path: path.clone(), error })?;
+        Serial::write_file_and_path_checked(&path, contents.as_bytes())
+    }
+
+    /// Files older than the current version are rewritten in place if `rewrite_in_place` is set, using `depth_limit`, unless they have enum variants or struct names.
+    /// - `directory` should not end with `/`
+    /// - `file_name` can contain directories, but should not start with `/`, and the end should be only the name of the desired file
+    pub fn load_ron_file<S0: AsRef<str>, S1: AsRef<str>>(&self, directory: S0, file_name: S1, depth_limit: usize) -> SerialResult<T> {
+        let path = Serial::path_string(directory.as_ref(), file_name.as_ref(), "ron");
+        let contents = Serial::get_string_from_path_checked(&path)?;
+        let (data, version) = self.from_ron_str(&contents, &path)?;
+
+        if self.rewrite_in_place && version != self.current_version() {
+            if Self::has_names(&contents) {
+                warn!("{path} has enum variants or struct names, which migrations can't keep, so it was not rewritten at version {}", self.current_version());
+            } else {
+                self.save_ron_file(&data, directory, file_name, depth_limit)?;
+            }
+        }
+
+        Ok(data)
+    }
+
+    /// Whether `contents` has identifiers other than field names, `Some`, `None`, `true`, `false`, `inf` & `NaN`, such as enum variants or struct names.
+    /// Strings, chars & comments are skipped.
+    fn has_names(contents: &str) -> bool {
+        let chars: Vec<char> = contents.chars().collect();
+        let next_non_whitespace = |from: usize| chars[from..].iter().copied().find(|c| !c.is_whitespace());
+        let mut i = 0;
+
+        while i < chars.len() {
+            let c = chars[i];
+            match c {
+                '"' | '\'' => {
+                    // Up to the closing quote, skipping escaped characters
+                    i += 1;
+                    while i < chars.len() && chars[i] != c { i += if chars[i] == '\\' { 2 } else { 1 }; }
+                    i += 1;
+                }
+                // Extensions, `#![enable(...)]`
+                '#' if chars.get(i + 1) == Some(&'!') => { while i < chars.len() && chars[i] != ']' { i += 1; } }
+                '/' if chars.get(i + 1) == Some(&'/') => { while i < chars.len() && chars[i] != '\n' { i += 1; } }
+                '/' if chars.get(i + 1) == Some(&'*') => {
+                    i += 2;
+                    while i < chars.len() && !(chars[i] == '*' && chars.get(i + 1) == Some(&'/')) { i += 1; }
+                    i += 2;
+                }
+                // Numbers, including hex & exponents
+                c if c.is_ascii_digit() => { while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.') { i += 1; } }
+                c if c.is_alphabetic() || c == '_' => {
+                    let start = i;
+                    while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') { i += 1; }
+                    let identifier: String = chars[start..i].iter().collect();
+
+                    match (identifier.as_str(), chars.get(i)) {
+                        // Byte strings are skipped as strings on the next loop
+                        ("b", Some('"' | '\'')) => {}
+                        // Raw strings, `r#"..."#` with any number of #
+                        ("r" | "br", Some('"' | '#')) => {
+                            let hashes = chars[i..].iter().take_while(|c| **c == '#').count();
+                            let end: Vec<char> = std::iter::once('"').chain(std::iter::repeat_n('#', hashes)).collect();
+                            i += hashes + 1;
+                            while i < chars.len() && !chars[i..].starts_with(&end) { i += 1; }
+                            i += end.len();
+                        }
+                        ("Some" | "None" | "true" | "false" | "inf" | "NaN", _) => {}
+                        _ => { if next_non_whitespace(i) != Some(':') { return true; } }
+                    }
+                }
+                _ => { i += 1; }
+            }
+        }
+
+        false
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Renamed {
+        new_name: u32,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct Inner {
+        x: u32,
+    }
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug)]
+    struct RenamedWithInner {
+        new_name: u32,
+        inner: Inner,
+    }
+
+    fn rename_field(value: ron::Value) -> ron::Value {
+        let ron::Value::Map(mut map) = value else { return value };
+        if let Some(old) = map.remove(&ron::Value::String("old_name".to_owned())) { map.insert(ron::Value::String("new_name".to_owned()), old); }
+        ron::Value::Map(map)
+    }
+
+    fn migrations<T: Serialize + for<'a> Deserialize<'a> + Sync + Send + 'static>() -> DataMigrations<T> {
+        let mut migrations = DataMigrations::<T>::default();
+        migrations.add(0, rename_field);
+        migrations.rewrite_in_place = true;
+        migrations
+    }
+
+    #[test]
+    fn names_are_found_outside_strings_and_comments() {
+        assert!(!DataMigrations::<Renamed>::has_names("#![enable(implicit_some)]\n(version: 1, data: (a: Some(1.0e-3), b: None, c: \"Variant(x)\", d: [true, inf]))"));
+        assert!(!DataMigrations::<Renamed>::has_names("(a: r#\"Raw \"quoted\"\"#, b: 'c', /* Variant */ c: 0x1F) // Variant"));
+        assert!(DataMigrations::<Renamed>::has_names("(mode: Smooth)"));
+        assert!(DataMigrations::<Renamed>::has_names("(shape: Circle(radius: 1.0))"));
+        assert!(DataMigrations::<Renamed>::has_names("Inner(x: 1)"));
+    }
+
+    #[test]
+    fn migrated_files_are_rewritten_unless_they_have_names() {
+        let directory = std::env::temp_dir().join(format!("tank_migration_{}", std::process::id()));
+        let directory = directory.to_str().unwrap();
+        Serial::write_file_and_path_checked(Serial::path_string(directory, "plain", "ron"), b"(old_name: 3)").unwrap();
+        Serial::write_file_and_path_checked(Serial::path_string(directory, "named", "ron"), b"(old_name: 3, inner: Inner(x: 4))").unwrap();
+
+        let plain = migrations::<Renamed>().load_ron_file(directory, "plain", 1);
+        let named = migrations::<RenamedWithInner>().load_ron_file(directory, "named", 1);
+        let plain_contents = Serial::get_string_from_path_checked(Serial::path_string(directory, "plain", "ron"));
+        let named_contents = Serial::get_string_from_path_checked(Serial::path_string(directory, "named", "ron"));
+        let _ = std::fs::remove_dir_all(directory);
+
+        assert_eq!(plain.unwrap(), Renamed { new_name: 3 });
+        assert_eq!(named.unwrap(), RenamedWithInner { new_name: 3, inner: Inner { x: 4 } });
+        assert!(plain_contents.unwrap().contains("version: 1"));
+        assert_eq!(named_contents.unwrap(), "(old_name: 3, inner: Inner(x: 4))");
+    }
+}
@@ -11,6 +11,8 @@ mod math;
 pub use math::*;
 mod mesh;
 pub use mesh::*;
+mod migration;
+pub use migration::*;
 mod noise;
 pub use self::noise::*;
 mod serial;
@@ -1,50 +1,216 @@
-use std::{fs::*, io::*, path::Path, str::FromStr};
+use std::{fs::*, io::*, path::Path, str::FromStr, sync::Arc, time::SystemTime};
 use serde::*;
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
+pub type SerialResult<T> = std::result::Result<T, SerialError>;
+
+/// Every error carries the path of the file or directory that caused it.
+#[derive(Clone, Debug)]
+pub enum SerialError {
+    /// The IO error is shared so that reports holding it can be cloned.
+    Io { path: String, error: Arc<std::io::Error> },
+    /// Failed to parse RON. `line` & `column` start at 1.
+    RonParse { path: String, error: ron::Error, line: usize, column: usize },
+    RonSerialize { path: String, error: ron::Error },
+    /// Bincode errors are shared so that reports holding them can be cloned.
+    Bincode { path: String, error: Arc<bincode::Error> },
+    /// Failed to upgrade data saved at `version` to the current version.
+    Migration { path: String, version: u32, message: String },
+    /// Failed to write a deserialized scene into a world.
+    Scene { path: String, message: String },
+    /// The file does not match the layout expected of it, such as a bad header or truncated contents.
+    Corrupt { path: String, message: String },
+    EmptyFile { path: String },
+    /// The path has no parent directory.
+    InvalidPath { path: String },
+}
+
+impl SerialError {
+    pub fn io<P: AsRef<Path>>(path: P, error: std::io::Error) -> Self {
+        Self::Io { path: path.as_ref().display().to_string(), error: Arc::new(error) }
+    }
+
+    pub fn ron_parse<P: AsRef<Path>>(path: P, error: ron::error::SpannedError) -> Self {
+        Self::RonParse { path: path.as_ref().display().to_string(), error: error.code, line: error.position.line, column: error.position.col }
+    }
+
+    pub fn bincode<P: AsRef<Path>>(path: P, error: bincode::Error) -> Self {
+        Self::Bincode { path: path.as_ref().display().to_string(), error: Arc::new(error) }
+    }
+
+    pub fn path(&self) -> &str {
+        match self {
+            Self::Io { path, .. } | Self::RonParse { path, .. } | Self::RonSerialize { path, .. } | Self::Bincode { path, .. } | Self::Migration { path, .. } | Self::Scene { path, .. } | Self::Corrupt { path, .. } | Self::EmptyFile { path } | Self::InvalidPath { path } => { path.as_str() }
+        }
+    }
+
+    /// Only parse errors have a position.
+    pub fn line_column(&self) -> Option<(usize, usize)> {
+        if let Self::RonParse { line, column, .. } = self { Some((*line, *column)) } else { None }
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        if let Self::Io { error, .. } = self { error.kind() == ErrorKind::NotFound } else { false }
+    }
+}
+
+impl std::fmt::Display for SerialError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io { path, error } => write!(f, "{path}: {error}"),
+            Self::RonParse { path, error, line, column } => write!(f, "{path}:{line}:{column}: {error}"),
+            Self::RonSerialize { path, error } => write!(f, "{path}: failed to serialize: {error}"),
+            Self::Bincode { path, error } => write!(f, "{path}: {error}"),
+            Self::Migration { path, version, message } => write!(f, "{path}: failed to migrate from version {version}: {message}"),
+            Self::Scene { path, message } => write!(f, "{path}: failed to spawn scene: {message}"),
+            Self::Corrupt { path, message } => write!(f, "{path}: corrupt: {message}"),
+            Self::EmptyFile { path } => write!(f, "{path}: file is empty"),
+            Self::InvalidPath { path } => write!(f, "{path}: path has no parent directory"),
+        }
+    }
+}
+
+impl std::error::Error for SerialError {}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// A way of turning serde types into file contents & back. `path` is only used for error reporting.
+pub trait SerialFormat {
+    /// Without a `.`
+    fn extension(&self) -> &'static str;
+    fn serialize<T: Serialize>(&self, data: &T, path: &str) -> SerialResult<Vec<u8>>;
+    fn deserialize<T: for<'a> Deserialize<'a>>(&self, bytes: &[u8], path: &str) -> SerialResult<T>;
+}
+
+/// Human readable authoring format
+#[derive(Clone, Copy, Debug)]
+pub struct RonFormat {
+    /// Max indentations
+    pub depth_limit: usize,
+}
+
+impl Default for RonFormat {
+    fn default() -> Self { Self { depth_limit: 1 } }
+}
+
+impl RonFormat {
+    pub fn new(depth_limit: usize) -> Self { Self { depth_limit } }
+}
+
+impl SerialFormat for RonFormat {
+    fn extension(&self) -> &'static str { "ron" }
+
+    fn serialize<T: Serialize>(&self, data: &T, path: &str) -> SerialResult<Vec<u8>> {
+        let contents = ron::ser::to_string_pretty(data, ron::ser::PrettyConfig::new().depth_limit(self.depth_limit))
+            .map_err(|error| SerialError::RonSerialize { path: path.to_owned(), error })?;
+        Ok(contents.into_bytes())
+    }
+
+    fn deserialize<T: for<'a> Deserialize<'a>>(&self, bytes: &[u8], path: &str) -> SerialResult<T> {
+        ron::de::from_bytes(bytes).map_err(|error| SerialError::ron_parse(path, error))
+    }
+}
+
+/// Compact shipping format. Does not support self-describing serde features such as untagged enums.
+#[derive(Clone, Copy, Default, Debug)]
+pub struct BincodeFormat;
+
+impl SerialFormat for BincodeFormat {
+    fn extension(&self) -> &'static str { "bin" }
+
+    fn serialize<T: Serialize>(&self, data: &T, path: &str) -> SerialResult<Vec<u8>> {
+        bincode::serialize(data).map_err(|error| SerialError::bincode(path, error))
+    }
+
+    fn deserialize<T: for<'a> Deserialize<'a>>(&self, bytes: &[u8], path: &str) -> SerialResult<T> {
+        bincode::deserialize(bytes).map_err(|error| SerialError::bincode(path, error))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Every function has a `_checked` variant returning `SerialResult`. The unchecked variants discard the error.
 pub struct Serial;
 impl Serial {
     /// Assumes that the last element in path is a file
     pub fn create_file_path<P: AsRef<Path>>(path: P) -> bool {
-        let Some(prefix) = path.as_ref().parent() else { println!("Failed to get prefix path for {}", path.as_ref().display()); return false };
-        if std::fs::create_dir_all(prefix).is_ok() { true } else { println!("Failed to create path for {}", prefix.display()); false }
+        match Self::create_file_path_checked(path) {
+            Ok(()) => true,
+            Err(error) => { println!("Failed to create path: {error}"); false }
+        }
+    }
+
+    /// Assumes that the last element in path is a file
+    pub fn create_file_path_checked<P: AsRef<Path>>(path: P) -> SerialResult<()> {
+        let Some(prefix) = path.as_ref().parent() else { return Err(SerialError::InvalidPath { path: path.as_ref().display().to_string() }) };
+        std::fs::create_dir_all(prefix).map_err(|error| SerialError::io(prefix, error))
     }
 
     /// Assumes that the last element in path is a directory
     pub fn create_directory_path<P: AsRef<Path>>(path: P) -> bool {
-        if std::fs::create_dir_all(path.as_ref()).is_ok() { true } else { println!("Failed to create path for {}", path.as_ref().display()); false }
+        match Self::create_directory_path_checked(path) {
+            Ok(()) => true,
+            Err(error) => { println!("Failed to create path: {error}"); false }
+        }
     }
 
-    //==============================================================================================
-    fn type_to_ron_string_with_depth_limit<T: Serialize>(data: &T, depth_limit: usize) -> ron::Result<String> {
-        ron::ser::to_string_pretty(data, ron::ser::PrettyConfig::new().depth_limit(depth_limit))
+    /// Assumes that the last element in path is a directory
+    pub fn create_directory_path_checked<P: AsRef<Path>>(path: P) -> SerialResult<()> {
+        std::fs::create_dir_all(path.as_ref()).map_err(|error| SerialError::io(path, error))
     }
 
+    //==============================================================================================
     /// Returns false if the file fails to create or to write
     fn try_write_file<P: AsRef<Path>>(path: P, bytes: &[u8]) -> bool {
-        let Ok(mut file) = File::create(path) else { return false };
-        if file.write_all(bytes).is_ok() { true } else { false }
+        Self::write_file_checked(path, bytes).is_ok()
+    }
+
+    pub fn write_file_checked<P: AsRef<Path>>(path: P, bytes: &[u8]) -> SerialResult<()> {
+        let mut file = File::create(path.as_ref()).map_err(|error| SerialError::io(&path, error))?;
+        file.write_all(bytes).map_err(|error| SerialError::io(&path, error))
     }
 
     /// Returns false if the file fails to create or to write
     fn try_write_file_and_path<P: AsRef<Path>>(path: P, bytes: &[u8]) -> bool {
-        Self::create_file_path(&path);
-        Self::try_write_file(path, bytes)
+        Self::write_file_and_path_checked(path, bytes).is_ok()
+    }
+
+    pub fn write_file_and_path_checked<P: AsRef<Path>>(path: P, bytes: &[u8]) -> SerialResult<()> {
+        Self::create_file_path_checked(&path)?;
+        Self::write_file_checked(path, bytes)
     }
 
     //==============================================================================================
     pub fn try_get_bytes_from_path<P: AsRef<Path>>(path: P) -> Option<Vec<u8>> {
-        let mut buf_reader = if let Ok(file) = File::open(path) { BufReader::new(file) } else { return None };
+        Self::get_bytes_from_path_checked(path).ok()
+    }
+
+    /// Empty files are an error
+    pub fn get_bytes_from_path_checked<P: AsRef<Path>>(path: P) -> SerialResult<Vec<u8>> {
+        let file = File::open(path.as_ref()).map_err(|error| SerialError::io(&path, error))?;
         let mut bytes: Vec<u8> = vec![];
-        if buf_reader.read_to_end(&mut bytes).is_err() { return None; }
-        if bytes.len() != 0 { Some(bytes) } else { None }
+        BufReader::new(file).read_to_end(&mut bytes).map_err(|error| SerialError::io(&path, error))?;
+        if !bytes.is_empty() { Ok(bytes) } else { Err(SerialError::EmptyFile { path: path.as_ref().display().to_string() }) }
     }
     
     pub fn try_get_string_from_path<P: AsRef<Path>>(path: P) -> Option<String> {
-        let mut buf_reader = if let Ok(file) = File::open(path) { BufReader::new(file) } else { return None };
+        Self::get_string_from_path_checked(path).ok()
+    }
+
+    /// Empty files are an error
+    pub fn get_string_from_path_checked<P: AsRef<Path>>(path: P) -> SerialResult<String> {
+        let file = File::open(path.as_ref()).map_err(|error| SerialError::io(&path, error))?;
         let mut contents = String::new();
-        if buf_reader.read_to_string(&mut contents).is_err() { return None; }
-        if contents.as_str().len() != 0 { Some(contents) } else { None }
+        BufReader::new(file).read_to_string(&mut contents).map_err(|error| SerialError::io(&path, error))?;
+        if !contents.is_empty() { Ok(contents) } else { Err(SerialError::EmptyFile { path: path.as_ref().display().to_string() }) }
+    }
+
+    /// Returns None if the file does not exist or the platform does not support modification times
+    pub fn try_get_modified_time<P: AsRef<Path>>(path: P) -> Option<SystemTime> {
+        Self::get_modified_time_checked(path).ok()
+    }
+
+    pub fn get_modified_time_checked<P: AsRef<Path>>(path: P) -> SerialResult<SystemTime> {
+        let metadata = std::fs::metadata(path.as_ref()).map_err(|error| SerialError::io(&path, error))?;
+        metadata.modified().map_err(|error| SerialError::io(&path, error))
     }
 
     //==============================================================================================
@@ -60,15 +226,26 @@ impl Serial {
     /// - `file_name` can contain directories, but should not start with `/`, and the end should be only the name of the desired file
     /// - `depth_limit` max indentations
     pub fn save_type_to_ron_file<S0: AsRef<str>, S1: AsRef<str>, T: Serialize>(data: &T, directory: S0, file_name: S1, depth_limit: usize) {
-        let Ok(contents) = Self::type_to_ron_string_with_depth_limit(data, depth_limit) else { return };
-        Self::try_write_file_and_path(&Self::path_string(directory, file_name, "ron"), contents.as_bytes());
+        if let Err(error) = Self::save_type_to_ron_file_checked(data, directory, file_name, depth_limit) { println!("Failed to save: {error}"); }
+    }
+
+    /// - `directory` should not end with `/`
+    /// - `file_name` can contain directories, but should not start with `/`, and the end should be only the name of the desired file
+    /// - `depth_limit` max indentations
+    pub fn save_type_to_ron_file_checked<S0: AsRef<str>, S1: AsRef<str>, T: Serialize>(data: &T, directory: S0, file_name: S1, depth_limit: usize) -> SerialResult<()> {
+        Self::save_type_to_file_checked(&RonFormat::new(depth_limit), data, directory, file_name)
     }
 
     /// - `directory` should not end with `/`
     /// - `file_name` can contain directories, but should not start with `/`, and the end should be only the name of the desired file
     pub fn load_type_from_ron_file<S0: AsRef<str>, S1: AsRef<str>, T: for<'a> Deserialize<'a>>(directory: S0, file_name: S1) -> Option<T> {
-        let Some(contents) = Self::load_string_from_ron_file(directory, file_name) else { return None };
-        if let Ok(data) = ron::from_str(&contents) { Some(data) } else { None }
+        Self::load_type_from_ron_file_checked(directory, file_name).ok()
+    }
+
+    /// - `directory` should not end with `/`
+    /// - `file_name` can contain directories, but should not start with `/`, and the end should be only the name of the desired file
+    pub fn load_type_from_ron_file_checked<S0: AsRef<str>, S1: AsRef<str>, T: for<'a> Deserialize<'a>>(directory: S0, file_name: S1) -> SerialResult<T> {
+        Self::load_type_from_file_checked(&RonFormat::default(), directory, file_name)
     }
 
     /// - `directory` should not end with `/`
@@ -77,10 +254,49 @@ impl Serial {
         Self::try_get_string_from_path(&Self::path_string(directory, file_name, "ron"))
     }
 
+    /// - `directory` should not end with `/`
+    /// - `file_name` can contain directories, but should not start with `/`, and the end should be only the name of the desired file
+    pub fn load_string_from_ron_file_checked<S0: AsRef<str>, S1: AsRef<str>>(directory: S0, file_name: S1) -> SerialResult<String> {
+        Self::get_string_from_path_checked(Self::path_string(directory, file_name, "ron"))
+    }
+
     /// - `directory` should not end with `/`
     /// - `file_name` can contain directories, but should not start with `/`, and the end should be only the name of the desired file
     pub fn remove_ron_file<S0: AsRef<str>, S1: AsRef<str>>(directory: S0, file_name: S1) {
-        std::fs::remove_file(&Self::path_string(directory, file_name, "ron"));
+        Self::remove_ron_file_checked(directory, file_name).ok();
+    }
+
+    /// - `directory` should not end with `/`
+    /// - `file_name` can contain directories, but should not start with `/`, and the end should be only the name of the desired file
+    pub fn remove_ron_file_checked<S0: AsRef<str>, S1: AsRef<str>>(directory: S0, file_name: S1) -> SerialResult<()> {
+        let path = Self::path_string(directory, file_name, "ron");
+        std::fs::remove_file(&path).map_err(|error| SerialError::io(&path, error))
+    }
+
+    //==============================================================================================
+    /// The extension is taken from `format`.
+    /// - `directory` should not end with `/`
+    /// - `file_name` can contain directories, but should not start with `/`, and the end should be only the name of the desired file
+    pub fn save_type_to_file_checked<F: SerialFormat, S0: AsRef<str>, S1: AsRef<str>, T: Serialize>(format: &F, data: &T, directory: S0, file_name: S1) -> SerialResult<()> {
+        let path = Self::path_string(directory, file_name, format.extension());
+        let bytes = format.serialize(data, &path)?;
+        Self::write_file_and_path_checked(&path, &bytes)
+    }
+
+    /// The extension is taken from `format`.
+    /// - `directory` should not end with `/`
+    /// - `file_name` can contain directories, but should not start with `/`, and the end should be only the name of the desired file
+    pub fn load_type_from_file_checked<F: SerialFormat, S0: AsRef<str>, S1: AsRef<str>, T: for<'a> Deserialize<'a>>(format: &F, directory: S0, file_name: S1) -> SerialResult<T> {
+        let path = Self::path_string(directory, file_name, format.extension());
+        let bytes = Self::get_bytes_from_path_checked(&path)?;
+        format.deserialize(&bytes, &path)
+    }
+
+    /// Serializes, deserializes & serializes `data` again, returning whether both serializations are identical.
+    pub fn verify_round_trip<F: SerialFormat, T: Serialize + for<'a> Deserialize<'a>>(format: &F, data: &T) -> SerialResult<bool> {
+        let bytes = format.serialize(data, "<memory>")?;
+        let round_trip: T = format.deserialize(&bytes, "<memory>")?;
+        Ok(format.serialize(&round_trip, "<memory>")? == bytes)
     }
 
     //==============================================================================================
@@ -94,18 +310,27 @@ impl Serial {
     /// 
     /// Returns `(Vec<DirectoryPaths>, Vec<FilePaths>)`
     pub fn paths_from_directory<S0: AsRef<str>, S1: AsRef<str>>(base_directory: S0, search_directory: S1) -> (Vec<String>, Vec<String>) {
+        Self::paths_from_directory_checked(base_directory, search_directory).unwrap_or_default()
+    }
+
+    /// `base_directory` will be added to the beginning of `search_directory`, but excluded from the paths returned.
+    /// 
+    /// Fails only if the directory itself can't be read. Unreadable entries are skipped.
+    /// 
+    /// Returns `(Vec<DirectoryPaths>, Vec<FilePaths>)`
+    pub fn paths_from_directory_checked<S0: AsRef<str>, S1: AsRef<str>>(base_directory: S0, search_directory: S1) -> SerialResult<(Vec<String>, Vec<String>)> {
         let (mut directories, mut files) = (vec![], vec![]);
-        
-        if let Ok(entries) = read_dir(&(base_directory.as_ref().to_owned() + "/" + search_directory.as_ref())) {
-            for entry in entries {
-                let Ok(entry) = entry else { continue };
-                let Some(entry_name) = Self::try_get_entry_name(&entry) else { continue };
-                let path = search_directory.as_ref().to_owned() + "/" + &entry_name;
-                if entry.path().is_dir() { directories.push(path); } else { files.push(path); }
-            }
+        let full_directory = base_directory.as_ref().to_owned() + "/" + search_directory.as_ref();
+        let entries = read_dir(&full_directory).map_err(|error| SerialError::io(&full_directory, error))?;
+
+        for entry in entries {
+            let Ok(entry) = entry else { continue };
+            let Some(entry_name) = Self::try_get_entry_name(&entry) else { continue };
+            let path = search_directory.as_ref().to_owned() + "/" + &entry_name;
+            if entry.path().is_dir() { directories.push(path); } else { files.push(path); }
         }
 
-        (directories, files)
+        Ok((directories, files))
     }
 
     /// `base_directory` will be added to the beginning of `search_directory`, but excluded from the paths returned.
@@ -113,9 +338,21 @@ impl Serial {
         Self::paths_from_directory(base_directory, search_directory).1
     }
 
+    /// `base_directory` will be added to the beginning of `search_directory`, but excluded from the paths returned.
+    pub fn file_paths_from_directory_checked<S0: AsRef<str>, S1: AsRef<str>>(base_directory: S0, search_directory: S1) -> SerialResult<Vec<String>> {
+        Ok(Self::paths_from_directory_checked(base_directory, search_directory)?.1)
+    }
+
     /// `base_directory` will be added to the beginning of `search_directory`, but excluded from the paths returned.
     pub fn file_paths_from_directory_recursive<S0: AsRef<str>, S1: AsRef<str>>(base_directory: S0, search_directory: S1) -> Vec<String> {
-        let (mut directories, mut files) = Self::paths_from_directory(base_directory.as_ref(), search_directory.as_ref());
+        Self::file_paths_from_directory_recursive_checked(base_directory, search_directory).unwrap_or_default()
+    }
+
+    /// `base_directory` will be added to the beginning of `search_directory`, but excluded from the paths returned.
+    /// 
+    /// Fails only if `search_directory` itself can't be read. Unreadable child directories are skipped.
+    pub fn file_paths_from_directory_recursive_checked<S0: AsRef<str>, S1: AsRef<str>>(base_directory: S0, search_directory: S1) -> SerialResult<Vec<String>> {
+        let (mut directories, mut files) = Self::paths_from_directory_checked(base_directory.as_ref(), search_directory.as_ref())?;
 
         loop {
             let Some(child_directory) = directories.pop() else { break };
@@ -124,6 +361,6 @@ impl Serial {
             files.extend(new_files);
         }
 
-        files
+        Ok(files)
     }
 }
\ No newline at end of file
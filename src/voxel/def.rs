@@ -0,0 +1,29 @@
+use crate::*;
+
+use serde::{Deserialize, Serialize};
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Loaded from `assets/data/voxels/*.ron` through `DataAssets<VoxelDef>`, and looked up by [VoxelData].
+#[derive(Serialize, Deserialize, Clone, Debug, Reflect)]
+pub struct VoxelDef {
+    /// Atlas tile of each face, ordered like `GRID_3D_DIRECTIONS`
+    pub face_texture_ids: [u16; 6],
+    /// Hides the faces of neighbouring voxels that touch it.
+    pub opaque: bool,
+    /// Meshed separately so it can be drawn with alpha blending. Faces between two voxels of the same def are hidden.
+    pub transparent: bool,
+    /// Included in chunk colliders.
+    pub collidable: bool,
+}
+
+impl Default for VoxelDef {
+    fn default() -> Self { Self::MISSING }
+}
+
+impl VoxelDef {
+    /// Used for ids without a def.
+    pub const MISSING: Self = Self { face_texture_ids: [0; 6], opaque: true, transparent: false, collidable: true };
+
+    /// The same atlas tile on every face.
+    pub fn uniform(texture_id: u16) -> Self { Self { face_texture_ids: [texture_id; 6], ..Self::MISSING } }
+}
@@ -48,45 +48,216 @@ impl<T: Default + Clone + Copy + Sync + Send + 'static> Leaf2d<T> {
 }
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Most bits a palette index can use. Chunks needing more distinct values are stored dense.
+const LEAF_3D_MAX_PALETTE_BITS: u32 = 8;
+
+/// How a [Leaf3d] currently stores its values.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Leaf3dStorage {
+    /// Every value is the same
+    Uniform,
+    /// Indices of this many bits into a palette of distinct values
+    Palette(u32),
+    Dense,
+}
+
+#[derive(Clone)]
+enum Leaf3dData<T: Default + Clone + Copy + Sync + Send + 'static> {
+    Uniform(T),
+    /// `indices` packs `64 / bits` indices per word, so none straddle two words
+    Palette { palette: Vec<T>, bits: u32, indices: Vec<u64> },
+    Dense(Box<[T; CHUNK_3D_SIZE]>),
+}
+
+impl<T: Default + Clone + Copy + Sync + Send + 'static> Leaf3dData<T> {
+    #[inline]
+    fn palette_index(indices: &[u64], bits: u32, index: usize) -> usize {
+        let per_word = (64 / bits) as usize;
+        ((indices[index / per_word] >> ((index % per_word) as u32 * bits)) & ((1 << bits) - 1)) as usize
+    }
+
+    #[inline]
+    fn set_palette_index(indices: &mut [u64], bits: u32, index: usize, palette_index: u64) {
+        let per_word = (64 / bits) as usize;
+        let shift = (index % per_word) as u32 * bits;
+        let word = &mut indices[index / per_word];
+        *word = (*word & !(((1 << bits) - 1) << shift)) | (palette_index << shift);
+    }
+
+    #[inline]
+    fn get(&self, index: usize) -> T {
+        match self {
+            Self::Uniform(value) => *value,
+            Self::Palette { palette, bits, indices } => palette[Self::palette_index(indices, *bits, index)],
+            Self::Dense(data) => data[index],
+        }
+    }
+
+    fn to_values(&self) -> [T; CHUNK_3D_SIZE] {
+        match self {
+            Self::Dense(data) => **data,
+            _ => std::array::from_fn(|index| self.get(index)),
+        }
+    }
+}
+
+/// Changing values compares them to find them in the palette.
+impl<T: PartialEq + Default + Clone + Copy + Sync + Send + 'static> Leaf3dData<T> {
+    /// The smallest representation holding `values`.
+    fn from_values(values: &[T; CHUNK_3D_SIZE]) -> Self {
+        let max_bits = LEAF_3D_MAX_PALETTE_BITS.min((std::mem::size_of::<T>() as u32 * 8).saturating_sub(1));
+        let mut palette: Vec<T> = vec![];
+
+        for value in values.iter() {
+            if palette.contains(value) { continue; }
+            if palette.len() >= 1 << max_bits { return Self::Dense(Box::new(*values)); }
+            palette.push(*value);
+        }
+
+        if palette.len() == 1 { return Self::Uniform(palette[0]); }
+
+        let bits = Self::palette_bits(palette.len());
+        if bits > max_bits { return Self::Dense(Box::new(*values)); }
+
+        let mut indices = vec![0; CHUNK_3D_SIZE / (64 / bits) as usize];
+        for (index, value) in values.iter().enumerate() {
+            let palette_index = palette.iter().position(|entry| *entry == *value).unwrap_or_default();
+            Self::set_palette_index(&mut indices, bits, index, palette_index as u64);
+        }
+
+        Self::Palette { palette, bits, indices }
+    }
+
+    /// 1, 2, 4 or 8 bits, enough to index `len` entries.
+    fn palette_bits(len: usize) -> u32 {
+        let bits = usize::BITS - (len.max(2) - 1).leading_zeros();
+        bits.next_power_of_two()
+    }
+
+    fn set(&mut self, index: usize, value: T) {
+        match self {
+            Self::Uniform(uniform) => {
+                if *uniform == value { return; }
+                let mut indices = vec![0; CHUNK_3D_SIZE / 64];
+                Self::set_palette_index(&mut indices, 1, index, 1);
+                *self = Self::Palette { palette: vec![*uniform, value], bits: 1, indices };
+            }
+            Self::Palette { palette, bits, indices } => {
+                if let Some(palette_index) = palette.iter().position(|entry| *entry == value) {
+                    Self::set_palette_index(indices, *bits, index, palette_index as u64);
+                } else if palette.len() < 1 << *bits {
+                    palette.push(value);
+                    Self::set_palette_index(indices, *bits, index, palette.len() as u64 - 1);
+                } else {
+                    // Rebuilding drops entries no longer used, so the palette only grows if it has to
+                    let mut values = self.to_values();
+                    values[index] = value;
+                    *self = Self::from_values(&values);
+                }
+            }
+            Self::Dense(data) => { data[index] = value; }
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Stores a single value while the chunk is uniform, then a palette of its distinct values with bit-packed indices,
+/// then every value once there are too many distinct values for a palette to be smaller.
+///
+/// Values that are set off keep their value, so [Leaf3d::get_value] is the same in every representation.
 #[derive(Component, Clone)]
 pub struct Leaf3d<T: Default + Clone + Copy + Sync + Send + 'static> {
-    data: [T; CHUNK_3D_SIZE],
+    data: Leaf3dData<T>,
     active_mask: Bitmask<CHUNK_3D_WORD_NUM>,
 }
 
 impl<T: Default + Clone + Copy + Sync + Send + 'static> Default for Leaf3d<T> {
     fn default() -> Self {
-        Self { data: [T::default(); CHUNK_3D_SIZE], active_mask: Bitmask::<CHUNK_3D_WORD_NUM>::new(false) }
+        Self { data: Leaf3dData::Uniform(T::default()), active_mask: Bitmask::<CHUNK_3D_WORD_NUM>::new(false) }
     }
 }
 
 impl<T: Default + Clone + Copy + Sync + Send + 'static> Leaf3d<T> {
-    #[inline] pub fn new(data: [T; CHUNK_3D_SIZE], active_mask: Bitmask<CHUNK_3D_WORD_NUM>) -> Self {
-        Self { data, active_mask}
-    }
-
     #[inline] pub fn default_arc_rwlock() -> Arc<RwLock<Self>> {
         Arc::new(RwLock::new(Self::default()))
     }
 
+    /// Every value in index order, read from the current representation without decompressing the chunk.
+    #[inline] pub fn values(&self) -> impl Iterator<Item = T> + '_ { (0..CHUNK_3D_SIZE).map(|index| self.data.get(index)) }
+    /// Every value, decompressed into a copy. Prefer [Leaf3d::values] when the values are only read once.
+    #[inline] pub fn to_data(&self) -> [T; CHUNK_3D_SIZE] { self.data.to_values() }
+    #[inline] pub fn active_mask(&self) -> &Bitmask<CHUNK_3D_WORD_NUM> { &self.active_mask }
+
+    pub fn storage(&self) -> Leaf3dStorage {
+        match &self.data {
+            Leaf3dData::Uniform(_) => Leaf3dStorage::Uniform,
+            Leaf3dData::Palette { bits, .. } => Leaf3dStorage::Palette(*bits),
+            Leaf3dData::Dense(_) => Leaf3dStorage::Dense,
+        }
+    }
+
+    #[inline] pub fn get_value(&self, index: usize) -> T { self.data.get(index) }
+    /// Only dense chunks skip the bounds check, uniform & palette chunks are read as in `get_value`.
+    ///
+    /// # Safety
+    /// `index` must be less than `CHUNK_3D_SIZE`.
+    #[inline] pub unsafe fn get_value_unchecked(&self, index: usize) -> T {
+        match &self.data {
+            Leaf3dData::Dense(data) => *data.get_unchecked(index),
+            data => data.get(index),
+        }
+    }
+    #[inline] pub fn is_value_on(&self, index: usize) -> bool { self.active_mask.is_bit_on(index) }
+
+    #[inline]
+    pub fn set_value_off(&mut self, index: usize) {
+        self.active_mask.set_bit_off(index);
+    }
+}
+
+/// Building & changing values picks a representation by comparing values.
+impl<T: PartialEq + Default + Clone + Copy + Sync + Send + 'static> Leaf3d<T> {
+    #[inline] pub fn new(data: [T; CHUNK_3D_SIZE], active_mask: Bitmask<CHUNK_3D_WORD_NUM>) -> Self {
+        Self { data: Leaf3dData::from_values(&data), active_mask}
+    }
+
     #[inline] pub fn new_arc_rwlock(data: [T; CHUNK_3D_SIZE], active_mask: Bitmask<CHUNK_3D_WORD_NUM>) -> Arc<RwLock<Self>> {
         Arc::new(RwLock::new(Self::new(data, active_mask)))
     }
-    
-    #[inline] pub fn data(&self) -> &[T; CHUNK_3D_SIZE] { &self.data }
-    #[inline] pub fn active_mask(&self) -> &Bitmask<CHUNK_3D_WORD_NUM> { &self.active_mask }
 
-    #[inline] pub fn get_value(&self, index: usize) -> T { self.data[index] }
-    #[inline] pub unsafe fn get_value_unchecked(&self, index: usize) -> T { *self.data.get_unchecked(index) }
-    
+    /// Switches to the smallest representation. Chunks only switch to larger ones as they change, so call this after large edits.
+    pub fn compact(&mut self) { self.data = Leaf3dData::from_values(&self.data.to_values()); }
+
     #[inline]
     pub fn set_value_on(&mut self, index: usize, value: T) {
-        self.data[index] = value;
+        self.data.set(index, value);
         self.active_mask.set_bit_on(index);
     }
-    
-    #[inline]
-    pub fn set_value_off(&mut self, index: usize) {
-        self.active_mask.set_bit_off(index);
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zero_sized_values_stay_uniform() {
+        let mut leaf = Leaf3d::<()>::new([(); CHUNK_3D_SIZE], Bitmask::<CHUNK_3D_WORD_NUM>::new(false));
+        leaf.set_value_on(3, ());
+        leaf.compact();
+        assert_eq!(leaf.storage(), Leaf3dStorage::Uniform);
+        assert!(leaf.is_value_on(3));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn values_match_every_storage() {
+        let mut leaf = Leaf3d::<u16>::default();
+        leaf.set_value_on(1, 7);
+        assert_eq!(leaf.storage(), Leaf3dStorage::Palette(1));
+        for index in 0..CHUNK_3D_SIZE { leaf.set_value_on(index, index as u16); }
+        assert_eq!(leaf.storage(), Leaf3dStorage::Dense);
+
+        assert!(leaf.values().eq(leaf.to_data().into_iter()));
+        assert!(leaf.values().enumerate().all(|(index, value)| value == index as u16));
+    }
+}
@@ -0,0 +1,264 @@
+use super::*;
+
+use bevy::{
+    tasks::{block_on, AsyncComputeTaskPool, Task},
+    utils::{HashMap, HashSet},
+};
+use futures_lite::future;
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Meshes every [FlatSparseRoot3d<T>] with a [FlatSparseRoot3dMesher] & [FlatSparseRoot3dChanges] as cubes, using `DataAssets<VoxelDef>`.
+pub struct FlatSparseRoot3dMesherPlugin<T: VoxelData + PartialEq + Default + Clone + Copy + Sync + Send + 'static> {
+    phantom_data: PhantomData<T>,
+}
+
+impl<T: VoxelData + PartialEq + Default + Clone + Copy + Sync + Send + 'static> Default for FlatSparseRoot3dMesherPlugin<T> {
+    fn default() -> Self { Self { phantom_data: PhantomData } }
+}
+
+impl<T: VoxelData + PartialEq + Default + Clone + Copy + Sync + Send + 'static> Plugin for FlatSparseRoot3dMesherPlugin<T> {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<FlatSparseRoot3dRemeshPlugin>() { app.add_plugins(FlatSparseRoot3dRemeshPlugin); }
+        app.add_systems(PostUpdate, sys_start_flat_sparse_root_3d_remeshes::<T>.before(sys_finish_flat_sparse_root_3d_remeshes));
+    }
+}
+
+/// Meshes every [FlatSparseRoot3d<T>] with a [FlatSparseRoot3dMesher] & [FlatSparseRoot3dChanges] as a smooth surface, using surface nets.
+///
+/// Mark changes with `FlatSparseRoot3dChanges::mark_density_change`, as smooth meshes also read diagonal neighbours.
+pub struct FlatSparseRoot3dSmoothMesherPlugin<T: DensityData + Default + Clone + Copy + Sync + Send + 'static> {
+    phantom_data: PhantomData<T>,
+}
+
+impl<T: DensityData + Default + Clone + Copy + Sync + Send + 'static> Default for FlatSparseRoot3dSmoothMesherPlugin<T> {
+    fn default() -> Self { Self { phantom_data: PhantomData } }
+}
+
+impl<T: DensityData + Default + Clone + Copy + Sync + Send + 'static> Plugin for FlatSparseRoot3dSmoothMesherPlugin<T> {
+    fn build(&self, app: &mut App) {
+        if !app.is_plugin_added::<FlatSparseRoot3dRemeshPlugin>() { app.add_plugins(FlatSparseRoot3dRemeshPlugin); }
+        app.add_systems(PostUpdate, sys_start_flat_sparse_root_3d_smooth_remeshes::<T>.before(sys_finish_flat_sparse_root_3d_remeshes));
+    }
+}
+
+/// Applies finished remeshes, shared by every mesher plugin.
+struct FlatSparseRoot3dRemeshPlugin;
+impl Plugin for FlatSparseRoot3dRemeshPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PostUpdate, sys_finish_flat_sparse_root_3d_remeshes);
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// For tracking changed chunks. Just use mark_change with global coord any time you edit a voxel.
+///
+/// Chunks touching the edited voxel are marked too, as their faces may have been uncovered or hidden.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct FlatSparseRoot3dChanges(HashSet<IVec3>);
+
+impl FlatSparseRoot3dChanges {
+    pub fn mark_change(&mut self, coord: IVec3) {
+        let chunk_key = coord & !CHUNK_3D_MASK;
+        self.0.insert(chunk_key);
+
+        let local_coord = coord & CHUNK_3D_MASK;
+        for axis in 0..3 {
+            let mut offset = IVec3::ZERO;
+            if local_coord[axis] == 0 { offset[axis] = -(CHUNK_3D_DIM as i32); }
+            else if local_coord[axis] == CHUNK_3D_MASK { offset[axis] = CHUNK_3D_DIM as i32; }
+            else { continue; }
+
+            self.0.insert(chunk_key + offset);
+        }
+    }
+
+    /// Like `mark_change`, but also marks diagonal neighbours, which smooth meshes sample across corners & edges.
+    pub fn mark_density_change(&mut self, coord: IVec3) {
+        let chunk_key = coord & !CHUNK_3D_MASK;
+        let local_coord = coord & CHUNK_3D_MASK;
+
+        let mut min = IVec3::ZERO;
+        let mut max = IVec3::ZERO;
+        for axis in 0..3 {
+            if local_coord[axis] == 0 { min[axis] = -1; }
+            else if local_coord[axis] == CHUNK_3D_MASK { max[axis] = 1; }
+        }
+
+        for offset in FlatSparseRoot3dMath::box_coords(min, max) { self.0.insert(chunk_key + offset * CHUNK_3D_DIM as i32); }
+    }
+
+    /// Marks only the chunk containing `coord`, such as after replacing a whole chunk.
+    pub fn mark_chunk(&mut self, coord: IVec3) { self.0.insert(coord & !CHUNK_3D_MASK); }
+
+    pub fn is_empty(&self) -> bool { self.0.is_empty() }
+    pub fn clear(&mut self) { self.0.clear() }
+    pub fn iter(&self) -> impl Iterator<Item = IVec3> + '_ { self.0.iter().copied() }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Put this on a [FlatSparseRoot3d] along with [FlatSparseRoot3dChanges], and the root will be meshed on the async compute task pool.
+///
+/// You must mark any changes made using [FlatSparseRoot3dChanges], or the mesh will not update.
+///
+/// Voxel types are meshed as cubes, and density types as smooth surfaces, see [FlatSparseRoot3dSmoothMesherPlugin].
+///
+/// Each chunk is a child entity holding its opaque mesh & trimesh collider, which are replaced together once the new mesh is ready.
+/// Transparent faces are a child of the chunk entity using `transparent_material`.
+///
+/// If a [Handle<StandardMaterial>] is on the entity, it will be used as the material for the opaque meshes.
+#[derive(Component, Reflect)]
+#[reflect(Component)]
+pub struct FlatSparseRoot3dMesher {
+    /// Max remeshes started, and max finished remeshes applied, per frame
+    pub chunks_per_frame: usize,
+    pub transparent_material: Option<Handle<StandardMaterial>>,
+    #[reflect(ignore)]
+    pub atlas: TexAtlas,
+    meshes: HashMap<IVec3, Entity>,
+    transparent_meshes: HashMap<IVec3, Entity>,
+    #[reflect(ignore)]
+    tasks: HashMap<IVec3, Task<Option<VoxelChunkMeshData>>>,
+}
+
+impl Default for FlatSparseRoot3dMesher {
+    fn default() -> Self {
+        Self {
+            chunks_per_frame: 8,
+            transparent_material: None,
+            atlas: TexAtlas::default(),
+            meshes: HashMap::default(),
+            transparent_meshes: HashMap::default(),
+            tasks: HashMap::default(),
+        }
+    }
+}
+
+impl FlatSparseRoot3dMesher {
+    pub fn new(chunks_per_frame: usize, atlas: TexAtlas) -> Self { Self { chunks_per_frame, atlas, ..default() } }
+
+    /// Chunks waiting on a task to finish
+    pub fn pending_count(&self) -> usize { self.tasks.len() }
+}
+
+fn sys_start_flat_sparse_root_3d_remeshes<T: VoxelData + PartialEq + Default + Clone + Copy + Sync + Send + 'static>(
+    mut root_query: Query<(&FlatSparseRoot3d<T>, &mut FlatSparseRoot3dMesher, &mut FlatSparseRoot3dChanges)>,
+    voxel_defs: Res<DataAssets<VoxelDef>>,
+    mut defs_copy: Local<Option<Arc<Vec<VoxelDef>>>>,
+) {
+    if defs_copy.is_none() || voxel_defs.is_changed() { *defs_copy = Some(Arc::new(voxel_defs.data().to_vec())); }
+    let Some(defs) = defs_copy.as_ref() else { return };
+    let task_pool = AsyncComputeTaskPool::get();
+
+    for (root, mut root_mesher, mut root_changes) in root_query.iter_mut() {
+        if root_changes.is_empty() { continue; }
+
+        // Chunks that are already being meshed stay marked, so they are meshed again once their current task finishes
+        let keys: Vec<IVec3> = root_changes.iter()
+            .filter(|key| !root_mesher.tasks.contains_key(key))
+            .take(root_mesher.chunks_per_frame)
+            .collect();
+
+        for key in keys {
+            root_changes.0.remove(&key);
+
+            let neighborhood = root.clone_neighborhood(&key);
+            let defs = defs.clone();
+            let atlas = root_mesher.atlas;
+            let task = task_pool.spawn(async move { VoxelMesher::mesh_chunk_greedy_with_defs(&neighborhood, &key, &defs, &atlas) });
+            root_mesher.tasks.insert(key, task);
+        }
+    }
+}
+
+fn sys_start_flat_sparse_root_3d_smooth_remeshes<T: DensityData + Default + Clone + Copy + Sync + Send + 'static>(
+    mut root_query: Query<(&FlatSparseRoot3d<T>, &mut FlatSparseRoot3dMesher, &mut FlatSparseRoot3dChanges)>,
+) {
+    let task_pool = AsyncComputeTaskPool::get();
+
+    for (root, mut root_mesher, mut root_changes) in root_query.iter_mut() {
+        if root_changes.is_empty() { continue; }
+
+        let keys: Vec<IVec3> = root_changes.iter()
+            .filter(|key| !root_mesher.tasks.contains_key(key))
+            .take(root_mesher.chunks_per_frame)
+            .collect();
+
+        for key in keys {
+            root_changes.0.remove(&key);
+
+            let neighborhood = root.clone_neighborhood(&key);
+            let task = task_pool.spawn(async move {
+                let mesh_data = SmoothVoxelMesher::mesh_chunk_surface_nets(&neighborhood, &key)?;
+                let collider = MeshData { verts: mesh_data.verts.clone(), indices: mesh_data.indices.clone(), ..default() };
+                Some(VoxelChunkMeshData { opaque: mesh_data, transparent: MeshData::default(), collider })
+            });
+            root_mesher.tasks.insert(key, task);
+        }
+    }
+}
+
+fn sys_finish_flat_sparse_root_3d_remeshes(
+    mut commands: Commands,
+    mut root_query: Query<(Entity, &mut FlatSparseRoot3dMesher)>,
+    material_query: Query<&Handle<StandardMaterial>, With<FlatSparseRoot3dMesher>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    for (root_entity, mut root_mesher) in root_query.iter_mut() {
+        if root_mesher.tasks.is_empty() { continue; }
+        let root_mesher = &mut *root_mesher;
+
+        let mut finished = vec![];
+        for (key, task) in root_mesher.tasks.iter_mut() {
+            if finished.len() >= root_mesher.chunks_per_frame { break; }
+            if let Some(chunk_mesh_data) = block_on(future::poll_once(task)) { finished.push((*key, chunk_mesh_data)); }
+        }
+
+        for (key, chunk_mesh_data) in finished {
+            root_mesher.tasks.remove(&key);
+            let chunk_mesh_data = chunk_mesh_data.unwrap_or_default();
+
+            // Opaque mesh & collider are inserted by the same commands flush, so they never disagree
+            let opaque_mesh = if chunk_mesh_data.opaque.is_empty() { None } else { Some(meshes.add(chunk_mesh_data.opaque.mesh())) };
+            let collider = chunk_mesh_data.collider.trimesh_collider();
+
+            if opaque_mesh.is_none() && collider.is_none() && chunk_mesh_data.transparent.is_empty() {
+                if let Some(chunk_entity) = root_mesher.meshes.remove(&key) { commands.entity(chunk_entity).despawn_recursive(); }
+                root_mesher.transparent_meshes.remove(&key);
+                continue;
+            }
+
+            let chunk_entity = if let Some(chunk_entity) = root_mesher.meshes.get(&key) {
+                *chunk_entity
+            } else {
+                let material = if let Ok(material) = material_query.get(root_entity) { material.clone() } else { materials.add(StandardMaterial { perceptual_roughness: 0.9, ..default() }) };
+                let transform = Transform::from_translation(key.as_vec3());
+                let chunk_entity = commands.spawn(PbrBundle { material, transform, ..default() }).id();
+                commands.entity(root_entity).add_child(chunk_entity);
+                root_mesher.meshes.insert(key, chunk_entity);
+                chunk_entity
+            };
+
+            let mut chunk_commands = commands.entity(chunk_entity);
+            chunk_commands.insert(opaque_mesh.unwrap_or_default());
+            if let Some(collider) = collider { chunk_commands.insert(collider); } else { chunk_commands.remove::<Collider>(); }
+
+            let transparent_mesh = if chunk_mesh_data.transparent.is_empty() { None } else { Some(meshes.add(chunk_mesh_data.transparent.mesh())) };
+            match (transparent_mesh, root_mesher.transparent_meshes.get(&key).copied()) {
+                (Some(mesh), Some(transparent_entity)) => { commands.entity(transparent_entity).insert(mesh); }
+                (Some(mesh), None) => {
+                    let material = if let Some(material) = &root_mesher.transparent_material { material.clone() } else { materials.add(StandardMaterial { alpha_mode: AlphaMode::Blend, ..default() }) };
+                    let transparent_entity = commands.spawn(PbrBundle { mesh, material, ..default() }).id();
+                    commands.entity(chunk_entity).add_child(transparent_entity);
+                    root_mesher.transparent_meshes.insert(key, transparent_entity);
+                }
+                (None, Some(transparent_entity)) => {
+                    commands.entity(transparent_entity).despawn_recursive();
+                    root_mesher.transparent_meshes.remove(&key);
+                }
+                (None, None) => {}
+            }
+        }
+    }
+}
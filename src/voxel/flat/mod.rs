@@ -4,6 +4,12 @@ use std::sync::{Arc, RwLock};
 
 mod chunk;
 pub use chunk::*;
+mod mesher;
+pub use mesher::*;
+mod query;
+pub use query::*;
+mod region;
+pub use region::*;
 mod sparse;
 pub use sparse::*;
 
@@ -23,9 +29,18 @@ pub const CHUNK_3D_MASK: i32 = CHUNK_3D_DIM as i32 - 1;
 
 pub const MAX_WORLD_LOG2DIM: u8 = 8;
 
+/// Registers the types shared by every [FlatSparseRoot3d]. Meshing & streaming are generic over the value type, so the app adds them for the types it uses:
+/// ```ignore
+/// app.add_plugins((
+///     FlatSparseRoot3dMesherPlugin::<u16>::default(),
+///     FlatSparseRoot3dSmoothMesherPlugin::<f32>::default(),
+///     ChunkStreamerPlugin::<FlatSparseRoot3d<u16>>::default(),
+/// ));
+/// ```
 pub struct TankVoxelFlatPlugin;
 impl Plugin for TankVoxelFlatPlugin {
     fn build(&self, app: &mut App) {
-        
+        app.register_type::<FlatSparseRoot3dChanges>()
+            .register_type::<FlatSparseRoot3dMesher>();
     }
 }
\ No newline at end of file
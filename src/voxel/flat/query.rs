@@ -0,0 +1,189 @@
+use super::*;
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Voxel hit by [FlatSparseRoot3d::raycast]. Coords are local to the root, with voxel `coord` centered on `coord`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct VoxelRayHit {
+    pub coord: IVec3,
+    /// Normal of the face the ray entered through, zero if the ray started inside the voxel
+    pub normal: IVec3,
+    /// Along the ray from its origin to where it entered the voxel
+    pub distance: f32,
+}
+
+impl VoxelRayHit {
+    /// The empty voxel in front of the hit face, where a new voxel would be placed.
+    pub fn adjacent_coord(&self) -> IVec3 { self.coord + self.normal }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+impl<T: Default + Clone + Copy + Sync + Send + 'static> FlatSparseRoot3d<T> {
+    /// Steps through every voxel along the ray (Amanatides & Woo), returning the first one where `is_solid(value)` is true.
+    ///
+    /// `origin` is local to the root. Voxels that are off or in missing chunks are passed to `is_solid` as the background.
+    ///
+    /// `max_distance` is capped to the furthest the ray could travel while still inside the root, so infinite rays end.
+    pub fn raycast(&self, origin: Vec3, direction: Vec3, max_distance: f32, is_solid: impl Fn(&T) -> bool) -> Option<VoxelRayHit> {
+        let direction = direction.try_normalize()?;
+        let half_total_dim = ((self.dim() * CHUNK_3D_DIM) >> 1) as f32;
+        let max_distance = max_distance.min(origin.length() + half_total_dim * 3.0_f32.sqrt() + 1.0);
+
+        // Voxels are centered on their coords, so their faces are on half coords
+        let start = origin + CUBE_HALF_DIM;
+        let mut coord = start.floor().as_ivec3();
+        let mut normal = IVec3::ZERO;
+        let mut distance = 0.0;
+
+        // Distance along the ray to cross a whole voxel, & to reach the next face, on each axis
+        let t_delta = direction.recip().abs();
+        let mut t_max = Vec3::splat(f32::INFINITY);
+        let mut step = IVec3::ZERO;
+        for axis in 0..3 {
+            if direction[axis] > 0.0 {
+                step[axis] = 1;
+                t_max[axis] = (coord[axis] as f32 + 1.0 - start[axis]) * t_delta[axis];
+            } else if direction[axis] < 0.0 {
+                step[axis] = -1;
+                t_max[axis] = (start[axis] - coord[axis] as f32) * t_delta[axis];
+            }
+        }
+
+        loop {
+            if is_solid(&self.get_active_value(&coord)) { return Some(VoxelRayHit { coord, normal, distance }); }
+
+            let axis = if t_max.x < t_max.y { if t_max.x < t_max.z { 0 } else { 2 } } else if t_max.y < t_max.z { 1 } else { 2 };
+            distance = t_max[axis];
+            if distance > max_distance { return None; }
+
+            coord[axis] += step[axis];
+            normal = IVec3::ZERO;
+            normal[axis] = -step[axis];
+            t_max[axis] += t_delta[axis];
+        }
+    }
+
+    /// `(coord, value)` of every voxel that is on, inside the box from `min` to `max` inclusive. Missing chunks are skipped.
+    pub fn overlap_box(&self, min: IVec3, max: IVec3) -> impl Iterator<Item = (IVec3, T)> + '_ {
+        let (min, max) = (min.min(max), min.max(max));
+        let chunk_min = (min & !CHUNK_3D_MASK) >> CHUNK_3D_LOG2DIM as i32;
+        let chunk_max = (max & !CHUNK_3D_MASK) >> CHUNK_3D_LOG2DIM as i32;
+
+        FlatSparseRoot3dMath::box_coords(chunk_min, chunk_max).flat_map(move |chunk_index| {
+            let chunk_key = chunk_index << CHUNK_3D_LOG2DIM as i32;
+            let mut values = vec![];
+            let Some(chunk) = self.chunks().get(&chunk_key) else { return values };
+
+            // Collected so the chunk is not locked while iterating
+            let chunk = chunk.read().unwrap();
+            for index in OnMaskIter::new(0, chunk.active_mask()) {
+                let coord = self.value_local_coord_from_index(index) + chunk_key;
+                if coord.cmpge(min).all() && coord.cmple(max).all() { values.push((coord, chunk.get_value(index as usize))); }
+            }
+
+            values
+        })
+    }
+
+    /// `(coord, value)` of every voxel that is on with its center within `radius` of `center`.
+    pub fn overlap_sphere(&self, center: Vec3, radius: f32) -> impl Iterator<Item = (IVec3, T)> + '_ {
+        let radius_squared = radius * radius;
+        self.overlap_box((center - radius).round().as_ivec3(), (center + radius).round().as_ivec3())
+            .filter(move |(coord, _)| coord.as_vec3().distance_squared(center) <= radius_squared)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+impl FlatSparseRoot3dMath {
+    /// Every coord in the box from `min` to `max` inclusive, in x, then y, then z order.
+    pub fn box_coords(min: IVec3, max: IVec3) -> impl Iterator<Item = IVec3> {
+        (min.z..=max.z).flat_map(move |z| (min.y..=max.y).flat_map(move |y| (min.x..=max.x).map(move |x| IVec3::new(x, y, z))))
+    }
+
+    /// Every coord with its center within `radius` of `center`, including empty voxels, such as for placing voxels in an area.
+    pub fn sphere_coords(center: Vec3, radius: f32) -> impl Iterator<Item = IVec3> {
+        let radius_squared = radius * radius;
+        Self::box_coords((center - radius).round().as_ivec3(), (center + radius).round().as_ivec3())
+            .filter(move |coord| coord.as_vec3().distance_squared(center) <= radius_squared)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn root_with(coords: &[IVec3]) -> FlatSparseRoot3d<u16> {
+        let mut root = FlatSparseRoot3d::new(4, 0);
+        for coord in coords { root.set_value_on(coord, 1); }
+        root
+    }
+
+    fn is_solid(value: &u16) -> bool { *value != 0 }
+
+    fn sorted(coords: impl Iterator<Item = IVec3>) -> Vec<IVec3> {
+        let mut coords: Vec<IVec3> = coords.collect();
+        coords.sort_by_key(|coord| (coord.z, coord.y, coord.x));
+        coords
+    }
+
+    #[test]
+    fn raycast_hits_along_axis() {
+        let root = root_with(&[IVec3::new(5, 0, 0)]);
+        let hit = root.raycast(Vec3::ZERO, Vec3::X, 100.0, is_solid).unwrap();
+        assert_eq!(hit.coord, IVec3::new(5, 0, 0));
+        assert_eq!(hit.normal, IVec3::NEG_X);
+        assert!((hit.distance - 4.5).abs() < 0.0001);
+        assert_eq!(hit.adjacent_coord(), IVec3::new(4, 0, 0));
+    }
+
+    #[test]
+    fn raycast_hits_diagonally() {
+        let wall: Vec<IVec3> = FlatSparseRoot3dMath::box_coords(IVec3::new(4, -5, -5), IVec3::new(4, 5, 5)).collect();
+        let root = root_with(&wall);
+        let direction = Vec3::new(1.0, 0.5, 0.0);
+        let hit = root.raycast(Vec3::ZERO, direction, 100.0, is_solid).unwrap();
+        assert_eq!(hit.coord, IVec3::new(4, 2, 0));
+        assert_eq!(hit.normal, IVec3::NEG_X);
+        assert!((hit.distance - 3.5 * direction.length()).abs() < 0.0001);
+    }
+
+    #[test]
+    fn raycast_normal_is_hit_face() {
+        let root = root_with(&[IVec3::ZERO]);
+        let hit = root.raycast(Vec3::new(0.0, 10.0, 0.0), Vec3::NEG_Y, 100.0, is_solid).unwrap();
+        assert_eq!(hit.coord, IVec3::ZERO);
+        assert_eq!(hit.normal, IVec3::Y);
+        assert!((hit.distance - 9.5).abs() < 0.0001);
+
+        let inside = root.raycast(Vec3::ZERO, Vec3::X, 100.0, is_solid).unwrap();
+        assert_eq!(inside.normal, IVec3::ZERO);
+        assert_eq!(inside.distance, 0.0);
+    }
+
+    #[test]
+    fn raycast_misses_beyond_max_distance() {
+        let root = root_with(&[IVec3::new(10, 0, 0)]);
+        assert!(root.raycast(Vec3::ZERO, Vec3::X, 5.0, is_solid).is_none());
+        assert!(root.raycast(Vec3::ZERO, Vec3::NEG_X, f32::INFINITY, is_solid).is_none());
+        assert!(root.raycast(Vec3::ZERO, Vec3::NEG_X, f32::NAN, is_solid).is_none());
+    }
+
+    #[test]
+    fn overlap_box_returns_active_coords_inside() {
+        let coords = [IVec3::new(0, 0, 0), IVec3::new(2, 1, 0), IVec3::new(9, 0, 0), IVec3::new(-1, 0, 0), IVec3::new(3, 3, 3)];
+        let root = root_with(&coords);
+
+        let found = sorted(root.overlap_box(IVec3::ZERO, IVec3::new(9, 2, 2)).map(|(coord, _)| coord));
+        assert_eq!(found, sorted([IVec3::new(0, 0, 0), IVec3::new(2, 1, 0), IVec3::new(9, 0, 0)].into_iter()));
+        assert!(root.overlap_box(IVec3::splat(20), IVec3::splat(30)).next().is_none());
+    }
+
+    #[test]
+    fn overlap_sphere_returns_active_coords_within_radius() {
+        let coords = [IVec3::new(0, 0, 0), IVec3::new(2, 0, 0), IVec3::new(2, 2, 0), IVec3::new(-3, 0, 0)];
+        let root = root_with(&coords);
+
+        let found = sorted(root.overlap_sphere(Vec3::ZERO, 2.5).map(|(coord, _)| coord));
+        assert_eq!(found, sorted([IVec3::new(0, 0, 0), IVec3::new(2, 0, 0)].into_iter()));
+        assert_eq!(sorted(FlatSparseRoot3dMath::sphere_coords(Vec3::ZERO, 1.0)).len(), 7);
+    }
+}
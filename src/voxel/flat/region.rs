@@ -0,0 +1,488 @@
+use super::*;
+
+use std::{fs::File, io::{Read, Seek, SeekFrom}};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Each 2d region holds `16 x 16` chunks
+pub const REGION_2D_LOG2DIM: u32 = 4;
+/// Each 3d region holds `8 x 8 x 8` chunks
+pub const REGION_3D_LOG2DIM: u32 = 3;
+
+const REGION_MAGIC: [u8; 4] = *b"TKVR";
+const REGION_VERSION: u16 = 1;
+/// Magic, version, dimensions & slot count
+const REGION_HEADER_SIZE: usize = 4 + 2 + 1 + 4;
+/// Offset & length of a slot, 0 length for missing chunks
+const REGION_SLOT_SIZE: usize = 4 + 4;
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// A chunk's active mask & its data as runs of equal values.
+#[derive(Serialize, Deserialize)]
+struct LeafRecord<T> {
+    active_mask: Vec<u64>,
+    runs: Vec<(u32, T)>,
+}
+
+impl<T: PartialEq + Copy> LeafRecord<T> {
+    fn new(data: impl IntoIterator<Item = T>, active_mask: &[u64]) -> Self {
+        let mut runs: Vec<(u32, T)> = vec![];
+        for value in data {
+            match runs.last_mut() {
+                Some((count, run_value)) if *run_value == value => { *count += 1; }
+                _ => { runs.push((1, value)); }
+            }
+        }
+
+        Self { active_mask: active_mask.to_vec(), runs }
+    }
+
+    fn into_parts<const SIZE: usize, const WORD_NUM: usize>(self, path: &str) -> SerialResult<([T; SIZE], Bitmask<WORD_NUM>)> {
+        let value_count: u64 = self.runs.iter().map(|(count, _)| *count as u64).sum();
+        if value_count != SIZE as u64 { return Err(SerialError::Corrupt { path: path.to_owned(), message: format!("chunk has {value_count} values, expected {SIZE}") }); }
+
+        let mut data = Vec::with_capacity(SIZE);
+        for (count, value) in self.runs.into_iter() { data.extend(std::iter::repeat_n(value, count as usize)); }
+
+        let data: [T; SIZE] = data.try_into()
+            .map_err(|data: Vec<T>| SerialError::Corrupt { path: path.to_owned(), message: format!("chunk has {} values, expected {SIZE}", data.len()) })?;
+        let words: [u64; WORD_NUM] = self.active_mask.try_into()
+            .map_err(|words: Vec<u64>| SerialError::Corrupt { path: path.to_owned(), message: format!("chunk mask has {} words, expected {WORD_NUM}", words.len()) })?;
+
+        Ok((data, Bitmask::from_words(words)))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Region files hold a fixed block of chunks: a header, an offset table with a slot per chunk, then every present chunk encoded with bincode.
+///
+/// Chunks store their active mask & their data run length encoded. Missing chunks have an empty slot, and a single chunk can be read
+/// without reading the rest of the file, so large worlds can be streamed.
+pub struct VoxelRegionFile;
+impl VoxelRegionFile {
+    pub const EXTENSION: &'static str = "vxr";
+
+    pub fn path_3d<S: AsRef<str>>(directory: S, region_key: IVec3) -> String {
+        Serial::path_string(directory, format!("r.{}.{}.{}", region_key.x, region_key.y, region_key.z), Self::EXTENSION)
+    }
+
+    pub fn path_2d<S: AsRef<str>>(directory: S, region_key: IVec2) -> String {
+        Serial::path_string(directory, format!("r.{}.{}", region_key.x, region_key.y), Self::EXTENSION)
+    }
+
+    /// Region keys of every region file in `directory` with `dimensions` parts in its name.
+    ///
+    /// Files without the region extension are skipped, so other files can share the directory.
+    fn region_keys<S: AsRef<str>>(directory: S, dimensions: usize) -> SerialResult<Vec<Vec<i32>>> {
+        let entries = std::fs::read_dir(directory.as_ref()).map_err(|error| SerialError::io(directory.as_ref(), error))?;
+        Ok(entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| path.is_file() && path.extension().is_some_and(|extension| extension == Self::EXTENSION))
+            .filter_map(|path| path.file_stem()?.to_str()?.strip_prefix("r.")?.split('.').map(|part| part.parse::<i32>().ok()).collect::<Option<Vec<i32>>>())
+            .filter(|key| key.len() == dimensions)
+            .collect())
+    }
+
+    //==============================================================================================
+    fn write(path: &str, dimensions: u8, slots: &[Option<Vec<u8>>]) -> SerialResult<()> {
+        let table_size = REGION_SLOT_SIZE * slots.len();
+        let mut bytes = Vec::with_capacity(REGION_HEADER_SIZE + table_size);
+        bytes.extend(REGION_MAGIC);
+        bytes.extend(REGION_VERSION.to_le_bytes());
+        bytes.push(dimensions);
+        bytes.extend((slots.len() as u32).to_le_bytes());
+
+        let mut offset = (REGION_HEADER_SIZE + table_size) as u32;
+        for slot in slots.iter() {
+            let length = if let Some(slot) = slot { slot.len() as u32 } else { 0 };
+            bytes.extend(offset.to_le_bytes());
+            bytes.extend(length.to_le_bytes());
+            offset += length;
+        }
+
+        for slot in slots.iter().flatten() { bytes.extend(slot); }
+        Serial::write_file_and_path_checked(path, &bytes)
+    }
+
+    /// `(offset, length)` of every slot, leaving `file` after the table.
+    ///
+    /// Every slot is checked to be inside the file, so a corrupt or truncated table can't cause huge allocations when slots are read.
+    fn read_table(file: &mut File, path: &str, dimensions: u8, slot_count: usize) -> SerialResult<Vec<(u32, u32)>> {
+        let corrupt = |message: String| SerialError::Corrupt { path: path.to_owned(), message };
+        let file_length = file.metadata().map_err(|error| SerialError::io(path, error))?.len();
+
+        let mut header = [0u8; REGION_HEADER_SIZE];
+        file.read_exact(&mut header).map_err(|error| SerialError::io(path, error))?;
+        if header[0..4] != REGION_MAGIC { return Err(corrupt("not a region file".to_owned())); }
+
+        let version = u16::from_le_bytes([header[4], header[5]]);
+        if version != REGION_VERSION { return Err(corrupt(format!("unsupported version {version}"))); }
+        if header[6] != dimensions { return Err(corrupt(format!("region is {}d, expected {dimensions}d", header[6]))); }
+
+        let file_slot_count = u32::from_le_bytes([header[7], header[8], header[9], header[10]]) as usize;
+        if file_slot_count != slot_count { return Err(corrupt(format!("region has {file_slot_count} slots, expected {slot_count}"))); }
+
+        let data_start = (REGION_HEADER_SIZE + REGION_SLOT_SIZE * slot_count) as u64;
+        if file_length < data_start { return Err(corrupt(format!("file has {file_length} bytes, too short for its table of {slot_count} slots"))); }
+
+        let mut table = vec![0u8; REGION_SLOT_SIZE * slot_count];
+        file.read_exact(&mut table).map_err(|error| SerialError::io(path, error))?;
+        let table: Vec<(u32, u32)> = table.chunks_exact(REGION_SLOT_SIZE)
+            .map(|slot| (u32::from_le_bytes([slot[0], slot[1], slot[2], slot[3]]), u32::from_le_bytes([slot[4], slot[5], slot[6], slot[7]])))
+            .collect();
+
+        for (slot_index, (offset, length)) in table.iter().enumerate() {
+            if *length == 0 { continue; }
+            let (start, end) = (*offset as u64, *offset as u64 + *length as u64);
+            if start < data_start || end > file_length {
+                return Err(corrupt(format!("slot {slot_index} covers bytes {start}..{end}, outside the data of a {file_length} byte file")));
+            }
+        }
+
+        Ok(table)
+    }
+
+    fn read_slot_at(file: &mut File, path: &str, (offset, length): (u32, u32)) -> SerialResult<Option<Vec<u8>>> {
+        if length == 0 { return Ok(None); }
+        let mut bytes = vec![0u8; length as usize];
+        file.seek(SeekFrom::Start(offset as u64)).map_err(|error| SerialError::io(path, error))?;
+        file.read_exact(&mut bytes).map_err(|error| SerialError::io(path, error))?;
+        Ok(Some(bytes))
+    }
+
+    fn read_all(path: &str, dimensions: u8, slot_count: usize) -> SerialResult<Vec<Option<Vec<u8>>>> {
+        let mut file = File::open(path).map_err(|error| SerialError::io(path, error))?;
+        let table = Self::read_table(&mut file, path, dimensions, slot_count)?;
+        table.into_iter().map(|slot| Self::read_slot_at(&mut file, path, slot)).collect()
+    }
+
+    /// Slots of an existing file, or every slot empty if there is no file.
+    fn read_all_or_empty(path: &str, dimensions: u8, slot_count: usize) -> SerialResult<Vec<Option<Vec<u8>>>> {
+        match Self::read_all(path, dimensions, slot_count) {
+            Err(error) if error.is_not_found() => Ok(vec![None; slot_count]),
+            result => result,
+        }
+    }
+
+    /// None if the file or the slot does not exist.
+    fn read_slot(path: &str, dimensions: u8, slot_count: usize, slot: usize) -> SerialResult<Option<Vec<u8>>> {
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => { return Ok(None); }
+            Err(error) => { return Err(SerialError::io(path, error)); }
+        };
+
+        let table = Self::read_table(&mut file, path, dimensions, slot_count)?;
+        Self::read_slot_at(&mut file, path, table[slot])
+    }
+
+    fn encode<T: Serialize + PartialEq + Copy>(data: impl IntoIterator<Item = T>, active_mask: &[u64], path: &str) -> SerialResult<Vec<u8>> {
+        bincode::serialize(&LeafRecord::new(data, active_mask)).map_err(|error| SerialError::bincode(path, error))
+    }
+
+    fn decode<T: DeserializeOwned + PartialEq + Copy, const SIZE: usize, const WORD_NUM: usize>(bytes: &[u8], path: &str) -> SerialResult<([T; SIZE], Bitmask<WORD_NUM>)> {
+        let record: LeafRecord<T> = bincode::deserialize(bytes).map_err(|error| SerialError::bincode(path, error))?;
+        record.into_parts(path)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+impl<T: Serialize + DeserializeOwned + PartialEq + Default + Clone + Copy + Sync + Send + 'static> FlatSparseRoot3d<T> {
+    const REGION_SLOT_COUNT: usize = 1 << (REGION_3D_LOG2DIM * 3);
+
+    pub fn region_key_from_chunk_key(chunk_key: IVec3) -> IVec3 { (chunk_key >> CHUNK_3D_LOG2DIM as i32) >> REGION_3D_LOG2DIM as i32 }
+
+    fn region_slot_from_chunk_key(chunk_key: IVec3) -> usize {
+        let local = (chunk_key >> CHUNK_3D_LOG2DIM as i32) & ((1 << REGION_3D_LOG2DIM) - 1);
+        ((local.z << (REGION_3D_LOG2DIM * 2)) + (local.y << REGION_3D_LOG2DIM) + local.x) as usize
+    }
+
+    fn chunk_key_from_region_slot(region_key: IVec3, slot: usize) -> IVec3 {
+        let local = FlatSparseRoot3dMath::local_coord_from_index(slot as u32, REGION_3D_LOG2DIM as u8);
+        ((region_key << REGION_3D_LOG2DIM as i32) + local) << CHUNK_3D_LOG2DIM as i32
+    }
+
+    /// Writes every region containing at least one chunk to `directory/r.{x}.{y}.{z}.vxr`.
+    pub fn save_regions<S: AsRef<str>>(&self, directory: S) -> SerialResult<()> {
+        let mut region_keys: Vec<IVec3> = self.chunks().keys().map(|chunk_key| Self::region_key_from_chunk_key(*chunk_key)).collect();
+        region_keys.sort_by_key(|key| (key.x, key.y, key.z));
+        region_keys.dedup();
+
+        for region_key in region_keys { self.save_region(directory.as_ref(), region_key)?; }
+        Ok(())
+    }
+
+    /// Writes the chunks of a single region. Chunks already in the file that are not loaded are kept, so unloaded chunks are not lost.
+    pub fn save_region<S: AsRef<str>>(&self, directory: S, region_key: IVec3) -> SerialResult<()> {
+        let path = VoxelRegionFile::path_3d(directory, region_key);
+        let mut slots = VoxelRegionFile::read_all_or_empty(&path, 3, Self::REGION_SLOT_COUNT)?;
+
+        for (slot_index, slot) in slots.iter_mut().enumerate() {
+            let Some(chunk) = self.chunk_from_coord(&Self::chunk_key_from_region_slot(region_key, slot_index)) else { continue };
+            let chunk = chunk.read().unwrap();
+            *slot = Some(VoxelRegionFile::encode(chunk.values(), chunk.active_mask().words(), &path)?);
+        }
+
+        VoxelRegionFile::write(&path, 3, &slots)
+    }
+
+    /// Writes a single chunk into its region file, keeping the other chunks in the file. Does nothing if the chunk is not loaded.
+    pub fn save_chunk_to_region<S: AsRef<str>>(&self, directory: S, chunk_key: IVec3) -> SerialResult<()> {
+        let chunk_key = chunk_key & !CHUNK_3D_MASK;
+        let Some(chunk) = self.chunk_from_coord(&chunk_key) else { return Ok(()) };
+        let path = VoxelRegionFile::path_3d(directory, Self::region_key_from_chunk_key(chunk_key));
+
+        let mut slots = VoxelRegionFile::read_all_or_empty(&path, 3, Self::REGION_SLOT_COUNT)?;
+        let chunk = chunk.read().unwrap();
+        slots[Self::region_slot_from_chunk_key(chunk_key)] = Some(VoxelRegionFile::encode(chunk.values(), chunk.active_mask().words(), &path)?);
+        VoxelRegionFile::write(&path, 3, &slots)
+    }
+
+    /// Loads every chunk of every region file in `directory`.
+    pub fn load_regions<S: AsRef<str>>(directory: S, log2dim: u8, background: T) -> SerialResult<Self> {
+        let mut root = Self::new(log2dim, background);
+        for key in VoxelRegionFile::region_keys(directory.as_ref(), 3)? {
+            root.load_region(directory.as_ref(), IVec3::new(key[0], key[1], key[2]))?;
+        }
+
+        Ok(root)
+    }
+
+    /// Inserts every chunk in the region, replacing chunks already loaded.
+    pub fn load_region<S: AsRef<str>>(&mut self, directory: S, region_key: IVec3) -> SerialResult<()> {
+        let path = VoxelRegionFile::path_3d(directory, region_key);
+        for (slot_index, slot) in VoxelRegionFile::read_all(&path, 3, Self::REGION_SLOT_COUNT)?.into_iter().enumerate() {
+            let Some(bytes) = slot else { continue };
+            let (data, active_mask) = VoxelRegionFile::decode(&bytes, &path)?;
+            self.insert_chunk(Self::chunk_key_from_region_slot(region_key, slot_index), Leaf3d::new(data, active_mask));
+        }
+
+        Ok(())
+    }
+
+    /// Reads a single chunk from its region file & inserts it, replacing a chunk already loaded.
+    ///
+    /// Returns false if the region file or the chunk does not exist.
+    pub fn load_chunk_from_region<S: AsRef<str>>(&mut self, directory: S, chunk_key: IVec3) -> SerialResult<bool> {
+        let chunk_key = chunk_key & !CHUNK_3D_MASK;
+        let path = VoxelRegionFile::path_3d(directory, Self::region_key_from_chunk_key(chunk_key));
+        let Some(bytes) = VoxelRegionFile::read_slot(&path, 3, Self::REGION_SLOT_COUNT, Self::region_slot_from_chunk_key(chunk_key))? else { return Ok(false) };
+
+        let (data, active_mask) = VoxelRegionFile::decode(&bytes, &path)?;
+        self.insert_chunk(chunk_key, Leaf3d::new(data, active_mask));
+        Ok(true)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+impl<T: Serialize + DeserializeOwned + PartialEq + Default + Clone + Copy + Sync + Send + 'static> FlatSparseRoot2d<T> {
+    const REGION_SLOT_COUNT: usize = 1 << (REGION_2D_LOG2DIM * 2);
+
+    pub fn region_key_from_chunk_key(chunk_key: IVec2) -> IVec2 { (chunk_key >> CHUNK_2D_LOG2DIM as i32) >> REGION_2D_LOG2DIM as i32 }
+
+    fn region_slot_from_chunk_key(chunk_key: IVec2) -> usize {
+        let local = (chunk_key >> CHUNK_2D_LOG2DIM as i32) & ((1 << REGION_2D_LOG2DIM) - 1);
+        ((local.y << REGION_2D_LOG2DIM) + local.x) as usize
+    }
+
+    fn chunk_key_from_region_slot(region_key: IVec2, slot: usize) -> IVec2 {
+        let local = FlatSparseRoot2dMath::local_coord_from_index(slot as u32, REGION_2D_LOG2DIM as u8);
+        ((region_key << REGION_2D_LOG2DIM as i32) + local) << CHUNK_2D_LOG2DIM as i32
+    }
+
+    /// Writes every region containing at least one chunk to `directory/r.{x}.{y}.vxr`.
+    pub fn save_regions<S: AsRef<str>>(&self, directory: S) -> SerialResult<()> {
+        let mut region_keys: Vec<IVec2> = self.chunks().keys().map(|chunk_key| Self::region_key_from_chunk_key(*chunk_key)).collect();
+        region_keys.sort_by_key(|key| (key.x, key.y));
+        region_keys.dedup();
+
+        for region_key in region_keys { self.save_region(directory.as_ref(), region_key)?; }
+        Ok(())
+    }
+
+    /// Writes the chunks of a single region. Chunks already in the file that are not loaded are kept, so unloaded chunks are not lost.
+    pub fn save_region<S: AsRef<str>>(&self, directory: S, region_key: IVec2) -> SerialResult<()> {
+        let path = VoxelRegionFile::path_2d(directory, region_key);
+        let mut slots = VoxelRegionFile::read_all_or_empty(&path, 2, Self::REGION_SLOT_COUNT)?;
+
+        for (slot_index, slot) in slots.iter_mut().enumerate() {
+            let Some(chunk) = self.chunk_from_coord(Self::chunk_key_from_region_slot(region_key, slot_index)) else { continue };
+            let chunk = chunk.read().unwrap();
+            *slot = Some(VoxelRegionFile::encode(chunk.data().iter().copied(), chunk.active_mask().words(), &path)?);
+        }
+
+        VoxelRegionFile::write(&path, 2, &slots)
+    }
+
+    /// Writes a single chunk into its region file, keeping the other chunks in the file. Does nothing if the chunk is not loaded.
+    pub fn save_chunk_to_region<S: AsRef<str>>(&self, directory: S, chunk_key: IVec2) -> SerialResult<()> {
+        let chunk_key = chunk_key & !CHUNK_2D_MASK;
+        let Some(chunk) = self.chunk_from_coord(chunk_key) else { return Ok(()) };
+        let path = VoxelRegionFile::path_2d(directory, Self::region_key_from_chunk_key(chunk_key));
+
+        let mut slots = VoxelRegionFile::read_all_or_empty(&path, 2, Self::REGION_SLOT_COUNT)?;
+        let chunk = chunk.read().unwrap();
+        slots[Self::region_slot_from_chunk_key(chunk_key)] = Some(VoxelRegionFile::encode(chunk.data().iter().copied(), chunk.active_mask().words(), &path)?);
+        VoxelRegionFile::write(&path, 2, &slots)
+    }
+
+    /// Loads every chunk of every region file in `directory`.
+    pub fn load_regions<S: AsRef<str>>(directory: S, log2dim: u8, background: T) -> SerialResult<Self> {
+        let mut root = Self::new(log2dim, background);
+        for key in VoxelRegionFile::region_keys(directory.as_ref(), 2)? {
+            root.load_region(directory.as_ref(), IVec2::new(key[0], key[1]))?;
+        }
+
+        Ok(root)
+    }
+
+    /// Inserts every chunk in the region, replacing chunks already loaded.
+    pub fn load_region<S: AsRef<str>>(&mut self, directory: S, region_key: IVec2) -> SerialResult<()> {
+        let path = VoxelRegionFile::path_2d(directory, region_key);
+        for (slot_index, slot) in VoxelRegionFile::read_all(&path, 2, Self::REGION_SLOT_COUNT)?.into_iter().enumerate() {
+            let Some(bytes) = slot else { continue };
+            let (data, active_mask) = VoxelRegionFile::decode(&bytes, &path)?;
+            self.insert_chunk(Self::chunk_key_from_region_slot(region_key, slot_index), Leaf2d::new(data, active_mask));
+        }
+
+        Ok(())
+    }
+
+    /// Reads a single chunk from its region file & inserts it, replacing a chunk already loaded.
+    ///
+    /// Returns false if the region file or the chunk does not exist.
+    pub fn load_chunk_from_region<S: AsRef<str>>(&mut self, directory: S, chunk_key: IVec2) -> SerialResult<bool> {
+        let chunk_key = chunk_key & !CHUNK_2D_MASK;
+        let path = VoxelRegionFile::path_2d(directory, Self::region_key_from_chunk_key(chunk_key));
+        let Some(bytes) = VoxelRegionFile::read_slot(&path, 2, Self::REGION_SLOT_COUNT, Self::region_slot_from_chunk_key(chunk_key))? else { return Ok(false) };
+
+        let (data, active_mask) = VoxelRegionFile::decode(&bytes, &path)?;
+        self.insert_chunk(chunk_key, Leaf2d::new(data, active_mask));
+        Ok(true)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Chunks are saved to region files by a [ChunkSaver].
+impl<T: Serialize + DeserializeOwned + PartialEq + Default + Clone + Copy + Sync + Send + 'static> StreamedRoot for FlatSparseRoot3d<T> {
+    type Key = IVec3;
+    type Changes = FlatSparseRoot3dChanges;
+
+    fn chunk_keys_in_radius(local_pos: Vec3, radius: f32) -> Vec<IVec3> {
+        let min = ((local_pos - radius) / CHUNK_3D_DIM as f32).floor().as_ivec3();
+        let max = ((local_pos + radius) / CHUNK_3D_DIM as f32).floor().as_ivec3();
+
+        FlatSparseRoot3dMath::box_coords(min, max)
+            .map(|chunk_index| chunk_index << CHUNK_3D_LOG2DIM as i32)
+            .filter(|key| Self::chunk_distance(*key, local_pos) <= radius)
+            .collect()
+    }
+
+    /// Voxels are centered on their coords, so chunks span from half a voxel before their key.
+    fn chunk_distance(key: IVec3, local_pos: Vec3) -> f32 {
+        (key.as_vec3() + CHUNK_3D_DIM as f32 * 0.5 - CUBE_HALF_DIM).distance(local_pos)
+    }
+
+    fn chunk_key_from_pos(local_pos: Vec3) -> IVec3 {
+        ((local_pos + CUBE_HALF_DIM) / CHUNK_3D_DIM as f32).floor().as_ivec3() << CHUNK_3D_LOG2DIM as i32
+    }
+
+    fn chunk_keys(&self) -> Vec<IVec3> { self.chunks().keys().copied().collect() }
+    fn has_chunk(&self, key: IVec3) -> bool { self.chunks().contains_key(&key) }
+    fn remove_chunk(&mut self, key: IVec3) { FlatSparseRoot3d::remove_chunk(self, key); }
+
+    /// Face neighbours may have faces uncovered or hidden by the chunk.
+    fn mark_chunk_changes(changes: &mut FlatSparseRoot3dChanges, key: IVec3) {
+        changes.mark_chunk(key);
+        for direction in GRID_3D_DIRECTIONS.iter() { changes.mark_chunk(key + *direction * CHUNK_3D_DIM as i32); }
+    }
+
+    fn save_chunk(&self, directory: &str, key: IVec3) -> SerialResult<()> { self.save_chunk_to_region(directory, key) }
+    fn load_chunk(&mut self, directory: &str, key: IVec3) -> SerialResult<bool> { self.load_chunk_from_region(directory, key) }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_directory(name: &str) -> String {
+        let directory = std::env::temp_dir().join(format!("tank_region_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&directory);
+        directory.to_str().unwrap().to_owned()
+    }
+
+    #[test]
+    fn save_load_round_trip() {
+        let directory = temp_directory("round_trip");
+        let mut root = FlatSparseRoot3d::<u16>::new(6, 0);
+        root.set_value_on(&IVec3::new(1, 2, 3), 5);
+        root.set_value_on(&IVec3::new(2, 2, 3), 6);
+        root.set_value_off(&IVec3::new(2, 2, 3));
+        // Another region, on the negative side
+        root.set_value_on(&IVec3::new(70, -3, 9), 7);
+        root.save_regions(&directory).unwrap();
+
+        let loaded = FlatSparseRoot3d::<u16>::load_regions(&directory, 6, 0).unwrap();
+        let _ = std::fs::remove_dir_all(&directory);
+
+        assert_eq!(loaded.chunks().len(), root.chunks().len());
+        for chunk_key in root.chunks().keys() {
+            for coord in FlatSparseRoot3dMath::box_coords(*chunk_key, *chunk_key + CHUNK_3D_DIM as i32 - 1) {
+                assert_eq!(loaded.get_value(&coord), root.get_value(&coord), "value at {coord}");
+                assert_eq!(loaded.is_value_on(&coord), root.is_value_on(&coord), "active at {coord}");
+            }
+        }
+    }
+
+    #[test]
+    fn truncated_region_is_corrupt() {
+        let directory = temp_directory("truncated");
+        let mut root = FlatSparseRoot3d::<u16>::new(6, 0);
+        root.set_value_on(&IVec3::new(1, 2, 3), 5);
+        root.save_regions(&directory).unwrap();
+
+        let path = VoxelRegionFile::path_3d(&directory, IVec3::ZERO);
+        let length = std::fs::metadata(&path).unwrap().len();
+        File::options().write(true).open(&path).unwrap().set_len(length - 1).unwrap();
+
+        let result = FlatSparseRoot3d::<u16>::load_regions(&directory, 6, 0);
+        let _ = std::fs::remove_dir_all(&directory);
+        assert!(matches!(result, Err(SerialError::Corrupt { .. })));
+    }
+
+    #[test]
+    fn save_load_2d_round_trip() {
+        let directory = temp_directory("round_trip_2d");
+        let mut root = FlatSparseRoot2d::<u16>::new(6, 0);
+        root.set_value_on(IVec2::new(1, 2), 5);
+        // Another region, on the negative side
+        root.set_value_on(IVec2::new(-300, 9), 7);
+        root.save_regions(&directory).unwrap();
+        assert!(std::path::Path::new(&VoxelRegionFile::path_2d(&directory, IVec2::new(-2, 0))).exists());
+
+        let loaded = FlatSparseRoot2d::<u16>::load_regions(&directory, 6, 0).unwrap();
+        let _ = std::fs::remove_dir_all(&directory);
+
+        assert_eq!(loaded.chunks().len(), 2);
+        for (chunk_key, chunk) in root.chunks().iter() {
+            let (chunk, loaded_chunk) = (chunk.read().unwrap(), loaded.chunk_from_coord(*chunk_key).unwrap().read().unwrap());
+            assert_eq!(loaded_chunk.data(), chunk.data(), "values of {chunk_key}");
+            assert_eq!(loaded_chunk.active_mask().words(), chunk.active_mask().words(), "active mask of {chunk_key}");
+        }
+    }
+
+    #[test]
+    fn other_files_in_directory_are_skipped() {
+        let directory = temp_directory("other_files");
+        let mut root = FlatSparseRoot3d::<u16>::new(6, 0);
+        root.set_value_on(&IVec3::new(1, 2, 3), 5);
+        root.save_regions(&directory).unwrap();
+        std::fs::write(format!("{directory}/r.1.2.3.bak"), b"backup").unwrap();
+        std::fs::write(format!("{directory}/r.4.5.6"), b"no extension").unwrap();
+
+        let loaded = FlatSparseRoot3d::<u16>::load_regions(&directory, 6, 0);
+        let _ = std::fs::remove_dir_all(&directory);
+        assert_eq!(loaded.unwrap().chunks().len(), 1);
+    }
+}
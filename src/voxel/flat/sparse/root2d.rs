@@ -1,4 +1,5 @@
 use super::*;
+use crate::voxel::{GRID_2D_DIAGONALS, GRID_2D_DIRECTIONS};
 
 use bevy::utils::HashMap;
 
@@ -21,6 +22,15 @@ impl<T: Default + Clone + Copy + Sync + Send + 'static> FlatSparseRoot2d<T> {
     #[inline] pub fn background(&self) -> T { self.background }
     #[inline] pub fn chunks(&self) -> &HashMap<IVec2, Arc<RwLock<Leaf2d<T>>>> { &self.chunks }
 
+    /// Replaces any chunk already at `chunk_key`.
+    pub fn insert_chunk(&mut self, chunk_key: IVec2, chunk: Leaf2d<T>) {
+        self.chunks.insert(chunk_key & !CHUNK_2D_MASK, Arc::new(RwLock::new(chunk)));
+    }
+
+    pub fn remove_chunk(&mut self, chunk_key: IVec2) -> Option<Arc<RwLock<Leaf2d<T>>>> {
+        self.chunks.remove(&(chunk_key & !CHUNK_2D_MASK))
+    }
+
     #[inline] pub fn total_dim(&self) -> i32 { (self.dim() * CHUNK_2D_DIM) as i32 }
     #[inline] pub fn half_total_dim(&self) -> i32 { self.total_dim() >> 1 }
 
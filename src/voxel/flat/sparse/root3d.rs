@@ -19,6 +19,17 @@ impl<T: Default + Clone + Copy + Sync + Send + 'static> FlatSparseRoot3d<T> {
         }
     }
 
+    /// A root sharing the chunk at `chunk_key` & all 26 of its neighbours with this one, so a single chunk can be meshed on another thread.
+    pub fn clone_neighborhood(&self, chunk_key: &IVec3) -> Self {
+        let mut chunks = HashMap::<IVec3, Arc<RwLock<Leaf3d<T>>>>::default();
+        for direction in FlatSparseRoot3dMath::box_coords(IVec3::NEG_ONE, IVec3::ONE) {
+            let key = *chunk_key + direction * CHUNK_3D_DIM as i32;
+            if let Some(chunk) = self.chunks.get(&key) { chunks.insert(key, chunk.clone()); }
+        }
+
+        Self { log2dim: self.log2dim, background: self.background, chunks }
+    }
+
     pub fn adjacent_value_from_index(&self, global_coord: &IVec3, face: usize) -> T {
         self.get_value(&(*global_coord + GRID_3D_DIRECTIONS[face]))
     }
@@ -43,17 +54,28 @@ impl<T: Default + Clone + Copy + Sync + Send + 'static> FlatSparseRoot3d<T> {
         }
     }
 
-    pub fn set_value_on(&mut self, coord: &IVec3, value: T) {
+    /// Like `get_value`, but values that are off are the background, so only voxels that are on count as solid for meshing & queries.
+    pub fn get_active_value(&self, coord: &IVec3) -> T {
         let key = *coord & !CHUNK_3D_MASK;
         if let Some(chunk) = self.chunks.get(&key) {
-            chunk.write().unwrap().set_value_on(self.value_index_from_coord(coord) as usize, value);
-        } else if !self.is_coord_out_of_bounds(coord) {
-            let mut new_chunk = Leaf3d::default();
-            new_chunk.set_value_on(self.value_index_from_coord(coord) as usize, value);
-            self.chunks.insert(key, Arc::new(RwLock::new(new_chunk)));
+            let chunk = chunk.read().unwrap();
+            let index = self.value_index_from_coord(coord) as usize;
+            if chunk.is_value_on(index) { chunk.get_value(index) } else { self.background }
+        } else {
+            self.background
         }
     }
 
+    /// Like `get_adjacent_values`, but using `get_active_value`.
+    pub fn get_adjacent_active_values(&self, global_coord: &IVec3) -> [T; 6] {
+        std::array::from_fn(|face| self.get_active_value(&(*global_coord + GRID_3D_DIRECTIONS[face])))
+    }
+
+    pub fn is_value_on(&self, coord: &IVec3) -> bool {
+        let key = *coord & !CHUNK_3D_MASK;
+        if let Some(chunk) = self.chunks.get(&key) { chunk.read().unwrap().is_value_on(self.value_index_from_coord(coord) as usize) } else { false }
+    }
+
     pub fn set_value_off(&mut self, coord: &IVec3) {
         let key = *coord & !CHUNK_3D_MASK;
         if let Some(chunk) = self.chunks.get(&key) {
@@ -70,17 +92,6 @@ impl<T: Default + Clone + Copy + Sync + Send + 'static> FlatSparseRoot3d<T> {
         }
     }
 
-    pub fn set_looping_value_on(&mut self, coord: &IVec3, value: T) {
-        let key = *coord & !CHUNK_3D_MASK;
-        if let Some(chunk) = self.chunks.get(&key) {
-            chunk.write().unwrap().set_value_on(self.value_index_from_coord(coord) as usize, value);
-        } else if !self.is_coord_out_of_bounds(coord) {
-            let mut new_chunk = Leaf3d::default();
-            new_chunk.set_value_on(self.value_index_from_coord(coord) as usize, value);
-            self.chunks.insert(key, Arc::new(RwLock::new(new_chunk)));
-        }
-    }
-
     pub fn set_looping_value_off(&mut self, coord: &IVec3) {
         let key = *coord & !CHUNK_3D_MASK;
         if let Some(chunk) = self.chunks.get(&key) {
@@ -94,6 +105,15 @@ impl<T: Default + Clone + Copy + Sync + Send + 'static> FlatSparseRoot3d<T> {
     #[inline] pub fn background(&self) -> T { self.background }
     #[inline] pub fn chunks(&self) -> &HashMap<IVec3, Arc<RwLock<Leaf3d<T>>>> { &self.chunks }
 
+    /// Replaces any chunk already at `chunk_key`.
+    pub fn insert_chunk(&mut self, chunk_key: IVec3, chunk: Leaf3d<T>) {
+        self.chunks.insert(chunk_key & !CHUNK_3D_MASK, Arc::new(RwLock::new(chunk)));
+    }
+
+    pub fn remove_chunk(&mut self, chunk_key: IVec3) -> Option<Arc<RwLock<Leaf3d<T>>>> {
+        self.chunks.remove(&(chunk_key & !CHUNK_3D_MASK))
+    }
+
     #[inline] pub fn is_coord_out_of_bounds(&self, coord: &IVec3) -> bool {
         let half_total_dim = ((self.dim() * CHUNK_3D_DIM) >> 1) as i32;
         let min = -half_total_dim;
@@ -117,6 +137,36 @@ impl<T: Default + Clone + Copy + Sync + Send + 'static> FlatSparseRoot3d<T> {
     #[inline] pub fn value_local_coord_from_index(&self, index: u32) -> IVec3 { FlatSparseRoot3dMath::local_coord_from_index(index, CHUNK_3D_LOG2DIM as u8) }
 }
 
+/// Setting values compares them to keep chunks compressed, see [Leaf3d].
+impl<T: PartialEq + Default + Clone + Copy + Sync + Send + 'static> FlatSparseRoot3d<T> {
+    pub fn set_value_on(&mut self, coord: &IVec3, value: T) {
+        let key = *coord & !CHUNK_3D_MASK;
+        if let Some(chunk) = self.chunks.get(&key) {
+            chunk.write().unwrap().set_value_on(self.value_index_from_coord(coord) as usize, value);
+        } else if !self.is_coord_out_of_bounds(coord) {
+            let mut new_chunk = Leaf3d::default();
+            new_chunk.set_value_on(self.value_index_from_coord(coord) as usize, value);
+            self.chunks.insert(key, Arc::new(RwLock::new(new_chunk)));
+        }
+    }
+
+    pub fn set_looping_value_on(&mut self, coord: &IVec3, value: T) {
+        let key = *coord & !CHUNK_3D_MASK;
+        if let Some(chunk) = self.chunks.get(&key) {
+            chunk.write().unwrap().set_value_on(self.value_index_from_coord(coord) as usize, value);
+        } else if !self.is_coord_out_of_bounds(coord) {
+            let mut new_chunk = Leaf3d::default();
+            new_chunk.set_value_on(self.value_index_from_coord(coord) as usize, value);
+            self.chunks.insert(key, Arc::new(RwLock::new(new_chunk)));
+        }
+    }
+
+    /// Switches every chunk to its smallest representation, see [Leaf3d::compact].
+    pub fn compact_chunks(&mut self) {
+        for chunk in self.chunks.values() { chunk.write().unwrap().compact(); }
+    }
+}
+
 pub struct FlatSparseRoot3dMath;
 impl FlatSparseRoot3dMath {
     #[inline] pub fn dim(log2dim: u8) -> u32 { 1 << log2dim }
@@ -1,9 +1,13 @@
 use crate::*;
 
+mod def;
+pub use def::*;
 mod flat;
 pub use flat::*;
 mod traits;
 pub use traits::*;
+mod tree;
+pub use tree::*;
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 pub const GRID_3D_DIRECTIONS: [IVec3; 6] = [
@@ -57,6 +61,9 @@ pub const GRID_2D_DIAGONALS: [IVec2; 4] = [
 pub struct TankVoxelPlugin;
 impl Plugin for TankVoxelPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(TankVoxelFlatPlugin);
+        app.add_plugins((
+                DataAssetPlugin::<VoxelDef>::new("voxels"),
+                TankVoxelFlatPlugin,
+            ));
     }
 }
\ No newline at end of file
@@ -2,4 +2,31 @@ use crate::*;
 
 ////////////////////////////////////////////////////////////////////////////////////////////////////
 pub trait HeightData { fn height(&self) -> u8; }
-pub trait ShapeData { fn shape(&self) -> u8; }
\ No newline at end of file
+pub trait ShapeData {
+    fn shape(&self) -> u8;
+    fn set_shape(&mut self, shape: u8);
+}
+/// Voxels whose look & behaviour are defined by a [VoxelDef] in `DataAssets<VoxelDef>`. Ids without a def use `VoxelDef::MISSING`.
+/// 
+/// `defs` is `DataAssets<VoxelDef>::data()`, or a copy of it when meshing off the main thread.
+pub trait VoxelData {
+    fn voxel_def_id(&self) -> u16;
+
+    fn voxel_def<'a>(&self, defs: &'a [VoxelDef]) -> &'a VoxelDef {
+        defs.get(self.voxel_def_id() as usize).unwrap_or(&VoxelDef::MISSING)
+    }
+
+    /// - `face` ordered like `GRID_3D_DIRECTIONS`
+    fn face_texture_id(&self, face: usize, defs: &[VoxelDef]) -> u16 { self.voxel_def(defs).face_texture_ids[face] }
+    fn is_opaque(&self, defs: &[VoxelDef]) -> bool { self.voxel_def(defs).opaque }
+    fn is_transparent(&self, defs: &[VoxelDef]) -> bool { self.voxel_def(defs).transparent }
+    fn is_collidable(&self, defs: &[VoxelDef]) -> bool { self.voxel_def(defs).collidable }
+}
+
+impl VoxelData for u8 { fn voxel_def_id(&self) -> u16 { *self as u16 } }
+impl VoxelData for u16 { fn voxel_def_id(&self) -> u16 { *self } }
+
+/// Scalar fields meshed as smooth surfaces. Densities above 0 are solid, and the surface is where the density crosses 0.
+pub trait DensityData { fn density(&self) -> f32; }
+
+impl DensityData for f32 { fn density(&self) -> f32 { *self } }
@@ -0,0 +1,25 @@
+use crate::*;
+
+mod node;
+pub use node::*;
+mod root;
+pub use root::*;
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// `32 x 32 x 32` lower nodes per upper node
+pub const TREE_UPPER_LOG2DIM: u32 = 5;
+pub const TREE_UPPER_WORD_NUM: usize = (1 << (TREE_UPPER_LOG2DIM * 3)) >> 6;
+/// `16 x 16 x 16` leaves per lower node
+pub const TREE_LOWER_LOG2DIM: u32 = 4;
+pub const TREE_LOWER_WORD_NUM: usize = (1 << (TREE_LOWER_LOG2DIM * 3)) >> 6;
+
+/// Voxels along each axis of a lower node: 128
+pub const TREE_LOWER_TOTAL_LOG2DIM: u32 = TREE_LOWER_LOG2DIM + CHUNK_3D_LOG2DIM;
+/// Voxels along each axis of an upper node, the nodes held by a [TreeRoot3d]: 4096
+pub const TREE_UPPER_TOTAL_LOG2DIM: u32 = TREE_UPPER_LOG2DIM + TREE_LOWER_TOTAL_LOG2DIM;
+pub const TREE_UPPER_TOTAL_MASK: i32 = (1 << TREE_UPPER_TOTAL_LOG2DIM) - 1;
+
+/// Holds `16 x 16 x 16` [Leaf3d]s or tiles
+pub type TreeLowerNode<T> = InternalNode<T, Leaf3d<T>, TREE_LOWER_LOG2DIM, TREE_LOWER_WORD_NUM>;
+/// Holds `32 x 32 x 32` [TreeLowerNode]s or tiles
+pub type TreeUpperNode<T> = InternalNode<T, TreeLowerNode<T>, TREE_UPPER_LOG2DIM, TREE_UPPER_WORD_NUM>;
@@ -0,0 +1,376 @@
+use super::*;
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// A node of a [TreeRoot3d]. Coords are global, each node only uses the bits below its own size.
+pub trait TreeNode<T: PartialEq + Default + Clone + Copy + Sync + Send + 'static>: Clone + Sync + Send + 'static {
+    /// Voxels along each axis, log2
+    const TOTAL_LOG2DIM: u32;
+
+    /// A node where every voxel is `value`.
+    fn new_tile(value: T, active: bool) -> Self;
+
+    /// Value & active state of the voxel at `coord`.
+    fn probe(&self, coord: &IVec3) -> (T, bool);
+    fn set_on(&mut self, coord: &IVec3, value: T);
+    fn set_off(&mut self, coord: &IVec3);
+    /// Sets every voxel in the box from `min` to `max` inclusive, where it overlaps the node at `origin`.
+    fn fill(&mut self, origin: IVec3, min: IVec3, max: IVec3, value: T, active: bool);
+
+    /// `(value, active)` if every voxel has the same active state, and the same value if they are active.
+    fn as_tile(&self) -> Option<(T, bool)>;
+    /// Replaces uniform children with tiles.
+    fn prune(&mut self);
+
+    fn leaf(&self, coord: &IVec3) -> Option<&Leaf3d<T>>;
+    fn active_count(&self) -> u64;
+    /// Min & max active coords, inclusive.
+    fn active_bounds(&self, origin: IVec3) -> Option<(IVec3, IVec3)>;
+    /// `(coord, value)` of every active voxel in the box from `min` to `max` inclusive. Children outside the box are skipped.
+    fn iter_active_in<'a>(&'a self, origin: IVec3, min: IVec3, max: IVec3) -> Box<dyn Iterator<Item = (IVec3, T)> + 'a>;
+}
+
+/// Whether the boxes from `min_0` to `max_0` & from `min_1` to `max_1`, all inclusive, overlap.
+#[inline]
+fn boxes_intersect(min_0: IVec3, max_0: IVec3, min_1: IVec3, max_1: IVec3) -> bool {
+    min_0.cmple(max_1).all() && max_0.cmpge(min_1).all()
+}
+
+fn union_bounds(bounds_0: Option<(IVec3, IVec3)>, bounds_1: Option<(IVec3, IVec3)>) -> Option<(IVec3, IVec3)> {
+    match (bounds_0, bounds_1) {
+        (Some((min_0, max_0)), Some((min_1, max_1))) => Some((min_0.min(min_1), max_0.max(max_1))),
+        (bounds, None) | (None, bounds) => bounds,
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+impl<T: PartialEq + Default + Clone + Copy + Sync + Send + 'static> TreeNode<T> for Leaf3d<T> {
+    const TOTAL_LOG2DIM: u32 = CHUNK_3D_LOG2DIM;
+
+    fn new_tile(value: T, active: bool) -> Self { Self::new([value; CHUNK_3D_SIZE], Bitmask::new(active)) }
+
+    #[inline]
+    fn probe(&self, coord: &IVec3) -> (T, bool) {
+        let index = FlatSparseRoot3dMath::value_index_from_coord(coord) as usize;
+        (self.get_value(index), self.is_value_on(index))
+    }
+
+    #[inline] fn set_on(&mut self, coord: &IVec3, value: T) { self.set_value_on(FlatSparseRoot3dMath::value_index_from_coord(coord) as usize, value); }
+    #[inline] fn set_off(&mut self, coord: &IVec3) { self.set_value_off(FlatSparseRoot3dMath::value_index_from_coord(coord) as usize); }
+
+    fn fill(&mut self, origin: IVec3, min: IVec3, max: IVec3, value: T, active: bool) {
+        for coord in FlatSparseRoot3dMath::box_coords(min.max(origin), max.min(origin + CHUNK_3D_MASK)) {
+            if active { self.set_on(&coord, value); } else { self.set_off(&coord); }
+        }
+    }
+
+    fn as_tile(&self) -> Option<(T, bool)> {
+        if self.active_mask().is_off() { return Some((self.get_value(0), false)); }
+        if self.active_mask().is_on() && self.storage() == Leaf3dStorage::Uniform { return Some((self.get_value(0), true)); }
+        None
+    }
+
+    fn prune(&mut self) { self.compact(); }
+
+    fn leaf(&self, _coord: &IVec3) -> Option<&Leaf3d<T>> { Some(self) }
+    fn active_count(&self) -> u64 { self.active_mask().count_on() as u64 }
+
+    fn active_bounds(&self, origin: IVec3) -> Option<(IVec3, IVec3)> {
+        OnMaskIter::new(0, self.active_mask())
+            .map(|index| FlatSparseRoot3dMath::local_coord_from_index(index, CHUNK_3D_LOG2DIM as u8) + origin)
+            .fold(None, |bounds, coord| union_bounds(bounds, Some((coord, coord))))
+    }
+
+    fn iter_active_in<'a>(&'a self, origin: IVec3, min: IVec3, max: IVec3) -> Box<dyn Iterator<Item = (IVec3, T)> + 'a> {
+        Box::new(OnMaskIter::new(0, self.active_mask())
+            .map(move |index| (FlatSparseRoot3dMath::local_coord_from_index(index, CHUNK_3D_LOG2DIM as u8) + origin, self.get_value(index as usize)))
+            .filter(move |(coord, _)| coord.cmpge(min).all() && coord.cmple(max).all()))
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+#[derive(Clone)]
+enum InternalSlot<T, C> {
+    /// Every voxel under the slot has this value, active if the slot's bit in `value_mask` is on
+    Tile(T),
+    Child(Box<C>),
+}
+
+/// `2^LOG2DIM` children or tiles along each axis, like the internal nodes of OpenVDB.
+///
+/// `child_mask` marks slots holding a child, and `value_mask` marks tiles that are active.
+#[derive(Clone)]
+pub struct InternalNode<T: PartialEq + Default + Clone + Copy + Sync + Send + 'static, C: TreeNode<T>, const LOG2DIM: u32, const WORD_NUM: usize> {
+    slots: Vec<InternalSlot<T, C>>,
+    child_mask: Bitmask<WORD_NUM>,
+    value_mask: Bitmask<WORD_NUM>,
+}
+
+impl<T: PartialEq + Default + Clone + Copy + Sync + Send + 'static, C: TreeNode<T>, const LOG2DIM: u32, const WORD_NUM: usize> InternalNode<T, C, LOG2DIM, WORD_NUM> {
+    const SIZE: usize = 1 << (LOG2DIM * 3);
+    const DIM_MASK: i32 = (1 << LOG2DIM) - 1;
+    /// From the min coord of a child to its max coord
+    const CHILD_EXTENT: i32 = (1 << C::TOTAL_LOG2DIM) - 1;
+
+    #[inline] pub fn child_mask(&self) -> &Bitmask<WORD_NUM> { &self.child_mask }
+    #[inline] pub fn value_mask(&self) -> &Bitmask<WORD_NUM> { &self.value_mask }
+
+    #[inline]
+    fn slot_index(coord: &IVec3) -> usize {
+        let local_coord = (*coord >> C::TOTAL_LOG2DIM as i32) & Self::DIM_MASK;
+        ((local_coord.z << (LOG2DIM * 2)) + (local_coord.y << LOG2DIM) + local_coord.x) as usize
+    }
+
+    #[inline]
+    fn child_origin(origin: IVec3, index: usize) -> IVec3 {
+        origin + (FlatSparseRoot3dMath::local_coord_from_index(index as u32, LOG2DIM as u8) << C::TOTAL_LOG2DIM as i32)
+    }
+
+    /// Replaces the tile at `index` with a child holding its value, if it is not a child already.
+    fn child_mut(&mut self, index: usize) -> &mut C {
+        if let InternalSlot::Tile(value) = self.slots[index] {
+            self.slots[index] = InternalSlot::Child(Box::new(C::new_tile(value, self.value_mask.is_bit_on(index))));
+            self.child_mask.set_bit_on(index);
+            self.value_mask.set_bit_off(index);
+        }
+
+        match &mut self.slots[index] {
+            InternalSlot::Child(child) => child,
+            InternalSlot::Tile(_) => unreachable!(),
+        }
+    }
+
+    fn set_tile(&mut self, index: usize, value: T, active: bool) {
+        self.slots[index] = InternalSlot::Tile(value);
+        self.child_mask.set_bit_off(index);
+        self.value_mask.set_bit(index, active);
+    }
+
+    fn children(&self, origin: IVec3) -> impl Iterator<Item = (&C, IVec3)> + '_ {
+        OnMaskIter::new(0, &self.child_mask).filter_map(move |index| match &self.slots[index as usize] {
+            InternalSlot::Child(child) => Some((child.as_ref(), Self::child_origin(origin, index as usize))),
+            InternalSlot::Tile(_) => None,
+        })
+    }
+
+    fn active_tiles(&self, origin: IVec3) -> impl Iterator<Item = (T, IVec3)> + '_ {
+        OnMaskIter::new(0, &self.value_mask).filter_map(move |index| match &self.slots[index as usize] {
+            InternalSlot::Tile(value) => Some((*value, Self::child_origin(origin, index as usize))),
+            InternalSlot::Child(_) => None,
+        })
+    }
+}
+
+impl<T: PartialEq + Default + Clone + Copy + Sync + Send + 'static, C: TreeNode<T>, const LOG2DIM: u32, const WORD_NUM: usize> Default for InternalNode<T, C, LOG2DIM, WORD_NUM> {
+    fn default() -> Self { Self::new_tile(T::default(), false) }
+}
+
+impl<T: PartialEq + Default + Clone + Copy + Sync + Send + 'static, C: TreeNode<T>, const LOG2DIM: u32, const WORD_NUM: usize> TreeNode<T> for InternalNode<T, C, LOG2DIM, WORD_NUM> {
+    const TOTAL_LOG2DIM: u32 = LOG2DIM + C::TOTAL_LOG2DIM;
+
+    fn new_tile(value: T, active: bool) -> Self {
+        let mut slots = Vec::with_capacity(Self::SIZE);
+        slots.resize_with(Self::SIZE, || InternalSlot::Tile(value));
+        Self { slots, child_mask: Bitmask::new(false), value_mask: Bitmask::new(active) }
+    }
+
+    #[inline]
+    fn probe(&self, coord: &IVec3) -> (T, bool) {
+        let index = Self::slot_index(coord);
+        match &self.slots[index] {
+            InternalSlot::Tile(value) => (*value, self.value_mask.is_bit_on(index)),
+            InternalSlot::Child(child) => child.probe(coord),
+        }
+    }
+
+    fn set_on(&mut self, coord: &IVec3, value: T) {
+        let index = Self::slot_index(coord);
+        if let InternalSlot::Tile(tile) = self.slots[index] {
+            if tile == value && self.value_mask.is_bit_on(index) { return; }
+        }
+
+        self.child_mut(index).set_on(coord, value);
+    }
+
+    fn set_off(&mut self, coord: &IVec3) {
+        let index = Self::slot_index(coord);
+        if let InternalSlot::Tile(_) = self.slots[index] {
+            if self.value_mask.is_bit_off(index) { return; }
+        }
+
+        self.child_mut(index).set_off(coord);
+    }
+
+    fn fill(&mut self, origin: IVec3, min: IVec3, max: IVec3, value: T, active: bool) {
+        let node_max = origin + ((1 << Self::TOTAL_LOG2DIM) - 1);
+        if !boxes_intersect(origin, node_max, min, max) { return; }
+
+        let local_min = (min.max(origin) - origin) >> C::TOTAL_LOG2DIM as i32;
+        let local_max = (max.min(node_max) - origin) >> C::TOTAL_LOG2DIM as i32;
+        for local_coord in FlatSparseRoot3dMath::box_coords(local_min, local_max) {
+            let index = ((local_coord.z << (LOG2DIM * 2)) + (local_coord.y << LOG2DIM) + local_coord.x) as usize;
+            let child_min = Self::child_origin(origin, index);
+
+            // Children covered by the box become tiles, only children on its edges are filled voxel by voxel
+            if min.cmple(child_min).all() && max.cmpge(child_min + Self::CHILD_EXTENT).all() {
+                self.set_tile(index, value, active);
+            } else {
+                self.child_mut(index).fill(child_min, min, max, value, active);
+            }
+        }
+    }
+
+    fn as_tile(&self) -> Option<(T, bool)> {
+        if !self.child_mask.is_off() { return None; }
+        let InternalSlot::Tile(first) = self.slots[0] else { return None };
+        if self.value_mask.is_off() { return Some((first, false)); }
+        if !self.value_mask.is_on() { return None; }
+
+        if self.slots.iter().all(|slot| matches!(slot, InternalSlot::Tile(value) if *value == first)) { Some((first, true)) } else { None }
+    }
+
+    fn prune(&mut self) {
+        let child_indices: Vec<u32> = OnMaskIter::new(0, &self.child_mask).collect();
+        for index in child_indices {
+            let index = index as usize;
+            let InternalSlot::Child(child) = &mut self.slots[index] else { continue };
+            child.prune();
+            if let Some((value, active)) = child.as_tile() { self.set_tile(index, value, active); }
+        }
+    }
+
+    fn leaf(&self, coord: &IVec3) -> Option<&Leaf3d<T>> {
+        match &self.slots[Self::slot_index(coord)] {
+            InternalSlot::Tile(_) => None,
+            InternalSlot::Child(child) => child.leaf(coord),
+        }
+    }
+
+    fn active_count(&self) -> u64 {
+        let tile_count = self.value_mask.count_on() as u64 * (1u64 << (C::TOTAL_LOG2DIM * 3));
+        self.children(IVec3::ZERO).map(|(child, _)| child.active_count()).sum::<u64>() + tile_count
+    }
+
+    fn active_bounds(&self, origin: IVec3) -> Option<(IVec3, IVec3)> {
+        let tile_bounds = self.active_tiles(origin)
+            .fold(None, |bounds, (_, child_min)| union_bounds(bounds, Some((child_min, child_min + Self::CHILD_EXTENT))));
+        self.children(origin).fold(tile_bounds, |bounds, (child, child_min)| union_bounds(bounds, child.active_bounds(child_min)))
+    }
+
+    fn iter_active_in<'a>(&'a self, origin: IVec3, min: IVec3, max: IVec3) -> Box<dyn Iterator<Item = (IVec3, T)> + 'a> {
+        let children = self.children(origin)
+            .filter(move |(_, child_min)| boxes_intersect(*child_min, *child_min + Self::CHILD_EXTENT, min, max))
+            .flat_map(move |(child, child_min)| child.iter_active_in(child_min, min, max));
+
+        let tiles = self.active_tiles(origin)
+            .filter(move |(_, child_min)| boxes_intersect(*child_min, *child_min + Self::CHILD_EXTENT, min, max))
+            .flat_map(move |(value, child_min)| {
+                FlatSparseRoot3dMath::box_coords(child_min.max(min), (child_min + Self::CHILD_EXTENT).min(max)).map(move |coord| (coord, value))
+            });
+
+        Box::new(children.chain(tiles))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fill_tiles_covered_children_and_fills_edge_leaves() {
+        // Leaves 1 & 2 along x are covered, leaves 0 & 3 only partly
+        let mut node = TreeLowerNode::<u16>::new_tile(0, false);
+        node.fill(IVec3::ZERO, IVec3::new(4, 0, 0), IVec3::new(27, 7, 7), 3, true);
+
+        assert!(node.child_mask().is_bit_on(0) && node.child_mask().is_bit_on(3));
+        assert!(node.child_mask().is_bit_off(1) && node.child_mask().is_bit_off(2));
+        assert!(node.value_mask().is_bit_on(1) && node.value_mask().is_bit_on(2));
+        assert!(node.leaf(&IVec3::new(4, 0, 0)).is_some());
+        assert!(node.leaf(&IVec3::new(12, 0, 0)).is_none());
+
+        assert_eq!(node.probe(&IVec3::new(3, 0, 0)), (0, false));
+        assert_eq!(node.probe(&IVec3::new(4, 7, 7)), (3, true));
+        assert_eq!(node.probe(&IVec3::new(12, 5, 5)), (3, true));
+        assert_eq!(node.probe(&IVec3::new(28, 0, 0)), (0, false));
+        assert_eq!(node.active_count(), 24 * 8 * 8);
+    }
+
+    #[test]
+    fn fill_tiles_covered_lower_nodes() {
+        let mut node = TreeUpperNode::<u16>::new_tile(0, false);
+        node.fill(IVec3::ZERO, IVec3::ZERO, IVec3::new(129, 127, 127), 2, true);
+
+        assert!(node.child_mask().is_bit_off(0) && node.value_mask().is_bit_on(0));
+        assert!(node.child_mask().is_bit_on(1));
+        assert!(node.leaf(&IVec3::ZERO).is_none());
+        assert!(node.leaf(&IVec3::new(128, 0, 0)).is_some());
+        assert!(node.leaf(&IVec3::new(136, 0, 0)).is_none());
+        assert_eq!(node.active_count(), 130 * 128 * 128);
+    }
+
+    #[test]
+    fn set_off_inside_active_tile_splits_it() {
+        let mut node = TreeLowerNode::<u16>::new_tile(7, true);
+        node.set_off(&IVec3::new(9, 1, 2));
+
+        assert!(node.child_mask().is_bit_on(1));
+        assert!(node.value_mask().is_bit_off(1));
+        assert_eq!(node.probe(&IVec3::new(9, 1, 2)), (7, false));
+        assert_eq!(node.probe(&IVec3::new(10, 1, 2)), (7, true));
+        assert_eq!(node.probe(&IVec3::new(20, 0, 0)), (7, true));
+        assert_eq!(node.active_count(), (1 << (TREE_LOWER_TOTAL_LOG2DIM * 3)) - 1);
+        assert_eq!(node.as_tile(), None);
+    }
+
+    #[test]
+    fn set_on_inside_matching_tile_keeps_it() {
+        let mut node = TreeLowerNode::<u16>::new_tile(7, true);
+        node.set_on(&IVec3::new(9, 1, 2), 7);
+        assert!(node.child_mask().is_off());
+
+        node.set_on(&IVec3::new(9, 1, 2), 8);
+        assert!(node.child_mask().is_bit_on(1));
+        assert_eq!(node.probe(&IVec3::new(9, 1, 2)), (8, true));
+    }
+
+    #[test]
+    fn prune_collapses_uniform_children_into_tiles() {
+        let mut node = TreeLowerNode::<u16>::new_tile(7, true);
+        node.set_off(&IVec3::new(9, 1, 2));
+        node.set_on(&IVec3::new(9, 1, 2), 7);
+        node.set_on(&IVec3::new(40, 0, 0), 1);
+        node.prune();
+
+        // The mended leaf is a tile again, the one with a different value stays a leaf
+        assert!(node.child_mask().is_bit_off(1) && node.value_mask().is_bit_on(1));
+        assert!(node.leaf(&IVec3::new(40, 0, 0)).is_some());
+        assert_eq!(node.as_tile(), None);
+
+        node.set_on(&IVec3::new(40, 0, 0), 7);
+        node.prune();
+        assert!(node.child_mask().is_off());
+        assert_eq!(node.as_tile(), Some((7, true)));
+    }
+
+    #[test]
+    fn prune_collapses_inactive_children() {
+        let mut node = TreeUpperNode::<u16>::new_tile(0, false);
+        node.set_on(&IVec3::new(200, 3, 3), 4);
+        node.set_off(&IVec3::new(200, 3, 3));
+        assert!(!node.child_mask().is_off());
+
+        node.prune();
+        assert!(node.child_mask().is_off());
+        assert_eq!(node.as_tile(), Some((0, false)));
+    }
+
+    #[test]
+    fn active_bounds_include_tiles_and_leaves() {
+        let mut node = TreeLowerNode::<u16>::new_tile(0, false);
+        node.fill(IVec3::ZERO, IVec3::new(8, 8, 8), IVec3::new(15, 15, 15), 1, true);
+        node.set_on(&IVec3::new(30, 2, 100), 1);
+
+        assert_eq!(node.active_bounds(IVec3::ZERO), Some((IVec3::new(8, 2, 8), IVec3::new(30, 15, 100))));
+        assert_eq!(node.active_count(), 8 * 8 * 8 + 1);
+        assert_eq!(TreeLowerNode::<u16>::new_tile(0, false).active_bounds(IVec3::ZERO), None);
+    }
+}
@@ -0,0 +1,213 @@
+use super::*;
+
+use bevy::utils::HashMap;
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+/// Sparse voxel tree with the same coordinate API as [FlatSparseRoot3d], but unbounded & hierarchical:
+/// upper nodes of `4096^3` voxels, lower nodes of `128^3`, then [Leaf3d]s of `8^3`.
+///
+/// Uniform regions are stored as a single tile value in the node above them, so large empty or filled areas cost almost nothing
+/// to store or iterate. Use `fill` to create them, and `prune` to collapse regions that became uniform through edits.
+#[derive(Component, Reflect)]
+pub struct TreeRoot3d<T: PartialEq + Default + Clone + Copy + Sync + Send + 'static> {
+    background: T,
+    #[reflect(ignore)] nodes: HashMap<IVec3, Box<TreeUpperNode<T>>>,
+}
+
+impl<T: PartialEq + Default + Clone + Copy + Sync + Send + 'static> TreeRoot3d<T> {
+    pub fn new(background: T) -> Self {
+        Self { background, nodes: HashMap::<IVec3, Box<TreeUpperNode<T>>>::default() }
+    }
+
+    #[inline] pub fn background(&self) -> T { self.background }
+    #[inline] pub fn nodes(&self) -> &HashMap<IVec3, Box<TreeUpperNode<T>>> { &self.nodes }
+    #[inline] pub fn node_key_from_coord(coord: &IVec3) -> IVec3 { *coord & !TREE_UPPER_TOTAL_MASK }
+
+    fn node_mut(&mut self, coord: &IVec3) -> &mut TreeUpperNode<T> {
+        let background = self.background;
+        self.nodes.entry(Self::node_key_from_coord(coord)).or_insert_with(|| Box::new(TreeUpperNode::new_tile(background, false)))
+    }
+
+    pub fn adjacent_value_from_index(&self, global_coord: &IVec3, face: usize) -> T {
+        self.get_value(&(*global_coord + GRID_3D_DIRECTIONS[face]))
+    }
+
+    pub fn get_adjacent_values(&self, global_coord: &IVec3) -> [T; 6] {
+        [
+            self.adjacent_value_from_index(global_coord, 0),
+            self.adjacent_value_from_index(global_coord, 1),
+            self.adjacent_value_from_index(global_coord, 2),
+            self.adjacent_value_from_index(global_coord, 3),
+            self.adjacent_value_from_index(global_coord, 4),
+            self.adjacent_value_from_index(global_coord, 5),
+        ]
+    }
+
+    /// Values that are off are the background.
+    pub fn get_value(&self, coord: &IVec3) -> T {
+        let Some(node) = self.nodes.get(&Self::node_key_from_coord(coord)) else { return self.background };
+        let (value, active) = node.probe(coord);
+        if active { value } else { self.background }
+    }
+
+    pub fn is_value_on(&self, coord: &IVec3) -> bool {
+        if let Some(node) = self.nodes.get(&Self::node_key_from_coord(coord)) { node.probe(coord).1 } else { false }
+    }
+
+    pub fn set_value_on(&mut self, coord: &IVec3, value: T) {
+        self.node_mut(coord).set_on(coord, value);
+    }
+
+    pub fn set_value_off(&mut self, coord: &IVec3) {
+        if let Some(node) = self.nodes.get_mut(&Self::node_key_from_coord(coord)) { node.set_off(coord); }
+    }
+
+    /// Sets every voxel in the box from `min` to `max` inclusive. Nodes & leaves covered by the box become single tiles.
+    pub fn fill(&mut self, min: IVec3, max: IVec3, value: T, active: bool) {
+        let (min, max) = (min.min(max), min.max(max));
+        let node_min = Self::node_key_from_coord(&min) >> TREE_UPPER_TOTAL_LOG2DIM as i32;
+        let node_max = Self::node_key_from_coord(&max) >> TREE_UPPER_TOTAL_LOG2DIM as i32;
+
+        for node_index in FlatSparseRoot3dMath::box_coords(node_min, node_max) {
+            let node_key = node_index << TREE_UPPER_TOTAL_LOG2DIM as i32;
+            if !active && !self.nodes.contains_key(&node_key) { continue; }
+            self.node_mut(&node_key).fill(node_key, min, max, value, active);
+        }
+    }
+
+    /// Collapses uniform leaves & nodes into tiles, and removes nodes with no active voxels.
+    pub fn prune(&mut self) {
+        for node in self.nodes.values_mut() { node.prune(); }
+        self.nodes.retain(|_, node| !matches!(node.as_tile(), Some((_, false))));
+    }
+
+    /// None if the voxel is in a tile rather than a leaf.
+    pub fn leaf_from_coord(&self, coord: &IVec3) -> Option<&Leaf3d<T>> {
+        self.nodes.get(&Self::node_key_from_coord(coord))?.leaf(coord)
+    }
+
+    /// Active voxels, counting every voxel of active tiles.
+    pub fn active_count(&self) -> u64 { self.nodes.values().map(|node| node.active_count()).sum() }
+
+    /// Min & max active coords, inclusive.
+    pub fn active_bounds(&self) -> Option<(IVec3, IVec3)> {
+        self.nodes.iter()
+            .filter_map(|(key, node)| node.active_bounds(*key))
+            .reduce(|(min_0, max_0), (min_1, max_1)| (min_0.min(min_1), max_0.max(max_1)))
+    }
+
+    /// `(coord, value)` of every voxel that is on, including every voxel of active tiles.
+    pub fn iter_active(&self) -> impl Iterator<Item = (IVec3, T)> + '_ {
+        self.overlap_box(IVec3::MIN, IVec3::MAX)
+    }
+
+    /// `(coord, value)` of every voxel that is on, inside the box from `min` to `max` inclusive. Nodes outside the box are skipped.
+    pub fn overlap_box(&self, min: IVec3, max: IVec3) -> impl Iterator<Item = (IVec3, T)> + '_ {
+        let (min, max) = (min.min(max), min.max(max));
+        self.nodes.iter()
+            .filter(move |(key, _)| key.cmple(max).all() && (**key + TREE_UPPER_TOTAL_MASK).cmpge(min).all())
+            .flat_map(move |(key, node)| node.iter_active_in(*key, min, max))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(coords: impl Iterator<Item = (IVec3, u16)>) -> Vec<(IVec3, u16)> {
+        let mut coords: Vec<(IVec3, u16)> = coords.collect();
+        coords.sort_by_key(|(coord, _)| (coord.z, coord.y, coord.x));
+        coords
+    }
+
+    #[test]
+    fn set_and_get_across_upper_nodes() {
+        let mut root = TreeRoot3d::<u16>::new(9);
+        let coords = [IVec3::new(-1, -1, -1), IVec3::ZERO, IVec3::new(-4096, 0, 4095), IVec3::new(4096, -4097, 0)];
+        for (index, coord) in coords.iter().enumerate() { root.set_value_on(coord, index as u16); }
+
+        assert_eq!(root.nodes().len(), 4);
+        for (index, coord) in coords.iter().enumerate() {
+            assert!(root.is_value_on(coord));
+            assert_eq!(root.get_value(coord), index as u16);
+        }
+        assert_eq!(root.get_value(&IVec3::new(1, 0, 0)), 9);
+        assert_eq!(root.active_count(), 4);
+    }
+
+    #[test]
+    fn fill_counts_and_bounds_tiles() {
+        // One lower node tile in each of the 8 upper nodes around the origin
+        let mut root = TreeRoot3d::<u16>::new(0);
+        root.fill(IVec3::splat(-128), IVec3::splat(127), 5, true);
+
+        assert_eq!(root.nodes().len(), 8);
+        assert!(root.leaf_from_coord(&IVec3::splat(-1)).is_none());
+        assert_eq!(root.active_count(), 256 * 256 * 256);
+        assert_eq!(root.active_bounds(), Some((IVec3::splat(-128), IVec3::splat(127))));
+        assert_eq!(root.get_value(&IVec3::new(-128, 127, 0)), 5);
+        assert!(!root.is_value_on(&IVec3::new(-129, 0, 0)));
+    }
+
+    #[test]
+    fn fill_off_ignores_missing_nodes() {
+        let mut root = TreeRoot3d::<u16>::new(0);
+        root.fill(IVec3::splat(-10), IVec3::splat(10), 0, false);
+        assert!(root.nodes().is_empty());
+        assert_eq!(root.active_bounds(), None);
+    }
+
+    #[test]
+    fn overlap_box_crosses_upper_node_boundary() {
+        let mut root = TreeRoot3d::<u16>::new(0);
+        root.fill(IVec3::splat(-128), IVec3::splat(127), 5, true);
+        root.set_value_on(&IVec3::new(-4097, 0, 0), 6);
+        root.set_value_on(&IVec3::new(4096, 0, 0), 7);
+
+        let expected: Vec<(IVec3, u16)> = sorted(FlatSparseRoot3dMath::box_coords(IVec3::new(-2, -1, -1), IVec3::new(1, 0, 0)).map(|coord| (coord, 5)));
+        assert_eq!(sorted(root.overlap_box(IVec3::new(-2, -1, -1), IVec3::new(1, 0, 0))), expected);
+
+        let far = sorted(root.overlap_box(IVec3::new(-5000, 0, 0), IVec3::new(-4000, 0, 0)));
+        assert_eq!(far, vec![(IVec3::new(-4097, 0, 0), 6)]);
+        assert_eq!(sorted(root.overlap_box(IVec3::new(4096, 0, 0), IVec3::new(128, 0, 0))), vec![(IVec3::new(4096, 0, 0), 7)]);
+    }
+
+    #[test]
+    fn iter_active_visits_leaves_and_tiles() {
+        let mut root = TreeRoot3d::<u16>::new(0);
+        root.fill(IVec3::new(-8, 0, 0), IVec3::new(-1, 7, 7), 1, true);
+        root.set_value_on(&IVec3::new(3, 2, 1), 2);
+
+        let active = sorted(root.iter_active());
+        assert_eq!(active.len(), 8 * 8 * 8 + 1);
+        assert!(active.contains(&(IVec3::new(3, 2, 1), 2)));
+        assert!(active.iter().filter(|(_, value)| *value == 1).all(|(coord, _)| coord.x < 0));
+    }
+
+    #[test]
+    fn prune_drops_empty_nodes() {
+        let mut root = TreeRoot3d::<u16>::new(0);
+        root.set_value_on(&IVec3::new(-5000, 0, 0), 1);
+        root.set_value_on(&IVec3::new(10, 0, 0), 1);
+        root.set_value_off(&IVec3::new(-5000, 0, 0));
+        assert_eq!(root.nodes().len(), 2);
+
+        root.prune();
+        assert_eq!(root.nodes().len(), 1);
+        assert!(root.is_value_on(&IVec3::new(10, 0, 0)));
+        assert!(root.leaf_from_coord(&IVec3::new(10, 0, 0)).is_some());
+    }
+
+    #[test]
+    fn prune_collapses_edits_back_into_tiles() {
+        let mut root = TreeRoot3d::<u16>::new(0);
+        root.fill(IVec3::new(-128, 0, 0), IVec3::new(-1, 127, 127), 3, true);
+        root.set_value_off(&IVec3::new(-50, 60, 70));
+        assert!(root.leaf_from_coord(&IVec3::new(-50, 60, 70)).is_some());
+
+        root.set_value_on(&IVec3::new(-50, 60, 70), 3);
+        root.prune();
+        assert!(root.leaf_from_coord(&IVec3::new(-50, 60, 70)).is_none());
+        assert_eq!(root.active_count(), 128 * 128 * 128);
+    }
+}